@@ -0,0 +1,202 @@
+//! Compares decoding chat and plugin message packets straight out of their frame against copying every string
+//! and byte array out of it, which is what reading them did before `Readable` could borrow.
+//!
+//! Run with `cargo bench --bench decode`. Allocations are counted by a wrapper around the system allocator, so
+//! they don't depend on how noisy the machine is, unlike the times printed next to them.
+
+use ferrous::protocol::{
+    client::{ChatMessage, ClientPlayPacket, PluginMessage},
+    error::ProtocolError,
+    packet_handler::{Frame, PacketHandler},
+    structs::{GreedyVecU8, ProtocolVersion, Readable, VarInt, Writeable},
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    io::Cursor,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const VERSION: ProtocolVersion = ProtocolVersion::LATEST;
+const ITERATIONS: usize = 100_000;
+
+/// A chat message read the way it was before, with the text and signature copied out of the frame
+#[allow(dead_code)] // only read to be thrown away
+struct OwnedChatMessage {
+    message: String,
+    timestamp: i64,
+    salt: i64,
+    message_signature: Option<Box<[u8; 256]>>,
+    message_count: VarInt,
+    acknowledged: [u8; 3],
+}
+
+impl<'de> Readable<'de> for OwnedChatMessage {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        Ok(OwnedChatMessage {
+            message: String::read(buffer, version)?,
+            timestamp: i64::read(buffer, version)?,
+            salt: i64::read(buffer, version)?,
+            message_signature: Option::<[u8; 256]>::read(buffer, version)?.map(Box::new),
+            message_count: VarInt::read(buffer, version)?,
+            acknowledged: <[u8; 3]>::read(buffer, version)?,
+        })
+    }
+}
+
+/// A plugin message read the way it was before, with the channel and data copied out of the frame
+#[allow(dead_code)]
+struct OwnedPluginMessage {
+    channel: String,
+    data: Vec<u8>,
+}
+
+impl<'de> Readable<'de> for OwnedPluginMessage {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        Ok(OwnedPluginMessage {
+            channel: String::read(buffer, version)?,
+            data: GreedyVecU8::read(buffer, version)?.0.into_owned(),
+        })
+    }
+}
+
+fn chat_message() -> ChatMessage<'static> {
+    ChatMessage {
+        message: "Has anyone seen where the nether portal went? It was right next to spawn a minute ago",
+        timestamp: 1_690_000_000_000,
+        salt: 0x1234_5678,
+        signature: Default::default(),
+        signed_preview: false,
+        last_seen: Default::default(),
+        last_received: None,
+        message_signature: Some(&[0x5A; 256]),
+        message_count: VarInt(3),
+        acknowledged: [0b111, 0, 0],
+    }
+}
+
+fn plugin_message() -> PluginMessage<'static> {
+    PluginMessage {
+        channel: "minecraft:register",
+        data: b"fabric:registry/sync\0fabric-screen-handler-api-v1:open_screen\0worldedit:cui"[..].into(),
+    }
+}
+
+/// The body of a frame holding `packet`, without its ID
+fn body<T: Writeable>(packet: &T) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    packet.write(&mut buffer, VERSION).unwrap();
+    buffer
+}
+
+/// A stream of `ITERATIONS` frames holding `packet`, as it would arrive from the client
+fn stream<T: Writeable>(packet: &T) -> Vec<u8> {
+    let mut handler = PacketHandler::new();
+    handler.set_version(VERSION);
+    let mut stream = Vec::new();
+    for _ in 0..ITERATIONS {
+        handler.write_packet(packet, &mut stream).unwrap();
+    }
+    stream
+}
+
+/// Runs `f` and reports how long it took and how many allocations it made, per packet
+fn measure(name: &str, f: impl FnOnce()) -> usize {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    let per_packet = elapsed.as_nanos() as f64 / ITERATIONS as f64;
+    println!(
+        "{:<36} {:>8.1} ns/packet {:>8.2} allocations/packet ({:?} total)",
+        name,
+        per_packet,
+        allocations as f64 / ITERATIONS as f64,
+        elapsed,
+    );
+    allocations
+}
+
+/// Decodes the same body over and over
+fn decode<'a, T: Readable<'a>>(body: &'a [u8]) {
+    for _ in 0..ITERATIONS {
+        black_box(T::read(&mut Cursor::new(black_box(body)), VERSION).unwrap());
+    }
+}
+
+/// Feeds the whole stream to a handler and decodes every frame that comes out of it as a play packet
+fn receive_borrowed(stream: &[u8]) {
+    receive(stream, |frame| {
+        black_box(frame.read::<ClientPlayPacket>().unwrap());
+    });
+}
+
+/// Like `receive_borrowed`, but skipping the packet ID by hand to read the owned copy of the packet
+fn receive_owned<T: for<'a> Readable<'a>>(stream: &[u8]) {
+    receive(stream, |frame| {
+        let mut cursor = Cursor::new(frame.body());
+        VarInt::read(&mut cursor, VERSION).unwrap();
+        black_box(T::read(&mut cursor, VERSION).unwrap());
+    });
+}
+
+fn receive(stream: &[u8], mut decode: impl FnMut(&Frame)) {
+    let mut handler = PacketHandler::new();
+    handler.set_version(VERSION);
+    for read in stream.chunks(4096) {
+        handler.feed(read);
+        while let Some(frame) = handler.next_frame().unwrap() {
+            decode(&frame);
+        }
+    }
+}
+
+fn main() {
+    let chat_body = body(&chat_message());
+    let plugin_body = body(&plugin_message());
+
+    println!("decoding a body that's already been framed");
+    let borrowed = measure("chat message, borrowed", || decode::<ChatMessage>(&chat_body));
+    let owned = measure("chat message, owned", || decode::<OwnedChatMessage>(&chat_body));
+    assert!(borrowed < owned);
+    let borrowed = measure("plugin message, borrowed", || decode::<PluginMessage>(&plugin_body));
+    let owned = measure("plugin message, owned", || decode::<OwnedPluginMessage>(&plugin_body));
+    assert!(borrowed < owned);
+
+    let chat_stream = stream(&ClientPlayPacket::from(chat_message()));
+    let plugin_stream = stream(&ClientPlayPacket::from(plugin_message()));
+
+    println!("\nfeeding a stream of frames through PacketHandler::next_frame");
+    let borrowed = measure("chat message, borrowed", || receive_borrowed(&chat_stream));
+    let owned = measure("chat message, owned", || receive_owned::<OwnedChatMessage>(&chat_stream));
+    assert!(borrowed < owned);
+    let borrowed = measure("plugin message, borrowed", || receive_borrowed(&plugin_stream));
+    let owned = measure("plugin message, owned", || receive_owned::<OwnedPluginMessage>(&plugin_stream));
+    assert!(borrowed < owned);
+}
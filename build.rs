@@ -0,0 +1,357 @@
+//! Generates the block and biome registries from the vanilla data in `data/<version>/`, along with what their IDs
+//! are on each newer version:
+//! - `blocks.json` is a block report, in the format the vanilla server writes with `--reports`.
+//! - `codec.json` is the registry codec vanilla sends in Join Game, as typed NBT in JSON, which is where clients get
+//!   their biome IDs from.
+//!
+//! The server itself numbers everything the way 1.18.2 does.
+
+use serde_json::Value;
+use std::{collections::HashMap, env, fmt::Write, fs, path::Path};
+
+/// The version the server's own IDs come from
+const BASE_VERSION: &str = "1.18.2";
+
+/// Where each `ProtocolVersion` variant's block report and codec are. 1.19.1 and 1.19.2 didn't add any blocks, so
+/// they share 1.19's report
+const VERSIONS: &[(&str, &str, &str)] = &[
+    ("V1_18_2", "1.18.2", "1.18.2"),
+    ("V1_19", "1.19", "1.19"),
+    ("V1_19_2", "1.19", "1.19.2"),
+];
+
+struct Block {
+    name: String,
+    properties: Vec<(String, Vec<String>)>,
+    first_state: u16,
+    state_count: u16,
+    default_state: u16,
+}
+
+fn main() {
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).to_owned();
+
+    let blocks = read_blocks(BASE_VERSION);
+    let state_count = blocks.iter().map(|block| block.state_count).sum();
+    let mut code = generate(&blocks, state_count);
+    code.push_str(&generate_block_ids(&blocks));
+    fs::write(out.join("blocks.rs"), code).expect("unable to write generated blocks");
+
+    fs::write(out.join("biomes.rs"), generate_biomes()).expect("unable to write generated biomes");
+    fs::write(out.join("codec.rs"), generate_codecs()).expect("unable to write generated codecs");
+}
+
+/// Reads the block report for `version`, in state ID order
+fn read_blocks(version: &str) -> Vec<Block> {
+    let path = format!("data/{}/blocks.json", version);
+    println!("cargo:rerun-if-changed={}", path);
+
+    let report = fs::read_to_string(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path, e));
+    let report: Value = serde_json::from_str(&report).unwrap_or_else(|e| panic!("{} is invalid JSON: {}", path, e));
+
+    let mut blocks: Vec<Block> = report
+        .as_object()
+        .expect("block report should be an object")
+        .iter()
+        .map(|(name, block)| parse_block(name, block))
+        .collect();
+    blocks.sort_by_key(|block| block.first_state);
+
+    // state IDs have to be contiguous for them to be worked out from a block's first state
+    let mut next_state = 0;
+    for block in &blocks {
+        assert_eq!(block.first_state, next_state, "{} doesn't follow on from the previous block", block.name);
+        next_state += block.state_count;
+    }
+
+    blocks
+}
+
+/// Reads the biome names in the codec at `path`, in ID order
+fn read_biomes(version: &str) -> Vec<String> {
+    let path = format!("data/{}/codec.json", version);
+    println!("cargo:rerun-if-changed={}", path);
+
+    let codec = fs::read_to_string(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path, e));
+    let codec: Value = serde_json::from_str(&codec).unwrap_or_else(|e| panic!("{} is invalid JSON: {}", path, e));
+
+    let entries = codec["value"]["minecraft:worldgen/biome"]["value"]["value"]["value"]["value"]
+        .as_array()
+        .unwrap_or_else(|| panic!("{} has no biome registry", path));
+    let mut biomes: Vec<(u64, String)> = entries
+        .iter()
+        .map(|entry| {
+            let id = entry["id"]["value"].as_u64().expect("biome IDs should be ints");
+            (id, entry["name"]["value"].as_str().expect("biome names should be strings").to_owned())
+        })
+        .collect();
+    biomes.sort();
+    for (index, (id, name)) in biomes.iter().enumerate() {
+        assert_eq!(*id as usize, index, "{} has gaps in its biome IDs at {}", path, name);
+    }
+
+    biomes.into_iter().map(|(_, name)| name).collect()
+}
+
+/// How many bits it takes to tell `count` IDs apart
+fn bits_for(count: usize) -> u32 {
+    usize::BITS - (count - 1).leading_zeros()
+}
+
+fn parse_block(name: &str, block: &Value) -> Block {
+    let strings = |values: &Value| -> Vec<String> {
+        values
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    // vanilla sorts properties by name, and so does serde_json's map, so the order matches the state order
+    let properties: Vec<(String, Vec<String>)> = match block.get("properties") {
+        Some(properties) => properties
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(name, values)| (name.clone(), strings(values)))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let states = block["states"].as_array().unwrap();
+    let id = |state: &Value| state["id"].as_u64().unwrap() as u16;
+    let first_state = id(&states[0]);
+    let default_state = id(states
+        .iter()
+        .find(|state| state.get("default").and_then(Value::as_bool) == Some(true))
+        .unwrap_or_else(|| panic!("{} has no default state", name)));
+
+    // check every state is where the property math in `BlockState` expects it to be
+    let mut stride = states.len();
+    let strides: Vec<usize> = properties
+        .iter()
+        .map(|(_, values)| {
+            stride /= values.len();
+            stride
+        })
+        .collect();
+    for (index, state) in states.iter().enumerate() {
+        assert_eq!(id(state) as usize, first_state as usize + index, "{} has gaps in its states", name);
+        for ((property, values), stride) in properties.iter().zip(&strides) {
+            let expected = &values[index / stride % values.len()];
+            assert_eq!(state["properties"][property].as_str(), Some(&expected[..]), "{} state {}", name, index);
+        }
+    }
+
+    Block {
+        name: name.to_owned(),
+        properties,
+        first_state,
+        state_count: states.len() as u16,
+        default_state,
+    }
+}
+
+fn generate(blocks: &[Block], state_count: u16) -> String {
+    let mut out = String::new();
+    let bits = 16 - (state_count - 1).leading_zeros();
+
+    writeln!(out, "/// How many block states there are").unwrap();
+    writeln!(out, "pub const BLOCK_STATE_COUNT: u16 = {};", state_count).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// The bits a global block state ID takes up, which is how wide entries are once a section stops using a palette").unwrap();
+    writeln!(out, "pub const BLOCK_STATE_BITS: u8 = {};", bits).unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "/// Every block, in state ID order").unwrap();
+    writeln!(out, "static BLOCKS: [BlockInfo; {}] = [", blocks.len()).unwrap();
+    for block in blocks {
+        let properties: Vec<String> = block
+            .properties
+            .iter()
+            .map(|(name, values)| format!("Property {{ name: {:?}, values: &{:?} }}", name, values))
+            .collect();
+        writeln!(
+            out,
+            "    BlockInfo {{ name: {:?}, properties: &[{}], first_state: {}, default_state: {} }},",
+            block.name,
+            properties.join(", "),
+            block.first_state,
+            block.default_state
+        )
+        .unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    let mut by_name: Vec<(&str, usize)> = blocks.iter().enumerate().map(|(i, b)| (&b.name[..], i)).collect();
+    by_name.sort();
+    writeln!(out, "/// Indexes into `BLOCKS`, sorted by name for binary searching").unwrap();
+    writeln!(out, "static BLOCKS_BY_NAME: [(&str, u16); {}] = [", blocks.len()).unwrap();
+    for (name, index) in by_name {
+        writeln!(out, "    ({:?}, {}),", name, index).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "impl BlockState {{").unwrap();
+    for block in blocks {
+        let constant = block.name.trim_start_matches("minecraft:").to_uppercase();
+        writeln!(out, "    pub const {}: BlockState = BlockState({});", constant, block.default_state).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+/// The indexes of the property values state `offset` of `block` has
+fn property_values(block: &Block, offset: u16) -> Vec<usize> {
+    let mut stride = block.state_count as usize;
+    block
+        .properties
+        .iter()
+        .map(|(_, values)| {
+            stride /= values.len();
+            offset as usize / stride % values.len()
+        })
+        .collect()
+}
+
+/// What each of our states is called in `target`, going by block name and then property values. Properties
+/// `target` added are left at their default, and ones it dropped are ignored
+fn remap_states(blocks: &[Block], target: &[Block]) -> Vec<u16> {
+    let target: HashMap<&str, &Block> = target.iter().map(|block| (&block.name[..], block)).collect();
+
+    let mut ids = Vec::new();
+    for block in blocks {
+        let to = target
+            .get(&block.name[..])
+            .unwrap_or_else(|| panic!("{} isn't in every block report", block.name));
+        let defaults = property_values(to, to.default_state - to.first_state);
+
+        for state in 0..block.state_count {
+            let values = property_values(block, state);
+            let mut offset = 0;
+            let mut stride = to.state_count as usize;
+            for ((property, options), default) in to.properties.iter().zip(&defaults) {
+                stride /= options.len();
+                let value = block
+                    .properties
+                    .iter()
+                    .zip(&values)
+                    .find(|((name, _), _)| name == property)
+                    .and_then(|((_, ours), &index)| options.iter().position(|option| *option == ours[index]))
+                    .unwrap_or(*default);
+                offset += value * stride;
+            }
+            ids.push(to.first_state + offset as u16);
+        }
+    }
+    ids
+}
+
+/// Writes a table per distinct mapping and a `network_ids` function picking between them by version. `mappings`
+/// has `None` for versions that number things the way we do
+fn generate_network_ids(out: &mut String, kind: &str, prefix: &str, id_type: &str, mappings: &[(&str, Option<Vec<u16>>, u32)]) {
+    let mut tables: Vec<(String, &Vec<u16>)> = Vec::new();
+    let mut arms = Vec::new();
+    for (variant, ids, bits) in mappings {
+        let table = match ids {
+            None => "None".to_owned(),
+            Some(ids) => {
+                if !tables.iter().any(|(_, table)| *table == ids) {
+                    tables.push((format!("{}_{}", prefix, &variant[1..]), ids));
+                }
+                let (name, _) = tables.iter().find(|(_, table)| *table == ids).unwrap();
+                format!("Some(&{})", name)
+            }
+        };
+        arms.push(format!(
+            "        ProtocolVersion::{} => Some(NetworkIds {{ ids: {}, bits: {} }}),",
+            variant, table, bits
+        ));
+    }
+
+    for (name, ids) in &tables {
+        writeln!(out, "/// Our {} IDs as clients on {} number them", kind, name[prefix.len() + 1..].replace('_', ".")).unwrap();
+        writeln!(out, "static {}: [{}; {}] = {:?};", name, id_type, ids.len(), ids).unwrap();
+        writeln!(out).unwrap();
+    }
+
+    writeln!(out, "/// How clients on `version` number {}s, if we have the data for it", kind).unwrap();
+    writeln!(out, "fn network_ids(version: ProtocolVersion) -> Option<NetworkIds> {{").unwrap();
+    writeln!(out, "    #[allow(unreachable_patterns)]").unwrap();
+    writeln!(out, "    match version {{").unwrap();
+    for arm in arms {
+        writeln!(out, "{}", arm).unwrap();
+    }
+    writeln!(out, "        _ => None,").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+fn generate_block_ids(blocks: &[Block]) -> String {
+    let mut reports = HashMap::new();
+    let mappings: Vec<_> = VERSIONS
+        .iter()
+        .map(|&(variant, report, _)| {
+            let target = reports.entry(report).or_insert_with(|| read_blocks(report));
+            let state_count = target.iter().map(|block| block.state_count as usize).sum();
+            let ids = (report != BASE_VERSION).then(|| remap_states(blocks, target));
+            (variant, ids, bits_for(state_count))
+        })
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out).unwrap();
+    generate_network_ids(&mut out, "block state", "STATE_IDS", "u16", &mappings);
+    out
+}
+
+fn generate_biomes() -> String {
+    let biomes = read_biomes(BASE_VERSION);
+    let mappings: Vec<_> = VERSIONS
+        .iter()
+        .map(|&(variant, _, codec)| {
+            let target = read_biomes(codec);
+            let ids = (target != biomes).then(|| {
+                biomes
+                    .iter()
+                    .map(|biome| {
+                        let id = target.iter().position(|name| name == biome);
+                        id.unwrap_or_else(|| panic!("{} isn't in the {} codec", biome, codec)) as u16
+                    })
+                    .collect()
+            });
+            (variant, ids, bits_for(target.len()))
+        })
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "/// Every biome, in the order {} numbers them, which is what biome IDs in a `ChunkSection` refer to", BASE_VERSION).unwrap();
+    writeln!(out, "pub static BIOMES: [&str; {}] = {:?};", biomes.len(), biomes).unwrap();
+    writeln!(out).unwrap();
+    generate_network_ids(&mut out, "biome", "BIOME_IDS", "u8", &mappings);
+    out
+}
+
+fn generate_codecs() -> String {
+    let mut out = String::new();
+    writeln!(out, "/// The registry codec clients on `version` are sent, as typed NBT in JSON, if we have one for it").unwrap();
+    writeln!(out, "fn codec_json(version: ProtocolVersion) -> Option<&'static str> {{").unwrap();
+    writeln!(out, "    #[allow(unreachable_patterns)]").unwrap();
+    writeln!(out, "    match version {{").unwrap();
+    for (variant, _, codec) in VERSIONS {
+        writeln!(
+            out,
+            "        ProtocolVersion::{} => Some(include_str!(concat!(env!(\"CARGO_MANIFEST_DIR\"), \"/data/{}/codec.json\"))),",
+            variant, codec
+        )
+        .unwrap();
+    }
+    writeln!(out, "        _ => None,").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
@@ -0,0 +1,65 @@
+//! Feeds arbitrary byte streams through `PacketHandler::next_frame`, decoding each frame as the packets a client can
+//! send in one connection state, on one protocol version.
+//!
+//! Run with `cargo fuzz run next_packet -- -malloc_limit_mb=64` so a crafted frame that makes the
+//! handler allocate far more than it was sent counts as a crash, along with any panic.
+
+#![no_main]
+
+use ferrous::protocol::{
+    client::{ClientHandshakePacket, ClientLoginPacket, ClientPlayPacket, ClientStatusPacket},
+    error::ProtocolError,
+    packet_handler::{Frame, PacketHandler},
+    structs::ProtocolVersion,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte picks the handler's settings, the connection state and the version, and the second how the
+    // rest is split into reads
+    let (settings, read_size, stream) = match data {
+        [settings, read_size, stream @ ..] => (*settings, *read_size as usize + 1, stream),
+        _ => return,
+    };
+
+    let mut handler = PacketHandler::new();
+    if settings & 0b01 != 0 {
+        handler.enable_compression(256);
+    }
+    if settings & 0b10 != 0 {
+        handler.enable_encryption([settings; 16]);
+    }
+    let state = (settings >> 2) & 0b11;
+    let versions = ProtocolVersion::ALL;
+    handler.set_version(versions[(settings >> 4) as usize % versions.len()]);
+
+    for read in stream.chunks(read_size) {
+        handler.feed(read);
+        loop {
+            match handler.next_frame() {
+                Ok(Some(frame)) => {
+                    if !decode(&frame, state) {
+                        return;
+                    }
+                }
+                // a connection is dropped on its first bad frame, so there's nothing more to read
+                Err(_) => return,
+                Ok(None) => break,
+            }
+        }
+    }
+});
+
+/// Reads a frame as the packets of the given state, returning whether a connection would keep reading after it
+fn decode(frame: &Frame, state: u8) -> bool {
+    match state {
+        0 => frame.read::<ClientHandshakePacket>().is_ok(),
+        1 => frame.read::<ClientStatusPacket>().is_ok(),
+        2 => frame.read::<ClientLoginPacket>().is_ok(),
+        // play packets that aren't decoded yet are skipped rather than dropping the client
+        _ => matches!(
+            frame.read::<ClientPlayPacket>(),
+            Ok(_) | Err(ProtocolError::UnknownPacketId { .. })
+        ),
+    }
+}
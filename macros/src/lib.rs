@@ -0,0 +1,247 @@
+//! Derive macros for Ferrous' protocol types.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, Fields, Ident};
+
+/// Derives `Readable` and `Writeable` for a struct by reading and writing each field in declaration order.
+///
+/// Struct attributes:
+/// - `#[packet(id = 0x00)]` also implements `Packet`, so the struct can be used in a `packet_enum!`.
+/// - `#[packet(id = 0x22, since(V1_19 = 0x1F, V1_19_4 = 0x24))]` changes the ID from those versions on, for packets
+///   that moved between versions. The versions have to be listed oldest first, which is checked at compile time.
+///
+/// Field attributes, for fields that only exist on some protocol versions:
+/// - `#[packet(since = V1_19)]` only reads and writes the field on that version and newer.
+/// - `#[packet(before = V1_19)]` only reads and writes the field on versions older than that.
+///
+/// A gated field must implement `Default`, which is what it's read as on versions that don't send it.
+///
+/// A struct with a lifetime parameter is read with it as the lifetime of the buffer, so its fields can borrow from
+/// the packet's bytes.
+#[proc_macro_derive(Packet, attributes(packet))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// A field of the struct being derived, with its version gates
+struct PacketField {
+    /// How the field is named in a struct expression or pattern, `name` or `0`
+    member: syn::Member,
+    /// The local variable the field is read into or written from
+    binding: Ident,
+    ty: syn::Type,
+    since: Option<Ident>,
+    before: Option<Ident>,
+}
+
+impl PacketField {
+    /// The condition under which this field is on the wire, if it's gated at all
+    fn condition(&self) -> Option<TokenStream2> {
+        let since = self.since.as_ref().map(|v| quote!(version >= crate::protocol::structs::ProtocolVersion::#v));
+        let before = self.before.as_ref().map(|v| quote!(version < crate::protocol::structs::ProtocolVersion::#v));
+
+        match (since, before) {
+            (Some(since), Some(before)) => Some(quote!(#since && #before)),
+            (since, before) => since.or(before),
+        }
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let data = match &input.data {
+        Data::Struct(data) => data,
+        _ => return Err(syn::Error::new(input.span(), "`Packet` can only be derived for structs")),
+    };
+
+    let id = packet_id(&input.attrs)?;
+    let fields = packet_fields(&data.fields)?;
+
+    // the struct's own lifetime if it borrows, or a fresh one for the buffer otherwise
+    let mut read_generics = input.generics.clone();
+    let de = match input.generics.lifetimes().next() {
+        Some(param) => param.lifetime.clone(),
+        None => {
+            let de = syn::Lifetime::new("'de", proc_macro2::Span::call_site());
+            read_generics.params.insert(0, syn::GenericParam::Lifetime(syn::LifetimeParam::new(de.clone())));
+            de
+        }
+    };
+    let (read_impl_generics, _, _) = read_generics.split_for_impl();
+
+    let reads = fields.iter().map(|field| {
+        let binding = &field.binding;
+        let ty = &field.ty;
+        let read = quote!(<#ty as crate::protocol::structs::Readable<#de>>::read(buffer, version)?);
+
+        match field.condition() {
+            Some(condition) => quote! {
+                let #binding: #ty = if #condition { #read } else { ::std::default::Default::default() };
+            },
+            None => quote!(let #binding: #ty = #read;),
+        }
+    });
+
+    let writes = fields.iter().map(|field| {
+        let binding = &field.binding;
+        let write = quote!(crate::protocol::structs::Writeable::write(#binding, buffer, version)?;);
+
+        match field.condition() {
+            Some(condition) => quote!(if #condition { #write }),
+            None => write,
+        }
+    });
+
+    // used both to build the struct out of the read fields and to take it apart for writing
+    let members = fields.iter().map(|field| &field.member);
+    let bindings = fields.iter().map(|field| &field.binding);
+    let shape = match &data.fields {
+        Fields::Unit => quote!(#name),
+        _ => quote!(#name { #(#members: #bindings),* }),
+    };
+
+    let packet_impl = id.map(|PacketId { id, since }| {
+        // the lookup below relies on the order, so listing a version before an older one is an error rather than a
+        // wrong ID on some versions
+        let order = since.windows(2).map(|pair| {
+            let (older, newer) = (&pair[0].0, &pair[1].0);
+            quote::quote_spanned! {newer.span()=>
+                const _: () = ::std::assert!(
+                    (crate::protocol::structs::ProtocolVersion::#older as u8)
+                        < (crate::protocol::structs::ProtocolVersion::#newer as u8),
+                    "`since` versions have to be listed oldest first",
+                );
+            }
+        });
+
+        // newest first, so the first version the client is at least on wins
+        let since = since.iter().rev().map(|(version, id)| {
+            quote! {
+                if version >= crate::protocol::structs::ProtocolVersion::#version {
+                    return #id;
+                }
+            }
+        });
+
+        quote! {
+            #(#order)*
+
+            impl #impl_generics crate::protocol::packets::Packet for #name #ty_generics #where_clause {
+                #[allow(unused_variables)]
+                fn id(version: crate::protocol::structs::ProtocolVersion) -> i32 {
+                    #(#since)*
+                    #id
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #read_impl_generics crate::protocol::structs::Readable<#de> for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn read(
+                buffer: &mut ::std::io::Cursor<&#de [u8]>,
+                version: crate::protocol::structs::ProtocolVersion,
+            ) -> ::std::result::Result<Self, crate::protocol::error::ProtocolError> {
+                #(#reads)*
+                ::std::result::Result::Ok(#shape)
+            }
+        }
+
+        impl #impl_generics crate::protocol::structs::Writeable for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn write(
+                &self,
+                buffer: &mut ::std::vec::Vec<u8>,
+                version: crate::protocol::structs::ProtocolVersion,
+            ) -> ::anyhow::Result<()> {
+                let #shape = self;
+                #(#writes)*
+                ::std::result::Result::Ok(())
+            }
+        }
+
+        #packet_impl
+    })
+}
+
+/// A packet's ID on the oldest version, and the versions it changed on
+struct PacketId {
+    id: Expr,
+    since: Vec<(Ident, Expr)>,
+}
+
+/// Finds the `#[packet(id = ...)]` attribute on the struct, if there is one
+fn packet_id(attrs: &[syn::Attribute]) -> syn::Result<Option<PacketId>> {
+    let mut id = None;
+    let mut since = Vec::new();
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("packet")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse::<Expr>()?);
+                Ok(())
+            } else if meta.path.is_ident("since") {
+                meta.parse_nested_meta(|version| {
+                    let ident = version.path.require_ident()?.clone();
+                    since.push((ident, version.value()?.parse::<Expr>()?));
+                    Ok(())
+                })
+            } else {
+                Err(meta.error("unknown packet attribute, expected `id` or `since`"))
+            }
+        })?;
+    }
+
+    match id {
+        Some(id) => Ok(Some(PacketId { id, since })),
+        None if since.is_empty() => Ok(None),
+        None => Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`since` only changes an `id`, which is missing",
+        )),
+    }
+}
+
+fn packet_fields(fields: &Fields) -> syn::Result<Vec<PacketField>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let (member, binding) = match &field.ident {
+                // prefixed so fields can't shadow `buffer` or `version`
+                Some(ident) => (syn::Member::Named(ident.clone()), format_ident!("field_{}", ident)),
+                None => (syn::Member::Unnamed(index.into()), format_ident!("field_{}", index)),
+            };
+
+            let mut packet_field = PacketField {
+                member,
+                binding,
+                ty: field.ty.clone(),
+                since: None,
+                before: None,
+            };
+
+            for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("packet")) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("since") {
+                        packet_field.since = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("before") {
+                        packet_field.before = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("unknown field attribute, expected `since` or `before`"))
+                    }
+                })?;
+            }
+
+            Ok(packet_field)
+        })
+        .collect()
+}
@@ -0,0 +1,407 @@
+use crate::network::forwarding::ForwardingMode;
+use crate::world::generation::GeneratorSettings;
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::{Marker, ScanError},
+    Yaml, YamlLoader,
+};
+
+/// Written out when there's no config file yet, and parsed for the defaults of any missing keys
+const DEFAULT_CONFIG: &str = "\
+# Ferrous server configuration
+
+# The address and port to listen on. 0.0.0.0 listens on every interface.
+bind-address: 0.0.0.0
+port: 25565
+
+# Shown in the server list
+motd: A Ferrous server
+max-players: 20
+
+# How many chunks around each player are sent, from 2 to 32
+view-distance: 10
+# How many chunks around each player are ticked, from 2 to 32. Anything past the view distance is ignored.
+simulation-distance: 10
+
+# Packets at least this many bytes long are compressed. -1 turns compression off.
+compression-threshold: 256
+
+# Whether players are verified with the session server. Turn this off behind a proxy.
+online-mode: true
+session-server: https://sessionserver.mojang.com
+
+# How a proxy forwards player info: none, bungeecord or velocity
+forwarding: none
+# The forwarding secret from velocity.toml, when using velocity forwarding
+velocity-secret: \"\"
+
+# The folder the world is saved in
+world: world
+
+# How chunks that have never been saved are made in each dimension. flat can be followed by a colon and a
+# superflat preset: its layers from the bottom up, then the biome, like
+# flat:minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains
+# noise makes hills, seas and caves, and can be followed by a colon and a seed, like noise:12345
+# void makes nothing at all, or a platform to spawn on with void:platform
+# schematic places a Sponge .schem file with its corner at x,y,z (0,64,0 if left out), like schematic:hub.schem;0,64,0
+overworld-generator: flat
+nether-generator: flat:minecraft:bedrock,3*minecraft:netherrack;minecraft:nether_wastes
+end-generator: flat:minecraft:end_stone;minecraft:the_end
+";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("unable to access {path}: {source}")]
+    Io { path: String, source: io::Error },
+
+    #[error("{path} is not valid YAML: {source}")]
+    Syntax { path: String, source: ScanError },
+
+    #[error("{path} must be a mapping of settings")]
+    NotAMapping { path: String },
+
+    #[error("{path} line {line}: `{key}` {message}")]
+    Invalid {
+        path: String,
+        key: String,
+        line: usize,
+        message: String,
+    },
+}
+
+/// The server settings loaded from `server.yml`
+pub struct Config {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub motd: String,
+    pub max_players: usize,
+    pub view_distance: u8,
+    pub simulation_distance: u8,
+    /// `None` when compression is turned off
+    pub compression_threshold: Option<usize>,
+    pub online_mode: bool,
+    pub session_server: String,
+    pub forwarding: ForwardingMode,
+    pub world_path: PathBuf,
+    pub overworld_generator: GeneratorSettings,
+    pub nether_generator: GeneratorSettings,
+    pub end_generator: GeneratorSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::parse(DEFAULT_CONFIG, "default config").expect("default config is valid")
+    }
+}
+
+impl Config {
+    /// Loads the config at `path`, writing out the default config there first if it doesn't exist
+    pub fn load_or_create(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let io_error = |source| ConfigError::Io {
+            path: path.display().to_string(),
+            source,
+        };
+
+        if !path.exists() {
+            fs::write(path, DEFAULT_CONFIG).map_err(io_error)?;
+        }
+        let source = fs::read_to_string(path).map_err(io_error)?;
+
+        Config::parse(&source, &path.display().to_string())
+    }
+
+    /// Parses a config file's contents, using the defaults for any keys it leaves out.
+    ///
+    /// `path` is only used in error messages.
+    pub fn parse(source: &str, path: &str) -> Result<Self, ConfigError> {
+        let settings = Settings::parse(source, path)?;
+        let defaults = Settings::parse(DEFAULT_CONFIG, "default config")?;
+        let get = |key: &'static str| -> Setting<'_> {
+            match settings.values.get(key) {
+                Some(value) => settings.setting(key, value),
+                None => Setting {
+                    path,
+                    key,
+                    line: 0,
+                    value: &defaults.values[key],
+                },
+            }
+        };
+
+        let forwarding = get("forwarding");
+        let forwarding = match forwarding.string()?.as_str() {
+            "none" => ForwardingMode::None,
+            "bungeecord" => ForwardingMode::BungeeCord,
+            "velocity" => match get("velocity-secret").string()? {
+                secret if secret.is_empty() => {
+                    return Err(forwarding.invalid("needs `velocity-secret` to be set to use velocity"))
+                }
+                secret => ForwardingMode::Velocity { secret },
+            },
+            _ => return Err(forwarding.invalid("must be one of none, bungeecord or velocity")),
+        };
+
+        let generator = |key| {
+            let setting = get(key);
+            setting
+                .string()?
+                .parse::<GeneratorSettings>()
+                .map_err(|e| setting.invalid(&format!("is not a valid generator: {:#}", e)))
+        };
+
+        let bind_address = get("bind-address");
+        let session_server = get("session-server");
+        let compression_threshold = get("compression-threshold").integer(-1, i32::MAX as i64)?;
+
+        Ok(Config {
+            bind_address: bind_address
+                .string()?
+                .parse()
+                .map_err(|_| bind_address.invalid("must be an IP address"))?,
+            port: get("port").integer(1, u16::MAX as i64)? as u16,
+            motd: get("motd").string()?,
+            max_players: get("max-players").integer(0, i32::MAX as i64)? as usize,
+            view_distance: get("view-distance").integer(2, 32)? as u8,
+            simulation_distance: get("simulation-distance").integer(2, 32)? as u8,
+            compression_threshold: if compression_threshold < 0 {
+                None
+            } else {
+                Some(compression_threshold as usize)
+            },
+            online_mode: get("online-mode").boolean()?,
+            session_server: match session_server.string()? {
+                url if url.starts_with("http://") || url.starts_with("https://") => url,
+                _ => return Err(session_server.invalid("must be an http:// or https:// URL")),
+            },
+            forwarding,
+            world_path: PathBuf::from(get("world").string()?),
+            overworld_generator: generator("overworld-generator")?,
+            nether_generator: generator("nether-generator")?,
+            end_generator: generator("end-generator")?,
+        })
+    }
+
+    /// The address to listen for connections on
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+}
+
+/// The raw key-value pairs of a config file, along with the line each key is on
+struct Settings<'a> {
+    path: &'a str,
+    values: HashMap<String, Yaml>,
+    lines: HashMap<String, usize>,
+}
+
+impl<'a> Settings<'a> {
+    fn parse(source: &str, path: &'a str) -> Result<Self, ConfigError> {
+        let syntax_error = |source| ConfigError::Syntax {
+            path: path.to_owned(),
+            source,
+        };
+
+        let document = YamlLoader::load_from_str(source)
+            .map_err(syntax_error)?
+            .into_iter()
+            .next()
+            .unwrap_or(Yaml::Null);
+        let mapping = match document {
+            Yaml::Hash(mapping) => mapping,
+            // an empty file leaves everything at the defaults
+            Yaml::Null => Default::default(),
+            _ => return Err(ConfigError::NotAMapping { path: path.to_owned() }),
+        };
+
+        let mut key_lines = KeyLines::default();
+        Parser::new(source.chars())
+            .load(&mut key_lines, false)
+            .map_err(syntax_error)?;
+
+        let mut values = HashMap::new();
+        for (key, value) in mapping {
+            let key = match key {
+                Yaml::String(key) => key,
+                _ => return Err(ConfigError::NotAMapping { path: path.to_owned() }),
+            };
+
+            if !DEFAULT_CONFIG.lines().any(|line| line.starts_with(&format!("{}:", key))) {
+                return Err(ConfigError::Invalid {
+                    path: path.to_owned(),
+                    line: key_lines.lines.get(&key).copied().unwrap_or_default(),
+                    key,
+                    message: "is not a known setting".to_owned(),
+                });
+            }
+            values.insert(key, value);
+        }
+
+        Ok(Settings {
+            path,
+            values,
+            lines: key_lines.lines,
+        })
+    }
+
+    fn setting<'s>(&'s self, key: &'s str, value: &'s Yaml) -> Setting<'s> {
+        Setting {
+            path: self.path,
+            key,
+            line: self.lines.get(key).copied().unwrap_or_default(),
+            value,
+        }
+    }
+}
+
+/// A single value out of a config file, which knows where it came from for error messages
+struct Setting<'a> {
+    path: &'a str,
+    key: &'a str,
+    line: usize,
+    value: &'a Yaml,
+}
+
+impl<'a> Setting<'a> {
+    fn invalid(&self, message: &str) -> ConfigError {
+        ConfigError::Invalid {
+            path: self.path.to_owned(),
+            key: self.key.to_owned(),
+            line: self.line,
+            message: message.to_owned(),
+        }
+    }
+
+    fn string(&self) -> Result<String, ConfigError> {
+        match self.value {
+            Yaml::String(value) => Ok(value.clone()),
+            // let unquoted numbers and booleans through, `motd: 1` is still a fine MOTD
+            Yaml::Integer(value) => Ok(value.to_string()),
+            Yaml::Real(value) => Ok(value.clone()),
+            Yaml::Boolean(value) => Ok(value.to_string()),
+            _ => Err(self.invalid("must be a string")),
+        }
+    }
+
+    fn integer(&self, min: i64, max: i64) -> Result<i64, ConfigError> {
+        match self.value {
+            Yaml::Integer(value) if (min..=max).contains(value) => Ok(*value),
+            _ => Err(self.invalid(&format!("must be a whole number from {} to {}", min, max))),
+        }
+    }
+
+    fn boolean(&self) -> Result<bool, ConfigError> {
+        match self.value {
+            Yaml::Boolean(value) => Ok(*value),
+            _ => Err(self.invalid("must be true or false")),
+        }
+    }
+}
+
+/// Records which line each top level key of a YAML document is on, since `YamlLoader` throws that away
+#[derive(Default)]
+struct KeyLines {
+    lines: HashMap<String, usize>,
+    /// How deeply nested in mappings and sequences the parser currently is
+    depth: usize,
+    /// Whether the next node at the top level is a key rather than a value
+    expecting_key: bool,
+}
+
+impl MarkedEventReceiver for KeyLines {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::MappingStart(_) | Event::SequenceStart(_) => {
+                if self.depth == 0 {
+                    self.expecting_key = true;
+                } else if self.depth == 1 {
+                    // a nested value in the top level mapping
+                    self.expecting_key = false;
+                }
+                self.depth += 1;
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.depth -= 1;
+                if self.depth == 1 {
+                    self.expecting_key = true;
+                }
+            }
+            Event::Scalar(value, ..) if self.depth == 1 => {
+                if self.expecting_key {
+                    self.lines.entry(value).or_insert_with(|| mark.line());
+                }
+                self.expecting_key = !self.expecting_key;
+            }
+            Event::Alias(_) if self.depth == 1 => self.expecting_key = !self.expecting_key,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_parses() {
+        let config = Config::default();
+        assert_eq!(config.address(), "0.0.0.0:25565".parse().unwrap());
+        assert_eq!(config.compression_threshold, Some(256));
+        assert!(config.online_mode);
+        assert!(matches!(config.forwarding, ForwardingMode::None));
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_defaults() {
+        let config = Config::parse("port: 25570\ncompression-threshold: -1\n", "server.yml").unwrap();
+        assert_eq!(config.port, 25570);
+        assert_eq!(config.compression_threshold, None);
+        assert_eq!(config.max_players, 20);
+        assert_eq!(config.motd, "A Ferrous server");
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_line() {
+        let error = Config::parse("motd: hi\n\nview-distance: 64\n", "server.yml")
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(error, "server.yml line 3: `view-distance` must be a whole number from 2 to 32");
+
+        let error = Config::parse("motd: hi\nprot: 1\n", "server.yml").err().unwrap().to_string();
+        assert_eq!(error, "server.yml line 2: `prot` is not a known setting");
+
+        let error = Config::parse("port: 1\nforwarding: velocity\n", "server.yml").err().unwrap().to_string();
+        assert_eq!(error, "server.yml line 2: `forwarding` needs `velocity-secret` to be set to use velocity");
+    }
+
+    #[test]
+    fn generators_are_picked_per_dimension() {
+        let config = Config::parse("nether-generator: \"flat:minecraft:bedrock;minecraft:basalt_deltas\"\n", "server.yml")
+            .unwrap();
+        assert_eq!(config.overworld_generator, GeneratorSettings::Flat(Default::default()));
+        assert!(matches!(&config.nether_generator, GeneratorSettings::Flat(preset) if preset.layers.len() == 1));
+
+        let error = Config::parse("end-generator: caves\n", "server.yml").err().unwrap().to_string();
+        assert_eq!(
+            error,
+            "server.yml line 1: `end-generator` is not a valid generator: unknown generator caves, expected flat, noise, void or schematic"
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        assert!(matches!(
+            Config::parse("port: [25565\n", "server.yml"),
+            Err(ConfigError::Syntax { .. })
+        ));
+        assert!(matches!(Config::parse("- port\n", "server.yml"), Err(ConfigError::NotAMapping { .. })));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod config;
+pub mod nbt;
+pub mod network;
+pub mod protocol;
+pub mod util;
+pub mod world;
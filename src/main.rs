@@ -1,24 +1,82 @@
-use std::{net::{TcpListener}, io::{Read, Cursor}, result::{Result}};
-use anyhow::Context;
-use byteorder::ReadBytesExt;
-use crate::protocol::structs::{Readable, Writeable, VarInt, ProtocolVersion};
+use ferrous::config::Config;
+use ferrous::network::{self, auth::AuthSettings, status::StatusSettings, ServerState};
+use ferrous::util::log;
+use ferrous::world::{
+    thread::{WorldThread, OVERWORLD, THE_END, THE_NETHER},
+    world::Dimension,
+};
+use std::{path::Path, sync::Arc};
 
-mod protocol;
-mod world;
-mod util;
+/// Where the server list icon is loaded from, like vanilla
+const FAVICON_PATH: &str = "server-icon.png";
 
-const PROTOCOL: ProtocolVersion = ProtocolVersion::V1_18_2;
+/// Where the server config is loaded from, and written to if it doesn't exist yet
+const CONFIG_PATH: &str = "server.yml";
 
-struct Config {
-    port: u16,
-}
+#[tokio::main]
+async fn main() {
+    log::info_main("Hello, minecraft!");
+
+    let config = match Config::load_or_create(CONFIG_PATH) {
+        Ok(config) => config,
+        Err(e) => {
+            log::fatal_main(&format!("Unable to load config: {}", e));
+            return;
+        }
+    };
+
+    let mut status = StatusSettings {
+        motd: config.motd.clone(),
+        max_players: config.max_players,
+        favicon: None,
+    };
+    if Path::new(FAVICON_PATH).exists() {
+        match StatusSettings::load_favicon(FAVICON_PATH) {
+            Ok(favicon) => status.favicon = Some(favicon),
+            Err(e) => log::warn_main(&format!("Not using favicon: {:#}", e)),
+        }
+    }
+
+    let auth = AuthSettings {
+        online_mode: config.online_mode,
+        session_server: config.session_server.clone(),
+        forwarding: config.forwarding.clone(),
+    };
 
-fn main() {
-    println!("Hello, minecraft!");
-    let tcp = TcpListener::bind("127.0.0.1:25565").expect("Unable to bind to port! Is another process running?");
-    
-    for connection in tcp.incoming() {
-        let connection = connection.expect("Unable to read TCP connection!");
-        
+    // laid out like vanilla's worlds, so one can be swapped in
+    let dimensions = [
+        (OVERWORLD, &config.overworld_generator, config.world_path.join("region")),
+        (THE_NETHER, &config.nether_generator, config.world_path.join("DIM-1").join("region")),
+        (THE_END, &config.end_generator, config.world_path.join("DIM1").join("region")),
+    ];
+    let mut world = Vec::new();
+    for (name, settings, region_folder) in dimensions {
+        let generator = match settings.chunk_generator(0) {
+            Ok(generator) => generator,
+            Err(e) => {
+                log::fatal_main(&format!("Unable to start the {} generator: {:#}", name, e));
+                return;
+            }
+        };
+        let dimension = Dimension::new(
+            Some(region_folder),
+            generator,
+            config.view_distance,
+            config.simulation_distance,
+        );
+        world.push((name, dimension));
     }
+
+    let mut state = match ServerState::new(status, auth, config.compression_threshold) {
+        Ok(state) => state,
+        Err(e) => {
+            log::fatal_main(&format!("Unable to start server: {:#}", e));
+            return;
+        }
+    };
+    state.world = Some(WorldThread::start(world));
+    let state = Arc::new(state);
+    network::listen(config.address(), state)
+        .await
+        .expect("Unable to bind to port! Is another process running?");
 }
@@ -0,0 +1,427 @@
+//! Turning tags back into serde types.
+//!
+//! Anything numeric is accepted wherever a number is expected, as long as it fits, and arrays are accepted
+//! wherever a sequence is.
+
+use super::{Compound, NbtError, Tag, BYTE_ARRAY, INT_ARRAY, LONG_ARRAY};
+use serde::de::{
+    self,
+    value::{BorrowedStrDeserializer, SeqDeserializer},
+    DeserializeSeed, Deserialize, EnumAccess, Error, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use std::{collections::btree_map, convert::TryFrom, fmt, slice};
+
+/// Converts `tag` to `T`, which can borrow strings from it
+pub fn from_tag<'de, T: Deserialize<'de>>(tag: &'de Tag) -> Result<T, NbtError> {
+    T::deserialize(Deserializer(tag))
+}
+
+/// Converts `compound` to `T`, which can borrow strings from it
+pub fn from_compound<'de, T: Deserialize<'de>>(compound: &'de Compound) -> Result<T, NbtError> {
+    T::deserialize(CompoundDeserializer(compound))
+}
+
+struct Deserializer<'de>(&'de Tag);
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = NbtError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_i8(*value),
+            Tag::Short(value) => visitor.visit_i16(*value),
+            Tag::Int(value) => visitor.visit_i32(*value),
+            Tag::Long(value) => visitor.visit_i64(*value),
+            Tag::Float(value) => visitor.visit_f32(*value),
+            Tag::Double(value) => visitor.visit_f64(*value),
+            Tag::String(value) => visitor.visit_borrowed_str(value),
+            Tag::List(values) => visitor.visit_seq(ListAccess(values.iter())),
+            Tag::Compound(compound) => visitor.visit_map(CompoundAccess::new(compound)),
+            // arrays show up as a map with a single special key, so `Tag`'s own impl can tell them from lists
+            Tag::ByteArray(_) | Tag::IntArray(_) | Tag::LongArray(_) => visitor.visit_map(ArrayAccess(Some(self.0))),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_bool(*value != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self.0 {
+            Tag::ByteArray(values) => visitor.visit_byte_buf(values.iter().map(|&value| value as u8).collect()),
+            _ => self.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        // a None is left out altogether, so anything that's there is a Some
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visit_array(self.0, visitor).unwrap_or_else(|visitor| self.deserialize_any(visitor))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, NbtError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        match self.0 {
+            Tag::String(variant) => visitor.visit_enum(BorrowedStrDeserializer::new(variant)),
+            Tag::Compound(compound) => visit_enum_compound(compound, visitor),
+            _ => Err(NbtError::custom("enums must be a string or a compound with one entry")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string map struct identifier
+    }
+}
+
+/// Visits an array tag as a sequence of numbers, handing the visitor back if `tag` isn't an array
+fn visit_array<'de, V: Visitor<'de>>(tag: &'de Tag, visitor: V) -> Result<Result<V::Value, NbtError>, V> {
+    fn visit<'de, V: Visitor<'de>, T: IntoDeserializer<'de, NbtError> + Copy>(
+        values: &[T],
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        let mut seq = SeqDeserializer::new(values.iter().copied());
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    match tag {
+        Tag::ByteArray(values) => Ok(visit(values, visitor)),
+        Tag::IntArray(values) => Ok(visit(values, visitor)),
+        Tag::LongArray(values) => Ok(visit(values, visitor)),
+        _ => Err(visitor),
+    }
+}
+
+fn visit_enum_compound<'de, V: Visitor<'de>>(compound: &'de Compound, visitor: V) -> Result<V::Value, NbtError> {
+    let mut entries = compound.iter();
+    match (entries.next(), entries.next()) {
+        (Some((variant, value)), None) => visitor.visit_enum(EnumCompound { variant, value }),
+        _ => Err(NbtError::custom("enums must be a string or a compound with one entry")),
+    }
+}
+
+/// The root of `from_compound`, which acts like a compound tag
+struct CompoundDeserializer<'de>(&'de Compound);
+
+impl<'de> de::Deserializer<'de> for CompoundDeserializer<'de> {
+    type Error = NbtError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_map(CompoundAccess::new(self.0))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        visit_enum_compound(self.0, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit seq tuple
+        tuple_struct map struct identifier
+    }
+}
+
+struct ListAccess<'de>(slice::Iter<'de, Tag>);
+
+impl<'de> SeqAccess<'de> for ListAccess<'de> {
+    type Error = NbtError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, NbtError> {
+        self.0.next().map(|tag| seed.deserialize(Deserializer(tag))).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct CompoundAccess<'de> {
+    entries: btree_map::Iter<'de, String, Tag>,
+    /// The value of the entry whose key was just read
+    value: Option<&'de Tag>,
+}
+
+impl<'de> CompoundAccess<'de> {
+    fn new(compound: &'de Compound) -> Self {
+        CompoundAccess {
+            entries: compound.iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for CompoundAccess<'de> {
+    type Error = NbtError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, NbtError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, NbtError> {
+        let value = self.value.take().expect("next_value called before next_key");
+        seed.deserialize(Deserializer(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+/// An array tag as a map of its special key to its values
+struct ArrayAccess<'de>(Option<&'de Tag>);
+
+impl<'de> MapAccess<'de> for ArrayAccess<'de> {
+    type Error = NbtError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, NbtError> {
+        let key = match self.0 {
+            Some(Tag::ByteArray(_)) => BYTE_ARRAY,
+            Some(Tag::IntArray(_)) => INT_ARRAY,
+            Some(Tag::LongArray(_)) => LONG_ARRAY,
+            _ => return Ok(None),
+        };
+        seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, NbtError> {
+        let array = self.0.take().expect("next_value called before next_key");
+        seed.deserialize(ArrayDeserializer(array))
+    }
+}
+
+/// The values of an array tag, which are only ever a sequence
+struct ArrayDeserializer<'de>(&'de Tag);
+
+impl<'de> de::Deserializer<'de> for ArrayDeserializer<'de> {
+    type Error = NbtError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visit_array(self.0, visitor).unwrap_or_else(|_| unreachable!("only arrays are visited as arrays"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// An enum variant written as a compound with the variant's name as the only key
+struct EnumCompound<'de> {
+    variant: &'de str,
+    value: &'de Tag,
+}
+
+impl<'de> EnumAccess<'de> for EnumCompound<'de> {
+    type Error = NbtError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), NbtError> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for EnumCompound<'de> {
+    type Error = NbtError;
+
+    fn unit_variant(self) -> Result<(), NbtError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, NbtError> {
+        seed.deserialize(Deserializer(self.value))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, NbtError> {
+        de::Deserializer::deserialize_seq(Deserializer(self.value), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        de::Deserializer::deserialize_any(Deserializer(self.value), visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TagVisitor)
+    }
+}
+
+/// Builds a tag out of whatever it's given, widening unsigned numbers the same way serializing does
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = Tag;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an NBT tag")
+    }
+
+    fn visit_bool<E: Error>(self, value: bool) -> Result<Tag, E> {
+        Ok(Tag::Byte(value as i8))
+    }
+
+    fn visit_i8<E: Error>(self, value: i8) -> Result<Tag, E> {
+        Ok(Tag::Byte(value))
+    }
+
+    fn visit_i16<E: Error>(self, value: i16) -> Result<Tag, E> {
+        Ok(Tag::Short(value))
+    }
+
+    fn visit_i32<E: Error>(self, value: i32) -> Result<Tag, E> {
+        Ok(Tag::Int(value))
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<Tag, E> {
+        Ok(Tag::Long(value))
+    }
+
+    fn visit_u8<E: Error>(self, value: u8) -> Result<Tag, E> {
+        Ok(Tag::Short(value as i16))
+    }
+
+    fn visit_u16<E: Error>(self, value: u16) -> Result<Tag, E> {
+        Ok(Tag::Int(value as i32))
+    }
+
+    fn visit_u32<E: Error>(self, value: u32) -> Result<Tag, E> {
+        Ok(Tag::Long(value as i64))
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<Tag, E> {
+        i64::try_from(value)
+            .map(Tag::Long)
+            .map_err(|_| E::custom(format!("{} is too big for a long", value)))
+    }
+
+    fn visit_f32<E: Error>(self, value: f32) -> Result<Tag, E> {
+        Ok(Tag::Float(value))
+    }
+
+    fn visit_f64<E: Error>(self, value: f64) -> Result<Tag, E> {
+        Ok(Tag::Double(value))
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Tag, E> {
+        Ok(Tag::String(value.to_owned()))
+    }
+
+    fn visit_string<E: Error>(self, value: String) -> Result<Tag, E> {
+        Ok(Tag::String(value))
+    }
+
+    fn visit_bytes<E: Error>(self, value: &[u8]) -> Result<Tag, E> {
+        Ok(Tag::ByteArray(value.iter().map(|&byte| byte as i8).collect()))
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Tag, D::Error> {
+        Tag::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Tag, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Tag::List(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Tag, A::Error> {
+        let mut compound = Compound::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match &key[..] {
+                BYTE_ARRAY if compound.is_empty() => return Ok(Tag::ByteArray(map.next_value()?)),
+                INT_ARRAY if compound.is_empty() => return Ok(Tag::IntArray(map.next_value()?)),
+                LONG_ARRAY if compound.is_empty() => return Ok(Tag::LongArray(map.next_value()?)),
+                _ => {
+                    let value = map.next_value()?;
+                    compound.insert(key, value);
+                }
+            }
+        }
+        Ok(Tag::Compound(compound))
+    }
+}
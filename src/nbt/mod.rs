@@ -0,0 +1,831 @@
+//! Minecraft's Named Binary Tag format, used in packets and world files.
+//!
+//! `Tag` and `Compound` hold NBT of any shape, and `to_tag`/`from_tag` convert them to and from serde types.
+
+mod de;
+mod ser;
+
+pub use de::{from_compound, from_tag};
+pub use ser::{to_compound, to_tag};
+
+use crate::protocol::error::ProtocolError;
+use crate::protocol::structs::{ProtocolVersion, Readable, Writeable};
+use anyhow::{bail, Context};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt::Display,
+    fs,
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+use thiserror::Error;
+
+/// How much NBT a read will take before giving up, so untrusted data can't use up the stack or memory
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NbtLimits {
+    /// How deeply lists and compounds can nest
+    pub max_depth: usize,
+    /// How many bytes of NBT can be read in total
+    pub max_bytes: usize,
+}
+
+impl NbtLimits {
+    /// What vanilla accepts in packets
+    pub const NETWORK: NbtLimits = NbtLimits {
+        max_depth: 512,
+        max_bytes: 2 * 1024 * 1024,
+    };
+
+    /// World files can be as big as they like, but are still kept from nesting deep enough to overflow the stack
+    pub const FILE: NbtLimits = NbtLimits {
+        max_depth: 512,
+        max_bytes: usize::MAX,
+    };
+}
+
+/// How an NBT file is compressed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NbtCompression {
+    None,
+    /// What `level.dat` and player data use
+    Gzip,
+    Zlib,
+}
+
+/// Converting to and from serde types failed
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct NbtError(String);
+
+impl serde::ser::Error for NbtError {
+    fn custom<T: Display>(message: T) -> Self {
+        NbtError(message.to_string())
+    }
+}
+
+impl serde::de::Error for NbtError {
+    fn custom<T: Display>(message: T) -> Self {
+        NbtError(message.to_string())
+    }
+}
+
+/// The named tags inside a compound
+pub type Compound = BTreeMap<String, Tag>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    /// Every element of a list has to be the same type
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const TAG_END: u8 = 0;
+
+impl Tag {
+    /// The type ID written in front of the tag
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    pub fn as_byte(&self) -> Option<i8> {
+        match self {
+            Tag::Byte(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_short(&self) -> Option<i16> {
+        match self {
+            Tag::Short(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Tag::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_long(&self) -> Option<i64> {
+        match self {
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            Tag::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_double(&self) -> Option<f64> {
+        match self {
+            Tag::Double(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Tag::ByteArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn write_payload(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        match self {
+            Tag::Byte(value) => buffer.write_i8(*value)?,
+            Tag::Short(value) => buffer.write_i16::<BigEndian>(*value)?,
+            Tag::Int(value) => buffer.write_i32::<BigEndian>(*value)?,
+            Tag::Long(value) => buffer.write_i64::<BigEndian>(*value)?,
+            Tag::Float(value) => buffer.write_f32::<BigEndian>(*value)?,
+            Tag::Double(value) => buffer.write_f64::<BigEndian>(*value)?,
+            Tag::ByteArray(values) => {
+                write_len(values.len(), buffer)?;
+                buffer.extend(values.iter().map(|&value| value as u8));
+            }
+            Tag::String(value) => write_string(value, buffer)?,
+            Tag::List(values) => {
+                let id = values.first().map_or(TAG_END, Tag::id);
+                if values.iter().any(|value| value.id() != id) {
+                    bail!("list elements must all be the same type");
+                }
+                buffer.write_u8(id)?;
+                write_len(values.len(), buffer)?;
+                for value in values {
+                    value.write_payload(buffer)?;
+                }
+            }
+            Tag::Compound(compound) => write_compound(compound, buffer)?,
+            Tag::IntArray(values) => {
+                write_len(values.len(), buffer)?;
+                for value in values {
+                    buffer.write_i32::<BigEndian>(*value)?;
+                }
+            }
+            Tag::LongArray(values) => {
+                write_len(values.len(), buffer)?;
+                for value in values {
+                    buffer.write_i64::<BigEndian>(*value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_len(len: usize, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+    let len = i32::try_from(len).context("NBT array too long")?;
+    buffer.write_i32::<BigEndian>(len)?;
+    Ok(())
+}
+
+fn write_compound(compound: &Compound, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+    for (name, tag) in compound {
+        buffer.write_u8(tag.id())?;
+        write_string(name, buffer)?;
+        tag.write_payload(buffer)?;
+    }
+    buffer.write_u8(TAG_END)?;
+    Ok(())
+}
+
+/// Writes a string in Java's modified UTF-8, which is what NBT uses: NUL is written as two bytes, and characters
+/// outside the BMP are written as their UTF-16 surrogates, three bytes each.
+fn write_string(value: &str, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+    let mut encoded = Vec::with_capacity(value.len());
+    for unit in value.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => encoded.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                encoded.push(0xC0 | (unit >> 6) as u8);
+                encoded.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                encoded.push(0xE0 | (unit >> 12) as u8);
+                encoded.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                encoded.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+
+    let len = u16::try_from(encoded.len()).context("NBT string too long")?;
+    buffer.write_u16::<BigEndian>(len)?;
+    buffer.extend_from_slice(&encoded);
+    Ok(())
+}
+
+/// Reads tags while keeping track of how deep they are and how many bytes they've taken up
+struct Decoder<'a, 'b> {
+    buffer: &'a mut Cursor<&'b [u8]>,
+    limits: NbtLimits,
+    /// Where in the buffer the NBT started
+    start: u64,
+}
+
+impl<'a, 'b> Decoder<'a, 'b> {
+    fn new(buffer: &'a mut Cursor<&'b [u8]>, limits: NbtLimits) -> Self {
+        let start = buffer.position();
+        Decoder { buffer, limits, start }
+    }
+
+    /// How many more bytes can be read, whichever runs out first of the data and the size limit
+    fn remaining(&self) -> usize {
+        let used = (self.buffer.position() - self.start) as usize;
+        let left = self.buffer.get_ref().len().saturating_sub(self.buffer.position() as usize);
+        left.min(self.limits.max_bytes.saturating_sub(used))
+    }
+
+    fn check_size(&self) -> anyhow::Result<()> {
+        if (self.buffer.position() - self.start) as usize > self.limits.max_bytes {
+            bail!("NBT is bigger than {} bytes", self.limits.max_bytes);
+        }
+        Ok(())
+    }
+
+    /// Reads an array or list length, making sure there's enough left for `element_size` bytes per element
+    fn read_len(&mut self, element_size: usize) -> anyhow::Result<usize> {
+        let len = self.buffer.read_i32::<BigEndian>()?;
+        if len < 0 {
+            bail!("negative NBT length {}", len);
+        }
+        let len = len as usize;
+        if len * element_size > self.remaining() {
+            bail!("NBT length {} runs past the end of the data or the size limit", len);
+        }
+        Ok(len)
+    }
+
+    /// Checks a list or compound `depth` levels down is allowed, counting the root as the first level
+    fn check_depth(&self, depth: usize) -> anyhow::Result<()> {
+        if depth > self.limits.max_depth {
+            bail!("NBT nested deeper than {}", self.limits.max_depth);
+        }
+        Ok(())
+    }
+
+    fn read_payload(&mut self, id: u8, depth: usize) -> anyhow::Result<Tag> {
+        let tag = match id {
+            1 => Tag::Byte(self.buffer.read_i8()?),
+            2 => Tag::Short(self.buffer.read_i16::<BigEndian>()?),
+            3 => Tag::Int(self.buffer.read_i32::<BigEndian>()?),
+            4 => Tag::Long(self.buffer.read_i64::<BigEndian>()?),
+            5 => Tag::Float(self.buffer.read_f32::<BigEndian>()?),
+            6 => Tag::Double(self.buffer.read_f64::<BigEndian>()?),
+            7 => {
+                let len = self.read_len(1)?;
+                let mut values = vec![0; len];
+                self.buffer.read_exact(&mut values)?;
+                Tag::ByteArray(values.into_iter().map(|value| value as i8).collect())
+            }
+            8 => Tag::String(self.read_string()?),
+            9 => {
+                self.check_depth(depth + 1)?;
+                let id = self.buffer.read_u8()?;
+                // an empty list can say its elements are anything, even End, since there aren't any
+                let len = self.read_len(0)?;
+                if id == TAG_END && len > 0 {
+                    bail!("list of {} End tags", len);
+                }
+                let mut values = Vec::with_capacity(len.min(self.remaining()));
+                for _ in 0..len {
+                    values.push(self.read_payload(id, depth + 1)?);
+                }
+                Tag::List(values)
+            }
+            10 => Tag::Compound(self.read_compound(depth + 1)?),
+            11 => {
+                let len = self.read_len(4)?;
+                let buffer = &mut self.buffer;
+                Tag::IntArray((0..len).map(|_| buffer.read_i32::<BigEndian>()).collect::<Result<_, _>>()?)
+            }
+            12 => {
+                let len = self.read_len(8)?;
+                let buffer = &mut self.buffer;
+                Tag::LongArray((0..len).map(|_| buffer.read_i64::<BigEndian>()).collect::<Result<_, _>>()?)
+            }
+            id => bail!("unknown NBT tag type {}", id),
+        };
+
+        self.check_size()?;
+        Ok(tag)
+    }
+
+    fn read_compound(&mut self, depth: usize) -> anyhow::Result<Compound> {
+        self.check_depth(depth)?;
+        let mut compound = Compound::new();
+        loop {
+            let id = self.buffer.read_u8()?;
+            if id == TAG_END {
+                return Ok(compound);
+            }
+            let name = self.read_string()?;
+            let tag = self.read_payload(id, depth)?;
+            compound.insert(name, tag);
+        }
+    }
+
+    /// Reads the root compound, along with its name if it has one
+    fn read_root(&mut self, named: bool) -> anyhow::Result<Nbt> {
+        let id = self.buffer.read_u8()?;
+        if id != 10 {
+            bail!("NBT root must be a compound, found tag type {}", id);
+        }
+
+        let name = if named { self.read_string()? } else { String::new() };
+        let root = self.read_compound(1)?;
+        self.check_size()?;
+        Ok(Nbt { name, root })
+    }
+
+    fn read_string(&mut self) -> anyhow::Result<String> {
+        let len = self.buffer.read_u16::<BigEndian>()? as usize;
+        if len > self.remaining() {
+            bail!("NBT string runs past the end of the data or the size limit");
+        }
+        let mut bytes = vec![0; len];
+        self.buffer.read_exact(&mut bytes)?;
+        decode_string(&bytes)
+    }
+}
+
+fn decode_string(bytes: &[u8]) -> anyhow::Result<String> {
+    // decode back to UTF-16 units first, so surrogate pairs written separately join back up
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let continuation = |offset: usize| match bytes.get(i + offset) {
+            Some(&byte) if byte & 0xC0 == 0x80 => Ok((byte & 0x3F) as u16),
+            _ => bail!("invalid modified UTF-8 in NBT string"),
+        };
+        let (unit, width) = match byte {
+            0x01..=0x7F => (byte as u16, 1),
+            0xC0..=0xDF => (((byte & 0x1F) as u16) << 6 | continuation(1)?, 2),
+            0xE0..=0xEF => (((byte & 0x0F) as u16) << 12 | continuation(1)? << 6 | continuation(2)?, 3),
+            _ => bail!("invalid modified UTF-8 in NBT string"),
+        };
+        units.push(unit);
+        i += width;
+    }
+
+    String::from_utf16(&units).context("NBT string has unpaired surrogates")
+}
+
+/// A root compound, the form NBT is always sent and stored in: tagged as a compound, with a name that's
+/// almost always empty
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Nbt {
+    pub name: String,
+    pub root: Compound,
+}
+
+impl From<Compound> for Nbt {
+    fn from(root: Compound) -> Self {
+        Nbt {
+            name: String::new(),
+            root,
+        }
+    }
+}
+
+impl Nbt {
+    /// Reads a root compound with a name, the way files and packets before 1.20.2 store it
+    pub fn read_named(buffer: &mut Cursor<&[u8]>, limits: NbtLimits) -> anyhow::Result<Nbt> {
+        Decoder::new(buffer, limits).read_root(true)
+    }
+
+    /// Reads a root compound without a name, the way packets from 1.20.2 on send it
+    pub fn read_unnamed(buffer: &mut Cursor<&[u8]>, limits: NbtLimits) -> anyhow::Result<Nbt> {
+        Decoder::new(buffer, limits).read_root(false)
+    }
+
+    pub fn write_named(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        buffer.write_u8(10)?;
+        write_string(&self.name, buffer)?;
+        write_compound(&self.root, buffer)
+    }
+
+    /// Writes the root compound without its name
+    pub fn write_unnamed(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        buffer.write_u8(10)?;
+        write_compound(&self.root, buffer)
+    }
+
+    /// Reads the contents of an NBT file, working out how it's compressed from its first bytes
+    pub fn from_file_bytes(bytes: &[u8]) -> anyhow::Result<Nbt> {
+        let mut decompressed = Vec::new();
+        let bytes = match bytes {
+            [0x1F, 0x8B, ..] => {
+                GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+                &decompressed[..]
+            }
+            // a compound starts with 0x0A, but zlib data always starts with 0x78
+            [0x78, ..] => {
+                ZlibDecoder::new(bytes).read_to_end(&mut decompressed)?;
+                &decompressed[..]
+            }
+            _ => bytes,
+        };
+
+        Nbt::read_named(&mut Cursor::new(bytes), NbtLimits::FILE)
+    }
+
+    /// The contents of an NBT file holding this compound
+    pub fn to_file_bytes(&self, compression: NbtCompression) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_named(&mut bytes)?;
+
+        Ok(match compression {
+            NbtCompression::None => bytes,
+            NbtCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes)?;
+                encoder.finish()?
+            }
+            NbtCompression::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes)?;
+                encoder.finish()?
+            }
+        })
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> anyhow::Result<Nbt> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("unable to read {}", path.display()))?;
+        Nbt::from_file_bytes(&bytes).with_context(|| format!("{} is not a valid NBT file", path.display()))
+    }
+
+    pub fn write_file(&self, path: impl AsRef<Path>, compression: NbtCompression) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_file_bytes(compression)?).with_context(|| format!("unable to write {}", path.display()))
+    }
+}
+
+/// Whether packets in `version` give the root compound a name. Every version we speak does; it's 1.20.2 where that
+/// changes, and this is the one place to tell it apart once it's supported
+const fn named_on_network(_version: ProtocolVersion) -> bool {
+    true
+}
+
+impl<'de> Readable<'de> for Nbt {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
+    where
+        Self: Sized,
+    {
+        let nbt = if named_on_network(version) {
+            Nbt::read_named(buffer, NbtLimits::NETWORK)?
+        } else {
+            Nbt::read_unnamed(buffer, NbtLimits::NETWORK)?
+        };
+        Ok(nbt)
+    }
+}
+
+impl Writeable for Nbt {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        if named_on_network(version) {
+            self.write_named(buffer)
+        } else {
+            self.write_unnamed(buffer)
+        }
+    }
+}
+
+/// A serde type sent as NBT, for packet fields with a fixed shape like the dimension codec
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NbtValue<T>(pub T);
+
+impl<'de, T: DeserializeOwned> Readable<'de> for NbtValue<T> {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
+    where
+        Self: Sized,
+    {
+        let nbt = Nbt::read(buffer, version)?;
+        Ok(NbtValue(from_compound(&nbt.root).map_err(anyhow::Error::from)?))
+    }
+}
+
+impl<T: Serialize> Writeable for NbtValue<T> {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        Nbt::from(to_compound(&self.0)?).write(buffer, version)
+    }
+}
+
+// names serde sees for the array wrappers, so the serializer knows to make an array tag rather than a list
+const BYTE_ARRAY: &str = "__nbt_byte_array";
+const INT_ARRAY: &str = "__nbt_int_array";
+const LONG_ARRAY: &str = "__nbt_long_array";
+
+macro_rules! array_wrapper {
+    ($(#[$doc:meta])* $name:ident($element:ty), $token:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Debug, Default, PartialEq, Eq)]
+        pub struct $name(pub Vec<$element>);
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct($token, &self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Vec::deserialize(deserializer).map($name)
+            }
+        }
+    };
+}
+
+array_wrapper!(
+    /// Bytes that serialize to a byte array tag, where a `Vec<i8>` would be a list of byte tags
+    ByteArray(i8),
+    BYTE_ARRAY
+);
+array_wrapper!(
+    /// Ints that serialize to an int array tag, where a `Vec<i32>` would be a list of int tags
+    IntArray(i32),
+    INT_ARRAY
+);
+array_wrapper!(
+    /// Longs that serialize to a long array tag, where a `Vec<i64>` would be a list of long tags
+    LongArray(i64),
+    LONG_ARRAY
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSION: ProtocolVersion = ProtocolVersion::V1_18_2;
+
+    #[test]
+    fn hello_world_matches_reference() {
+        // hello_world.nbt from the original NBT spec
+        let bytes = b"\x0a\x00\x0bhello world\x08\x00\x04name\x00\x09Bananrama\x00";
+
+        let nbt = Nbt::read(&mut Cursor::new(&bytes[..]), VERSION).unwrap();
+        assert_eq!(nbt.name, "hello world");
+        assert_eq!(nbt.root["name"], Tag::String("Bananrama".to_owned()));
+
+        let mut written = Vec::new();
+        nbt.write(&mut written, VERSION).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn every_tag_round_trips() {
+        let mut nested = Compound::new();
+        nested.insert("heights".to_owned(), Tag::LongArray(vec![i64::MIN, 0, 7]));
+        nested.insert("ints".to_owned(), Tag::IntArray(vec![-1, 1]));
+
+        let mut root = Compound::new();
+        root.insert("byte".to_owned(), Tag::Byte(-3));
+        root.insert("short".to_owned(), Tag::Short(300));
+        root.insert("int".to_owned(), Tag::Int(-70000));
+        root.insert("long".to_owned(), Tag::Long(1 << 40));
+        root.insert("float".to_owned(), Tag::Float(0.5));
+        root.insert("double".to_owned(), Tag::Double(-2.25));
+        root.insert("bytes".to_owned(), Tag::ByteArray(vec![1, -1]));
+        root.insert("text".to_owned(), Tag::String("nul \0 and emoji 🦀".to_owned()));
+        root.insert("list".to_owned(), Tag::List(vec![Tag::Compound(nested.clone()), Tag::Compound(nested)]));
+        root.insert("empty".to_owned(), Tag::List(Vec::new()));
+
+        let nbt = Nbt::from(root);
+        let mut buffer = Vec::new();
+        nbt.write(&mut buffer, VERSION).unwrap();
+        assert_eq!(Nbt::read(&mut Cursor::new(&buffer[..]), VERSION).unwrap(), nbt);
+    }
+
+    #[test]
+    fn strings_use_modified_utf8() {
+        let mut buffer = Vec::new();
+        write_string("\0🦀", &mut buffer).unwrap();
+        // NUL as two bytes, then the crab as two three-byte surrogates
+        assert_eq!(buffer, [0, 8, 0xC0, 0x80, 0xED, 0xA0, 0xBE, 0xED, 0xB6, 0x80]);
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        let read = |bytes: &[u8]| Nbt::read(&mut Cursor::new(bytes), VERSION);
+
+        // a long array claiming far more entries than there are bytes
+        assert!(read(b"\x0a\x00\x00\x0c\x00\x01a\x7f\xff\xff\xff").is_err());
+        // lists nested past the depth limit
+        let mut deep = b"\x0a\x00\x00\x09\x00\x01a".to_vec();
+        for _ in 0..600 {
+            deep.extend_from_slice(b"\x09\x00\x00\x00\x01");
+        }
+        assert!(read(&deep).is_err());
+        // not a compound at the root
+        assert!(read(b"\x08\x00\x00\x00\x00").is_err());
+    }
+
+    #[test]
+    fn size_limits_apply() {
+        let mut root = Compound::new();
+        root.insert("bytes".to_owned(), Tag::ByteArray(vec![0; 3 * 1024 * 1024]));
+        let mut buffer = Vec::new();
+        Nbt::from(root).write_named(&mut buffer).unwrap();
+
+        assert!(Nbt::read(&mut Cursor::new(&buffer[..]), VERSION).is_err());
+        assert!(Nbt::read_named(&mut Cursor::new(&buffer[..]), NbtLimits::FILE).is_ok());
+
+        let shallow = NbtLimits {
+            max_depth: 2,
+            max_bytes: usize::MAX,
+        };
+        let nested = b"\x0a\x00\x00\x0a\x00\x01a\x0a\x00\x01b\x00\x00\x00";
+        assert!(Nbt::read_named(&mut Cursor::new(&nested[..]), shallow).is_err());
+        assert!(Nbt::read_named(&mut Cursor::new(&nested[..]), NbtLimits::FILE).is_ok());
+    }
+
+    #[test]
+    fn roots_can_be_unnamed() {
+        let mut root = Compound::new();
+        root.insert("a".to_owned(), Tag::Int(1));
+        let nbt = Nbt::from(root);
+
+        let mut buffer = Vec::new();
+        nbt.write_unnamed(&mut buffer).unwrap();
+        assert_eq!(buffer, b"\x0a\x03\x00\x01a\x00\x00\x00\x01\x00");
+        assert_eq!(Nbt::read_unnamed(&mut Cursor::new(&buffer[..]), NbtLimits::NETWORK).unwrap(), nbt);
+    }
+
+    #[test]
+    fn files_are_decompressed() {
+        let mut root = Compound::new();
+        root.insert("LevelName".to_owned(), Tag::String("world".to_owned()));
+        let nbt = Nbt {
+            name: "Data".to_owned(),
+            root,
+        };
+
+        for compression in [NbtCompression::None, NbtCompression::Gzip, NbtCompression::Zlib] {
+            let bytes = nbt.to_file_bytes(compression).unwrap();
+            assert_eq!(Nbt::from_file_bytes(&bytes).unwrap(), nbt);
+        }
+        assert_eq!(&nbt.to_file_bytes(NbtCompression::Gzip).unwrap()[..2], [0x1F, 0x8B]);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Line { from: (i32, i32), to: (i32, i32) },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Everything {
+        flag: bool,
+        count: u8,
+        id: i64,
+        name: String,
+        nickname: Option<String>,
+        scores: Vec<i16>,
+        heights: LongArray,
+        data: ByteArray,
+        shape: Shape,
+        shapes: Vec<Shape>,
+        extra: BTreeMap<String, Tag>,
+    }
+
+    #[test]
+    fn serde_types_round_trip() {
+        let mut extra = BTreeMap::new();
+        extra.insert("ints".to_owned(), Tag::IntArray(vec![1, 2]));
+        extra.insert("list".to_owned(), Tag::List(vec![Tag::Short(1)]));
+        let value = Everything {
+            flag: true,
+            count: 200,
+            id: -5,
+            name: "steve".to_owned(),
+            nickname: None,
+            scores: vec![1, -1],
+            heights: LongArray(vec![i64::MAX]),
+            data: ByteArray(vec![1, 2, 3]),
+            shape: Shape::Point,
+            shapes: vec![
+                Shape::Circle(2.5),
+                Shape::Line {
+                    from: (0, 0),
+                    to: (1, -1),
+                },
+            ],
+            extra,
+        };
+
+        let compound = to_compound(&value).unwrap();
+        assert_eq!(compound["flag"], Tag::Byte(1));
+        assert_eq!(compound["count"], Tag::Short(200));
+        assert!(!compound.contains_key("nickname"));
+        assert_eq!(compound["scores"], Tag::List(vec![Tag::Short(1), Tag::Short(-1)]));
+        assert_eq!(compound["heights"], Tag::LongArray(vec![i64::MAX]));
+        assert_eq!(compound["data"], Tag::ByteArray(vec![1, 2, 3]));
+        assert_eq!(from_compound::<Everything>(&compound).unwrap(), value);
+
+        // and through bytes, the way it'd go over the network
+        let mut buffer = Vec::new();
+        NbtValue(&value).write(&mut buffer, VERSION).unwrap();
+        let read = NbtValue::<Everything>::read(&mut Cursor::new(&buffer[..]), VERSION).unwrap();
+        assert_eq!(read.0, value);
+    }
+
+    #[test]
+    fn tags_keep_their_types_through_serde() {
+        let mut root = Compound::new();
+        root.insert("bytes".to_owned(), Tag::ByteArray(vec![-1]));
+        root.insert("longs".to_owned(), Tag::LongArray(vec![]));
+        root.insert("list".to_owned(), Tag::List(vec![Tag::Float(1.0)]));
+        let tag = Tag::Compound(root);
+
+        assert_eq!(to_tag(&tag).unwrap(), tag);
+        assert_eq!(from_tag::<Tag>(&tag).unwrap(), tag);
+
+        // numbers fit into whatever's asked for if they can
+        assert_eq!(from_tag::<u64>(&Tag::Byte(5)).unwrap(), 5);
+        assert!(from_tag::<u8>(&Tag::Int(300)).is_err());
+        assert_eq!(from_tag::<Vec<u8>>(&Tag::ByteArray(vec![1, 2])).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn mismatched_types_are_rejected() {
+        assert!(to_tag(&None::<i32>).is_err());
+        assert!(to_compound(&5).is_err());
+        assert!(from_tag::<String>(&Tag::Int(1)).is_err());
+        // lists can only hold one type
+        assert!(to_tag(&(1, "two")).is_err());
+    }
+}
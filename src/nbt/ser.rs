@@ -0,0 +1,390 @@
+//! Turning serde types into tags.
+//!
+//! Structs and maps become compounds, sequences become lists, and `None` fields are left out. NBT has no
+//! unsigned types, so unsigned integers are widened to the next signed type up.
+
+use super::{Compound, NbtError, Tag, BYTE_ARRAY, INT_ARRAY, LONG_ARRAY};
+use serde::ser::{self, Error, Serialize};
+use std::convert::TryFrom;
+
+/// Converts `value` to a tag
+pub fn to_tag<T: Serialize + ?Sized>(value: &T) -> Result<Tag, NbtError> {
+    value
+        .serialize(Serializer)?
+        .ok_or_else(|| NbtError::custom("NBT can't hold a None on its own"))
+}
+
+/// Converts `value` to a compound, which it has to serialize as, like a struct or a map does
+pub fn to_compound<T: Serialize + ?Sized>(value: &T) -> Result<Compound, NbtError> {
+    match to_tag(value)? {
+        Tag::Compound(compound) => Ok(compound),
+        tag => Err(NbtError::custom(format!("expected a compound, found tag type {}", tag.id()))),
+    }
+}
+
+impl Serialize for Tag {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Tag::Byte(value) => serializer.serialize_i8(*value),
+            Tag::Short(value) => serializer.serialize_i16(*value),
+            Tag::Int(value) => serializer.serialize_i32(*value),
+            Tag::Long(value) => serializer.serialize_i64(*value),
+            Tag::Float(value) => serializer.serialize_f32(*value),
+            Tag::Double(value) => serializer.serialize_f64(*value),
+            Tag::ByteArray(values) => serializer.serialize_newtype_struct(BYTE_ARRAY, values),
+            Tag::String(value) => serializer.serialize_str(value),
+            Tag::List(values) => serializer.collect_seq(values),
+            Tag::Compound(compound) => serializer.collect_map(compound),
+            Tag::IntArray(values) => serializer.serialize_newtype_struct(INT_ARRAY, values),
+            Tag::LongArray(values) => serializer.serialize_newtype_struct(LONG_ARRAY, values),
+        }
+    }
+}
+
+/// Serializes to `None` for a `None`, so compounds can leave it out
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = VariantSerializer<ListSerializer>;
+    type SerializeMap = CompoundSerializer;
+    type SerializeStruct = CompoundSerializer;
+    type SerializeStructVariant = VariantSerializer<CompoundSerializer>;
+
+    fn serialize_bool(self, value: bool) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Byte(value as i8)))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Byte(value)))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Short(value)))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Int(value)))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Long(value)))
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Short(value as i16)))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Int(value as i32)))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Long(value as i64)))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Self::Ok, NbtError> {
+        match i64::try_from(value) {
+            Ok(value) => Ok(Some(Tag::Long(value))),
+            Err(_) => Err(NbtError::custom(format!("{} is too big for a long", value))),
+        }
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Float(value)))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Double(value)))
+    }
+
+    fn serialize_char(self, value: char) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::String(value.to_string())))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::String(value.to_owned())))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::ByteArray(value.iter().map(|&byte| byte as i8).collect())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, NbtError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, NbtError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, NbtError> {
+        Err(NbtError::custom("NBT has no unit type"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Compound(Compound::new())))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::String(variant.to_owned())))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, NbtError> {
+        let tag = value.serialize(self)?;
+        if ![BYTE_ARRAY, INT_ARRAY, LONG_ARRAY].contains(&name) {
+            return Ok(tag);
+        }
+
+        let elements = match tag {
+            Some(Tag::List(elements)) => elements,
+            // bytes serialized with `serialize_bytes` are already an array
+            Some(Tag::ByteArray(bytes)) if name == BYTE_ARRAY => return Ok(Some(Tag::ByteArray(bytes))),
+            _ => return Err(NbtError::custom("arrays have to serialize as sequences")),
+        };
+        let array = match name {
+            BYTE_ARRAY => elements.iter().map(Tag::as_byte).collect::<Option<_>>().map(Tag::ByteArray),
+            INT_ARRAY => elements.iter().map(Tag::as_int).collect::<Option<_>>().map(Tag::IntArray),
+            _ => elements.iter().map(Tag::as_long).collect::<Option<_>>().map(Tag::LongArray),
+        };
+        array
+            .map(Some)
+            .ok_or_else(|| NbtError::custom("array elements are the wrong type"))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, NbtError> {
+        let mut compound = Compound::new();
+        if let Some(tag) = value.serialize(self)? {
+            compound.insert(variant.to_owned(), tag);
+        }
+        Ok(Some(Tag::Compound(compound)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, NbtError> {
+        Ok(ListSerializer {
+            elements: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, NbtError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, NbtError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, NbtError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, NbtError> {
+        Ok(CompoundSerializer {
+            compound: Compound::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct, NbtError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, NbtError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+struct ListSerializer {
+    elements: Vec<Tag>,
+}
+
+impl ListSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        let tag = value
+            .serialize(Serializer)?
+            .ok_or_else(|| NbtError::custom("lists can't hold None"))?;
+        if self.elements.first().is_some_and(|first| first.id() != tag.id()) {
+            return Err(NbtError::custom("list elements must all be the same type"));
+        }
+        self.elements.push(tag);
+        Ok(())
+    }
+
+    fn finish(self) -> Tag {
+        Tag::List(self.elements)
+    }
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Some(self.finish()))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Some(self.finish()))
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Some(self.finish()))
+    }
+}
+
+struct CompoundSerializer {
+    compound: Compound,
+    /// The key of the entry being serialized, between `serialize_key` and `serialize_value`
+    key: Option<String>,
+}
+
+impl CompoundSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), NbtError> {
+        if let Some(tag) = value.serialize(Serializer)? {
+            self.compound.insert(key, tag);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Tag {
+        Tag::Compound(self.compound)
+    }
+}
+
+impl ser::SerializeMap for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), NbtError> {
+        // numbers are allowed as well as strings, the way JSON allows them
+        self.key = Some(match to_tag(key)? {
+            Tag::String(key) => key,
+            Tag::Byte(key) => key.to_string(),
+            Tag::Short(key) => key.to_string(),
+            Tag::Int(key) => key.to_string(),
+            Tag::Long(key) => key.to_string(),
+            _ => return Err(NbtError::custom("compound keys must be strings")),
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Some(self.finish()))
+    }
+}
+
+impl ser::SerializeStruct for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), NbtError> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Some(self.finish()))
+    }
+}
+
+/// Wraps what an enum variant holds in a compound with the variant's name as the only key
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+fn wrap_variant(variant: &str, tag: Tag) -> Option<Tag> {
+    let mut compound = Compound::new();
+    compound.insert(variant.to_owned(), tag);
+    Some(Tag::Compound(compound))
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<ListSerializer> {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(wrap_variant(self.variant, self.inner.finish()))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<CompoundSerializer> {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), NbtError> {
+        self.inner.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(wrap_variant(self.variant, self.inner.finish()))
+    }
+}
@@ -0,0 +1,313 @@
+use crate::network::forwarding::ForwardingMode;
+use crate::protocol::error::ProtocolError;
+use anyhow::Context;
+use md5::Md5;
+use rsa::{
+    pkcs1v15::{Signature, VerifyingKey},
+    pkcs8::{DecodePublicKey, EncodePublicKey},
+    signature::Verifier,
+    Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey,
+};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::{convert::TryFrom, sync::OnceLock, time::Duration};
+use uuid::{Builder, Uuid};
+
+/// Mojang's session server, which vouches for players joining online-mode servers
+pub const MOJANG_SESSION_SERVER: &str = "https://sessionserver.mojang.com";
+
+/// The key size vanilla uses for the login key exchange
+const KEY_BITS: usize = 1024;
+
+/// How long the session server gets to accept a connection, and then to answer, before the login is given up on
+const SESSION_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const SESSION_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How players are authenticated when they log in
+pub struct AuthSettings {
+    /// Whether players have to be verified by the session server
+    pub online_mode: bool,
+    /// The base URL of the session server
+    pub session_server: String,
+    /// Whether player info is forwarded by a proxy, which takes the place of online mode
+    pub forwarding: ForwardingMode,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        AuthSettings {
+            online_mode: true,
+            session_server: MOJANG_SESSION_SERVER.to_owned(),
+            forwarding: ForwardingMode::None,
+        }
+    }
+}
+
+/// The keypair clients encrypt their shared secret with during login
+pub struct ServerKey {
+    private: RsaPrivateKey,
+    /// The public key as DER, which is how it's sent in Encryption Request
+    public_der: Vec<u8>,
+}
+
+impl ServerKey {
+    pub fn generate() -> anyhow::Result<Self> {
+        let private = RsaPrivateKey::new(&mut rand::thread_rng(), KEY_BITS).context("unable to generate RSA key")?;
+        let public_der = private
+            .to_public_key()
+            .to_public_key_der()
+            .context("unable to encode RSA public key")?
+            .into_vec();
+
+        Ok(ServerKey { private, public_der })
+    }
+
+    pub fn public_der(&self) -> &[u8] {
+        &self.public_der
+    }
+
+    /// Decrypts the `what` the client encrypted with our public key
+    pub fn decrypt(&self, what: &'static str, data: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        self.private
+            .decrypt(Pkcs1v15Encrypt, data)
+            .map_err(|e| ProtocolError::Decryption(what, e))
+    }
+}
+
+/// A player's profile, as returned by the session server
+#[derive(Clone, Debug, Deserialize)]
+pub struct GameProfile {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// A signed profile property, such as the player's skin under `textures`
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+/// The UUID vanilla gives a player in offline mode: an MD5 based (version 3) UUID of `OfflinePlayer:<name>`
+pub fn offline_uuid(name: &str) -> Uuid {
+    let digest = Md5::digest(format!("OfflinePlayer:{}", name).as_bytes());
+    Builder::from_md5_bytes(digest.into()).into_uuid()
+}
+
+/// Computes the server ID hash both the client and the session server derive from the key exchange.
+///
+/// This is a SHA-1 digest printed as a signed, two's complement number in hex, like Java's `BigInteger.toString(16)`.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(server_id.as_bytes());
+    hasher.update(shared_secret);
+    hasher.update(public_key);
+    let mut digest: [u8; 20] = hasher.finalize().into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        // negate: invert every bit, then add one
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            *byte = !*byte;
+            if carry {
+                let (sum, overflowed) = byte.overflowing_add(1);
+                *byte = sum;
+                carry = overflowed;
+            }
+        }
+    }
+
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hex = match hex.trim_start_matches('0') {
+        "" => "0",
+        trimmed => trimmed,
+    };
+
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_owned()
+    }
+}
+
+/// Checks the verify token a 1.19 to 1.19.2 client signed with its chat key, given as DER, rather than encrypting it.
+///
+/// The signature is SHA-256 with RSA over the token followed by the salt.
+pub fn verify_signed_token(public_key: &[u8], token: &[u8], salt: i64, signature: &[u8]) -> anyhow::Result<bool> {
+    let key = RsaPublicKey::from_public_key_der(public_key).context("invalid player public key")?;
+    let signature = Signature::try_from(signature).context("invalid verify token signature")?;
+
+    let message = [token, &salt.to_be_bytes()[..]].concat();
+    Ok(VerifyingKey::<Sha256>::new(key).verify(&message, &signature).is_ok())
+}
+
+/// A `has_joined` check a connection is waiting on, for whoever drives it to make off its task
+pub struct JoinCheck {
+    pub session_server: String,
+    pub username: String,
+    pub server_hash: String,
+}
+
+impl JoinCheck {
+    pub fn send(&self) -> anyhow::Result<Option<GameProfile>> {
+        has_joined(&self.session_server, &self.username, &self.server_hash)
+    }
+}
+
+/// Asks the session server whether `username` has joined with the given server hash.
+///
+/// Returns the player's profile if they have, or `None` if the session server doesn't vouch for them. This blocks
+/// until the session server answers or times out, so it has to be kept off the async workers.
+pub fn has_joined(session_server: &str, username: &str, server_hash: &str) -> anyhow::Result<Option<GameProfile>> {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    let agent = AGENT.get_or_init(|| {
+        ureq::AgentBuilder::new()
+            .timeout_connect(SESSION_CONNECT_TIMEOUT)
+            .timeout_read(SESSION_READ_TIMEOUT)
+            .build()
+    });
+    has_joined_with(agent, session_server, username, server_hash)
+}
+
+fn has_joined_with(
+    agent: &ureq::Agent,
+    session_server: &str,
+    username: &str,
+    server_hash: &str,
+) -> anyhow::Result<Option<GameProfile>> {
+    let url = format!("{}/session/minecraft/hasJoined", session_server.trim_end_matches('/'));
+    let response = agent
+        .get(&url)
+        .query("username", username)
+        .query("serverId", server_hash)
+        .call()
+        .context("session server request failed")?;
+
+    // 204 No Content is how the session server says no
+    if response.status() != 200 {
+        return Ok(None);
+    }
+
+    let body = response.into_string().context("unable to read session server response")?;
+    let profile = serde_json::from_str(&body).context("session server sent an invalid profile")?;
+    Ok(Some(profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    #[test]
+    fn server_hash_matches_vanilla() {
+        // sha1 of just the names, the usual reference values for this encoding
+        assert_eq!(server_hash("Notch", &[], &[]), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(server_hash("jeb_", &[], &[]), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(server_hash("simon", &[], &[]), "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+
+    #[test]
+    fn offline_uuid_matches_vanilla() {
+        assert_eq!(offline_uuid("Notch").to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        assert_eq!(offline_uuid("jeb_").get_version_num(), 3);
+    }
+
+    #[test]
+    fn key_decrypts_what_its_public_half_encrypts() {
+        let key = ServerKey::generate().unwrap();
+        let public = RsaPublicKey::from_public_key_der(key.public_der()).unwrap();
+
+        let secret = [42u8; 16];
+        let encrypted = public.encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, &secret).unwrap();
+        assert_eq!(key.decrypt("shared secret", &encrypted).unwrap(), secret);
+    }
+
+    #[test]
+    fn signed_verify_tokens_check_out() {
+        use rsa::{pkcs1v15::SigningKey, signature::{SignatureEncoding, Signer}};
+
+        let player = RsaPrivateKey::new(&mut rand::thread_rng(), KEY_BITS).unwrap();
+        let public = player.to_public_key().to_public_key_der().unwrap().into_vec();
+        let signature = SigningKey::<Sha256>::new(player).sign(&[1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 9]).to_vec();
+
+        assert!(verify_signed_token(&public, &[1, 2, 3, 4], 9, &signature).unwrap());
+        assert!(!verify_signed_token(&public, &[1, 2, 3, 4], 10, &signature).unwrap());
+        assert!(!verify_signed_token(&public, &[4, 3, 2, 1], 9, &signature).unwrap());
+        assert!(verify_signed_token(&[0; 8], &[1, 2, 3, 4], 9, &signature).is_err());
+    }
+
+    /// Serves a single canned HTTP response, returning the server's base URL and the request line it received
+    fn stand_in_session_server(status: &'static str, body: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            // drain the headers
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request_line
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn has_joined_parses_profile() {
+        let (url, server) = stand_in_session_server(
+            "200 OK",
+            r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch","properties":[{"name":"textures","value":"e30=","signature":"c2ln"}]}"#,
+        );
+
+        let profile = has_joined(&url, "notch", "-1f").unwrap().expect("profile should be returned");
+        assert_eq!(profile.id, Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap());
+        assert_eq!(profile.name, "Notch");
+        assert_eq!(profile.properties[0].name, "textures");
+        assert_eq!(profile.properties[0].signature.as_deref(), Some("c2ln"));
+
+        let request_line = server.join().unwrap();
+        assert!(request_line.starts_with("GET /session/minecraft/hasJoined?username=notch&serverId=-1f "));
+    }
+
+    #[test]
+    fn has_joined_rejects_on_no_content() {
+        let (url, server) = stand_in_session_server("204 No Content", "");
+
+        assert!(has_joined(&url, "Notch", "abc").unwrap().is_none());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn has_joined_gives_up_on_a_silent_session_server() {
+        // accepts the connection, but never says anything
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || listener.accept().unwrap());
+
+        let agent = ureq::AgentBuilder::new().timeout_read(Duration::from_millis(100)).build();
+        assert!(has_joined_with(&agent, &url, "Notch", "abc").is_err());
+        handle.join().unwrap();
+    }
+}
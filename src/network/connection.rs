@@ -0,0 +1,777 @@
+use crate::protocol::{
+    error::ProtocolError,
+    client::{
+        ClientHandshakePacket, ClientLoginPacket, ClientPlayPacket, ClientStatusPacket, EncryptionResponse, Handshake,
+        HandshakeState, LoginPluginResponse, LoginStart, PlayerPublicKey, Verification,
+    },
+    packet_handler::{EncryptionKey, PacketHandler},
+    server::{
+        Disconnect, EncryptionRequest, KeepAlive, LoginPluginRequest, LoginSuccess, Pong, Property, Response,
+        ServerLoginPacket, ServerPlayPacket, ServerStatusPacket, SetCompression,
+    },
+    structs::{ProtocolVersion, VarInt, Writeable},
+};
+use crate::network::{
+    auth::{self, GameProfile, JoinCheck},
+    forwarding::{self, ForwardedPlayer, ForwardingMode},
+    status, ServerState,
+};
+use crate::util::{log, Identifier};
+use crate::world::blocks;
+use rand::RngCore;
+use std::{
+    convert::TryFrom,
+    net::SocketAddr,
+    sync::{atomic::Ordering, mpsc::Receiver, Arc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How many bytes a client's packets can decompress to each second before it's dropped. Frames are small enough
+/// that one can't do much harm, but a steady stream of them that each inflate a thousandfold could
+const MAX_DECOMPRESSED_PER_SECOND: usize = 32 * 1024 * 1024;
+
+/// How often a player in the play state is sent Keep Alive. Clients give up on a server after 30 seconds of silence
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The protocol state a connection is in, which decides how packet IDs are interpreted
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Handshaking,
+    Status,
+    Login,
+    Play,
+}
+
+/// Adds up an amount over one second windows, to tell when a client goes past a rate
+struct RateCounter {
+    max: usize,
+    window_start: Instant,
+    count: usize,
+}
+
+impl RateCounter {
+    fn new(max: usize) -> Self {
+        RateCounter {
+            max,
+            window_start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    /// Counts `amount` as happening at `now`, returning whether the window it's in has gone over the max
+    fn add(&mut self, amount: usize, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count = self.count.saturating_add(amount);
+        self.count > self.max
+    }
+}
+
+/// A single client connection, driven by the bytes its socket receives.
+///
+/// The connection doesn't own the socket: received bytes are passed to `receive`, and anything
+/// it wants to send is collected until `take_outgoing` is called.
+pub struct Connection {
+    address: SocketAddr,
+    server: Arc<ServerState>,
+    state: ConnectionState,
+    handler: PacketHandler,
+    /// The name sent in Login Start, before it's been verified
+    login_name: Option<String>,
+    /// The chat signing key a 1.19 to 1.19.2 client sent in Login Start, which it may sign the verify token with
+    login_key: Option<PlayerPublicKey<'static>>,
+    /// The token sent in Encryption Request, while we wait for the client's response
+    verify_token: Option<[u8; 4]>,
+    /// The player info BungeeCord forwarded in the handshake
+    forwarded: Option<ForwardedPlayer>,
+    /// The ID of the Velocity player info request, while we wait for the proxy's response
+    velocity_message_id: Option<i32>,
+    /// Who the player is, once they've logged in
+    profile: Option<GameProfile>,
+    /// What the world thread sends this player, once they've joined it
+    world_packets: Option<Receiver<ServerPlayPacket<'static>>>,
+    /// When Keep Alive was last sent, or when the player got into the play state
+    last_keep_alive: Instant,
+    /// The session server check that has to be made before the login can go on, until it's taken to be made
+    join_check: Option<JoinCheck>,
+    /// Set from asking for the session server check until its answer comes back, while nothing more is read
+    authenticating: bool,
+
+    /// How much the client's packets have decompressed to lately
+    decompressed: RateCounter,
+
+    /// Set once the first bytes have been checked for a legacy server list ping
+    received_any: bool,
+    outgoing: Vec<u8>,
+    closed: bool,
+}
+
+impl Connection {
+    pub fn new(address: SocketAddr, server: Arc<ServerState>) -> Self {
+        Connection {
+            address,
+            server,
+            state: ConnectionState::Handshaking,
+            handler: PacketHandler::new(),
+            login_name: None,
+            login_key: None,
+            verify_token: None,
+            forwarded: None,
+            velocity_message_id: None,
+            profile: None,
+            world_packets: None,
+            last_keep_alive: Instant::now(),
+            join_check: None,
+            authenticating: false,
+            decompressed: RateCounter::new(MAX_DECOMPRESSED_PER_SECOND),
+            received_any: false,
+            outgoing: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// Whether the connection is finished and its socket should be closed once the outgoing bytes are flushed
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Takes every byte queued to be sent to the client
+    pub fn take_outgoing(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// Handles bytes read from the socket, processing every packet that is now complete
+    pub fn receive(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        if !self.received_any && !bytes.is_empty() {
+            self.received_any = true;
+            if status::is_legacy_ping(bytes) {
+                self.handle_legacy_ping(bytes);
+                return Ok(());
+            }
+        }
+
+        self.handler.feed(bytes);
+        self.process_frames()
+    }
+
+    /// Takes the session server check the login is waiting on, if there is one. It blocks, so it's left to whoever
+    /// drives the connection to make it off their task, and to hand the answer to `finish_join_check`
+    pub fn take_join_check(&mut self) -> Option<JoinCheck> {
+        self.join_check.take()
+    }
+
+    /// Carries on with the login now the session server has answered, then with anything that arrived meanwhile
+    pub fn finish_join_check(&mut self, result: anyhow::Result<Option<GameProfile>>) -> anyhow::Result<()> {
+        self.authenticating = false;
+        let name = self.login_name.clone().unwrap_or_default();
+        match result {
+            Ok(Some(profile)) => self.finish_login(profile)?,
+            Ok(None) => return self.disconnect("Failed to verify username!"),
+            Err(e) => {
+                log::warn_net(&format!("Unable to authenticate {}: {:#}", name, e));
+                return self.disconnect("Authentication servers are down. Please try again later, sorry!");
+            }
+        }
+        self.process_frames()
+    }
+
+    /// Handles every complete frame that has been fed, unless the login is waiting on the session server
+    fn process_frames(&mut self) -> anyhow::Result<()> {
+        while !self.closed && !self.authenticating {
+            let frame = match self.handler.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => return self.reject(e),
+            };
+            if self.decompressed.add(self.handler.take_decompressed(), Instant::now()) {
+                return self.reject(ProtocolError::DecompressionRate { max: MAX_DECOMPRESSED_PER_SECOND });
+            }
+
+            // the packet type depends on the state, which can change between two packets of the same read. Packets
+            // borrow from their frame, which leaves the connection free to answer them
+            let handled = match self.state {
+                ConnectionState::Handshaking => match frame.read() {
+                    Ok(ClientHandshakePacket::Handshake(handshake)) => self.handle_handshake(handshake),
+                    Err(e) => return self.reject(e),
+                },
+                ConnectionState::Status => match frame.read() {
+                    Ok(packet) => self.handle_status(packet),
+                    Err(e) => return self.reject(e),
+                },
+                ConnectionState::Login => match frame.read() {
+                    Ok(packet) => self.handle_login(packet),
+                    Err(e) => return self.reject(e),
+                },
+                ConnectionState::Play => match frame.read() {
+                    Ok(packet) => self.handle_play(packet),
+                    // most of what a client sends in play isn't handled yet, which isn't the client's fault
+                    Err(ProtocolError::UnknownPacketId { id, .. }) => {
+                        log::debug_net(&format!("Ignoring play packet 0x{:02X} from {}", id, self.address));
+                        Ok(())
+                    }
+                    Err(e) => return self.reject(e),
+                },
+            };
+            handled?;
+        }
+
+        Ok(())
+    }
+
+    fn send<T: Writeable>(&mut self, packet: T) -> anyhow::Result<()> {
+        self.handler.write_packet(&packet, &mut self.outgoing)
+    }
+
+    fn handle_handshake(&mut self, handshake: Handshake) -> anyhow::Result<()> {
+        self.state = match handshake.next_state {
+            HandshakeState::Status => ConnectionState::Status,
+            HandshakeState::Login => ConnectionState::Login,
+        };
+
+        // status packets are the same on every version, so a client we can't speak to still gets listed, just as
+        // incompatible. Logging in is where that ends, before its Login Start has to be made sense of
+        let client_protocol = handshake.protocol_version.0;
+        match ProtocolVersion::from_protocol_id(client_protocol) {
+            // chunks are renumbered from the block report for each version in data/, and can't be sent to anything
+            // newer than the last of those, so those clients can't play yet
+            Some(version) if self.state == ConnectionState::Login && !blocks::has_registry_for(version) => {
+                self.handler.set_version(version);
+                let supported: Vec<&str> = blocks::registry_versions().map(|version| version.name()).collect();
+                return self.disconnect(&format!("Unsupported version! Please play on {}", supported.join(" or ")));
+            }
+            Some(version) => self.handler.set_version(version),
+            None if self.state == ConnectionState::Login => {
+                let message = if client_protocol < ProtocolVersion::OLDEST.protocol_id() {
+                    format!("Outdated client! Please use {} or newer", ProtocolVersion::OLDEST.name())
+                } else {
+                    format!("Outdated server! I'm still on {}", ProtocolVersion::LATEST.name())
+                };
+                return self.disconnect(&message);
+            }
+            None => {}
+        }
+
+        // BungeeCord only forwards player info when logging in
+        if self.state == ConnectionState::Login && matches!(self.server.auth.forwarding, ForwardingMode::BungeeCord) {
+            match forwarding::parse_bungeecord(&handshake.server_address) {
+                Ok((_, player)) => self.forwarded = Some(player),
+                Err(e) => {
+                    log::debug_net(&format!("Rejecting unforwarded login from {}: {:#}", self.address, e));
+                    return self.disconnect(
+                        "If you wish to use IP forwarding, please enable it in your BungeeCord config as well!",
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_status(&mut self, packet: ClientStatusPacket) -> anyhow::Result<()> {
+        match packet {
+            ClientStatusPacket::Request(_) => {
+                let json = status::response_json(&self.server.status, self.online_players(), self.handler.version());
+                self.send(ServerStatusPacket::from(Response { json }))
+            }
+            ClientStatusPacket::Ping(ping) => {
+                self.send(ServerStatusPacket::from(Pong { payload: ping.payload }))?;
+                // the client is done with us after its ping is answered
+                self.closed = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Answers a server list ping from a pre-netty client, which can't be framed like a modern packet
+    fn handle_legacy_ping(&mut self, request: &[u8]) {
+        log::debug_net(&format!("Legacy server list ping from {}", self.address));
+
+        let response = status::legacy_response(&self.server.status, self.online_players(), ProtocolVersion::LATEST, request);
+        self.outgoing.extend_from_slice(&response);
+        self.closed = true;
+    }
+
+    fn online_players(&self) -> usize {
+        self.server.online_players.load(Ordering::Relaxed)
+    }
+
+    fn handle_login(&mut self, packet: ClientLoginPacket) -> anyhow::Result<()> {
+        match packet {
+            ClientLoginPacket::LoginStart(login_start) => self.handle_login_start(login_start),
+            ClientLoginPacket::EncryptionResponse(response) => self.handle_encryption_response(response),
+            ClientLoginPacket::LoginPluginResponse(response) => self.handle_login_plugin_response(response),
+        }
+    }
+
+    fn handle_login_start(&mut self, login_start: LoginStart) -> anyhow::Result<()> {
+        if self.login_name.is_some() {
+            return self.disconnect("Already logging in");
+        }
+
+        self.login_name = Some(login_start.name.clone());
+        self.login_key = login_start.public_key.map(PlayerPublicKey::into_owned);
+
+        if let Some(player) = self.forwarded.take() {
+            return self.finish_forwarded_login(player);
+        }
+
+        if let ForwardingMode::Velocity { .. } = self.server.auth.forwarding {
+            let message_id = rand::thread_rng().next_u32() as i32;
+            self.velocity_message_id = Some(message_id);
+
+            return self.send(ServerLoginPacket::from(LoginPluginRequest {
+                message_id: VarInt(message_id),
+                channel: Identifier::try_from(forwarding::VELOCITY_CHANNEL.to_owned())?,
+                data: vec![forwarding::VELOCITY_VERSION].into(),
+            }));
+        }
+
+        let public_key = match &self.server.key {
+            Some(key) => key.public_der().to_vec(),
+            None => {
+                return self.finish_login(GameProfile {
+                    id: auth::offline_uuid(&login_start.name),
+                    name: login_start.name,
+                    properties: Vec::new(),
+                })
+            }
+        };
+
+        let mut verify_token = [0u8; 4];
+        rand::thread_rng().fill_bytes(&mut verify_token);
+        self.verify_token = Some(verify_token);
+
+        self.send(ServerLoginPacket::from(EncryptionRequest {
+            server_id: String::new(),
+            public_key: public_key.into(),
+            verify_token: verify_token.to_vec().into(),
+        }))
+    }
+
+    fn handle_encryption_response(&mut self, response: EncryptionResponse) -> anyhow::Result<()> {
+        // held on to separately, so the key can be used while the connection is kicking the client
+        let server = Arc::clone(&self.server);
+        let (key, expected_token) = match (&server.key, self.verify_token.take()) {
+            (Some(key), Some(token)) => (key, token),
+            _ => return self.disconnect("Unexpected encryption response"),
+        };
+
+        let verified = match &response.verification {
+            Verification::Token(token) => match key.decrypt("verify token", &token.0) {
+                Ok(token) => token == expected_token,
+                Err(e) => return self.reject(e),
+            },
+            Verification::Signature { salt, signature } => match &self.login_key {
+                Some(player_key) => match auth::verify_signed_token(&player_key.key.0, &expected_token, *salt, &signature.0)
+                {
+                    Ok(verified) => verified,
+                    Err(e) => {
+                        log::debug_net(&format!("{} sent an unusable verify token signature: {:#}", self.address, e));
+                        false
+                    }
+                },
+                None => false,
+            },
+        };
+        if !verified {
+            return self.disconnect("Invalid verify token");
+        }
+
+        let shared_secret = match key.decrypt("shared secret", &response.shared_secret.0) {
+            Ok(secret) => secret,
+            Err(e) => return self.reject(e),
+        };
+        let shared_secret = match EncryptionKey::try_from(&shared_secret[..]) {
+            Ok(secret) => secret,
+            Err(_) => return self.disconnect("Invalid shared secret"),
+        };
+        let server_hash = auth::server_hash("", &shared_secret, key.public_der());
+
+        // the client encrypts everything after its response, including how we answer it
+        self.handler.enable_encryption(shared_secret);
+
+        self.join_check = Some(JoinCheck {
+            session_server: server.auth.session_server.clone(),
+            username: self.login_name.clone().unwrap_or_default(),
+            server_hash,
+        });
+        self.authenticating = true;
+        Ok(())
+    }
+
+    fn handle_login_plugin_response(&mut self, response: LoginPluginResponse) -> anyhow::Result<()> {
+        let secret = match &self.server.auth.forwarding {
+            ForwardingMode::Velocity { secret } if self.velocity_message_id == Some(response.message_id.0) => secret,
+            _ => return self.disconnect("Unexpected login plugin response"),
+        };
+        self.velocity_message_id = None;
+
+        let data = match &response.data {
+            Some(data) => &data.0,
+            // a vanilla client that didn't go through the proxy doesn't know the channel
+            None => return self.disconnect("This server requires you to connect with Velocity."),
+        };
+
+        match forwarding::parse_velocity(secret, data) {
+            Ok(player) => self.finish_forwarded_login(player),
+            Err(e) => {
+                log::warn_net(&format!("Invalid Velocity forwarding from {}: {:#}", self.address, e));
+                self.disconnect("Unable to verify player details")
+            }
+        }
+    }
+
+    /// Logs in a player a proxy has already authenticated
+    fn finish_forwarded_login(&mut self, player: ForwardedPlayer) -> anyhow::Result<()> {
+        self.address = SocketAddr::new(player.address, self.address.port());
+
+        let name = player.name.or_else(|| self.login_name.clone()).unwrap_or_default();
+        self.finish_login(GameProfile {
+            id: player.id,
+            name,
+            properties: player.properties,
+        })
+    }
+
+    /// Turns on compression if it's configured, then sends Login Success and moves on to the play state
+    fn finish_login(&mut self, profile: GameProfile) -> anyhow::Result<()> {
+        log::info_net(&format!("{} ({}) logged in from {}", profile.name, profile.id, self.address));
+
+        if let Some(threshold) = self.server.compression_threshold {
+            // Set Compression itself goes out uncompressed, and the client compresses everything it sends once it
+            // has read it, so any frame after this one is compressed
+            self.send(ServerLoginPacket::from(SetCompression {
+                threshold: VarInt(threshold as i32),
+            }))?;
+            self.handler.enable_compression(threshold);
+        }
+
+        let properties: Vec<Property> = profile
+            .properties
+            .iter()
+            .map(|property| Property {
+                name: property.name.clone(),
+                value: property.value.clone(),
+                signature: property.signature.clone(),
+            })
+            .collect();
+        self.send(ServerLoginPacket::from(LoginSuccess {
+            uuid: profile.id,
+            username: profile.name.clone(),
+            properties: properties.into(),
+        }))?;
+        self.profile = Some(profile);
+        self.state = ConnectionState::Play;
+        self.server.online_players.fetch_add(1, Ordering::Relaxed);
+
+        self.last_keep_alive = Instant::now();
+        if let (Some(world), Some(profile)) = (&self.server.world, &self.profile) {
+            self.world_packets = Some(world.join(profile.id, self.handler.version()));
+        }
+        Ok(())
+    }
+
+    /// Sends whatever the world has sent this player since the last tick, and a keep alive every so often
+    pub fn tick(&mut self) -> anyhow::Result<()> {
+        if self.state != ConnectionState::Play || self.closed {
+            return Ok(());
+        }
+        if self.last_keep_alive.elapsed() >= KEEP_ALIVE_INTERVAL {
+            self.last_keep_alive = Instant::now();
+            // vanilla uses the time as the ID, which the client only echoes back
+            let id = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as i64);
+            self.send(ServerPlayPacket::from(KeepAlive { id }))?;
+        }
+
+        let packets: Vec<ServerPlayPacket> = match &self.world_packets {
+            Some(packets) => packets.try_iter().collect(),
+            None => Vec::new(),
+        };
+        self.send_all(packets)
+    }
+
+    fn handle_play(&mut self, packet: ClientPlayPacket) -> anyhow::Result<()> {
+        match packet {
+            ClientPlayPacket::SetPlayerPosition(position) => self.move_to(position.x, position.z),
+            ClientPlayPacket::SetPlayerPositionAndRotation(position) => self.move_to(position.x, position.z),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the player's view to the chunk the given block coords are in. What they can now see is sent as the
+    /// world gets to it
+    fn move_to(&mut self, x: f64, z: f64) -> anyhow::Result<()> {
+        if let (Some(world), Some(profile), Some(_)) = (&self.server.world, &self.profile, &self.world_packets) {
+            world.move_player(profile.id, (x.floor() as i32) >> 4, (z.floor() as i32) >> 4);
+        }
+        Ok(())
+    }
+
+    fn send_all(&mut self, packets: Vec<ServerPlayPacket>) -> anyhow::Result<()> {
+        for packet in packets {
+            self.send(packet)?;
+        }
+        Ok(())
+    }
+
+    /// Drops a client that broke the protocol, telling it why if it's logging in and can still be told
+    fn reject(&mut self, error: ProtocolError) -> anyhow::Result<()> {
+        match self.state {
+            ConnectionState::Login => self.disconnect(&format!("Invalid packet: {}", error)),
+            _ => {
+                log::debug_net(&format!("Dropping {}, which sent an invalid packet: {}", self.address, error));
+                self.closed = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Kicks the client during login with the given message
+    fn disconnect(&mut self, message: &str) -> anyhow::Result<()> {
+        log::info_net(&format!("Disconnecting {}: {}", self.address, message));
+
+        let reason = serde_json::json!({ "text": message }).to_string();
+        self.send(ServerLoginPacket::from(Disconnect { reason }))?;
+        self.closed = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.state == ConnectionState::Play {
+            self.server.online_players.fetch_sub(1, Ordering::Relaxed);
+        }
+        if let (Some(world), Some(profile), Some(_)) = (&self.server.world, &self.profile, &self.world_packets) {
+            world.leave(profile.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{auth::AuthSettings, status::StatusSettings};
+    use crate::protocol::client::SetPlayerPosition;
+    use crate::world::{generation::ChunkGenerator, thread::{WorldThread, OVERWORLD}, world::{Chunk, Dimension}};
+    use uuid::Uuid;
+
+    fn offline_server() -> Arc<ServerState> {
+        let auth = AuthSettings {
+            online_mode: false,
+            ..Default::default()
+        };
+        Arc::new(ServerState::new(StatusSettings::default(), auth, None).unwrap())
+    }
+
+    fn connection() -> Connection {
+        Connection::new("127.0.0.1:25565".parse().unwrap(), offline_server())
+    }
+
+    #[test]
+    fn nothing_is_read_while_the_session_server_is_asked() {
+        let mut connection = connection();
+        connection.state = ConnectionState::Login;
+        connection.login_name = Some("Notch".to_owned());
+        connection.join_check = Some(JoinCheck {
+            session_server: auth::MOJANG_SESSION_SERVER.to_owned(),
+            username: "Notch".to_owned(),
+            server_hash: "-1f".to_owned(),
+        });
+        connection.authenticating = true;
+
+        // a second Login Start, which would get a kick if it were read now
+        let mut bytes = Vec::new();
+        let login_start = LoginStart { name: "Notch".to_owned(), public_key: None, uuid: None };
+        PacketHandler::new().write_packet(&ClientLoginPacket::from(login_start), &mut bytes).unwrap();
+        connection.receive(&bytes).unwrap();
+        assert!(connection.take_outgoing().is_empty());
+        assert!(connection.take_join_check().is_some());
+
+        let profile = GameProfile { id: Uuid::from_u128(1), name: "Notch".to_owned(), properties: Vec::new() };
+        connection.finish_join_check(Ok(Some(profile))).unwrap();
+        assert_eq!(connection.state, ConnectionState::Play);
+        assert!(!connection.is_closed());
+
+        let mut connection = self::connection();
+        connection.state = ConnectionState::Login;
+        connection.authenticating = true;
+        connection.finish_join_check(Ok(None)).unwrap();
+        assert!(connection.is_closed());
+    }
+
+    #[test]
+    fn malformed_player_keys_are_kicked() {
+        let server = Arc::new(ServerState::new(StatusSettings::default(), AuthSettings::default(), None).unwrap());
+        let mut connection = Connection::new("127.0.0.1:25565".parse().unwrap(), server);
+        connection.state = ConnectionState::Login;
+        connection.verify_token = Some([1, 2, 3, 4]);
+        connection.login_key = Some(PlayerPublicKey {
+            expires_at: 0,
+            key: vec![0x30, 0x00].into(),
+            signature: Vec::new().into(),
+        });
+
+        let response = EncryptionResponse {
+            shared_secret: Vec::new().into(),
+            verification: Verification::Signature { salt: 0, signature: vec![0; 4].into() },
+        };
+        connection.handle_encryption_response(response).unwrap();
+        assert!(connection.is_closed());
+        assert!(!connection.take_outgoing().is_empty());
+    }
+
+    #[test]
+    fn only_legacy_pings_get_a_legacy_response() {
+        let mut connection = connection();
+        connection.receive(&[status::LEGACY_PING, 0x01]).unwrap();
+        assert_eq!(connection.take_outgoing()[0], 0xFF);
+        assert!(connection.is_closed());
+
+        // a handshake 254 bytes long, whose length starts with 0xFE too
+        let handshake = Handshake {
+            protocol_version: VarInt(ProtocolVersion::V1_18_2.protocol_id()),
+            server_address: "a".repeat(246),
+            server_port: 25565,
+            next_state: HandshakeState::Login,
+        };
+        let mut client = PacketHandler::new();
+        client.set_version(ProtocolVersion::V1_18_2);
+        let mut bytes = Vec::new();
+        client.write_packet(&ClientHandshakePacket::from(handshake), &mut bytes).unwrap();
+        assert_eq!(bytes[..2], [status::LEGACY_PING, 0x01]);
+
+        let mut connection = self::connection();
+        connection.receive(&bytes).unwrap();
+        assert_eq!(connection.state, ConnectionState::Login);
+        assert!(!connection.is_closed());
+    }
+
+    #[test]
+    fn set_compression_is_the_last_uncompressed_frame() {
+        let auth = AuthSettings {
+            online_mode: false,
+            ..Default::default()
+        };
+        let server = Arc::new(ServerState::new(StatusSettings::default(), auth, Some(256)).unwrap());
+        let mut connection = Connection::new("127.0.0.1:25565".parse().unwrap(), server);
+
+        let mut client = PacketHandler::new();
+        client.set_version(ProtocolVersion::V1_18_2);
+        let mut bytes = Vec::new();
+        let handshake = Handshake {
+            protocol_version: VarInt(ProtocolVersion::V1_18_2.protocol_id()),
+            server_address: "localhost".to_owned(),
+            server_port: 25565,
+            next_state: HandshakeState::Login,
+        };
+        client.write_packet(&ClientHandshakePacket::from(handshake), &mut bytes).unwrap();
+        let login_start = LoginStart { name: "Notch".to_owned(), public_key: None, uuid: None };
+        client.write_packet(&ClientLoginPacket::from(login_start), &mut bytes).unwrap();
+        connection.receive(&bytes).unwrap();
+        assert_eq!(connection.state, ConnectionState::Play);
+
+        let outgoing = connection.take_outgoing();
+        let mut stream = std::io::Cursor::new(&outgoing[..]);
+        let next = |stream: &mut std::io::Cursor<&[u8]>| VarInt::read_from(stream).unwrap().0;
+
+        // Set Compression is framed as length, id, threshold
+        assert_eq!(next(&mut stream), 3);
+        assert_eq!(next(&mut stream), 0x03);
+        assert_eq!(next(&mut stream), 256);
+
+        // Login Success has the data length between the two, which is 0 since it's under the threshold
+        let len = next(&mut stream) as u64;
+        let start = stream.position();
+        assert_eq!(next(&mut stream), 0);
+        assert_eq!(next(&mut stream), 0x02);
+        assert_eq!(start + len, outgoing.len() as u64);
+    }
+
+    /// Ticks the connection until the world centres its view on the given chunk, failing if it takes too long
+    fn wait_for_view(connection: &mut Connection, center: (i32, i32)) {
+        let mut handler = PacketHandler::new();
+        handler.set_version(connection.handler.version());
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(10), "the view never moved to {:?}", center);
+            connection.tick().unwrap();
+            handler.feed(&connection.take_outgoing());
+            while let Some(frame) = handler.next_frame().unwrap() {
+                if let Ok(ServerPlayPacket::UpdateViewPosition(view)) = frame.read() {
+                    if (view.chunk_x.0, view.chunk_z.0) == center {
+                        return;
+                    }
+                }
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn moving_moves_the_players_view() {
+        let mut server = ServerState::new(StatusSettings::default(), AuthSettings::default(), None).unwrap();
+        let overworld = Dimension::new(None, ChunkGenerator::new(Chunk::new, 1), 2, 2);
+        server.world = Some(WorldThread::start(vec![(OVERWORLD, overworld)]));
+        let server = Arc::new(server);
+        let mut connection = Connection::new("127.0.0.1:25565".parse().unwrap(), Arc::clone(&server));
+        connection.handler.set_version(ProtocolVersion::V1_18_2);
+        connection.state = ConnectionState::Login;
+        connection.authenticating = true;
+
+        let id = Uuid::from_u128(1);
+        let profile = GameProfile { id, name: "Notch".to_owned(), properties: Vec::new() };
+        connection.finish_join_check(Ok(Some(profile))).unwrap();
+        connection.take_outgoing();
+        wait_for_view(&mut connection, (0, 0));
+
+        let mut bytes = Vec::new();
+        let position = SetPlayerPosition { x: 40.5, y: 64.0, z: -0.5, on_ground: true };
+        let mut handler = PacketHandler::new();
+        handler.set_version(ProtocolVersion::V1_18_2);
+        handler.write_packet(&ClientPlayPacket::from(position), &mut bytes).unwrap();
+        connection.receive(&bytes).unwrap();
+        wait_for_view(&mut connection, (2, -1));
+
+        drop(connection);
+        let server = Arc::try_unwrap(server).ok().unwrap();
+        let dimensions = server.world.unwrap().stop().unwrap();
+        assert_eq!(dimensions[0].1.tracker().viewer(id), None);
+    }
+
+    #[test]
+    fn players_are_kept_alive() {
+        let mut connection = connection();
+        connection.state = ConnectionState::Play;
+        connection.last_keep_alive = Instant::now();
+        connection.tick().unwrap();
+        assert!(connection.take_outgoing().is_empty());
+
+        connection.last_keep_alive -= KEEP_ALIVE_INTERVAL;
+        connection.tick().unwrap();
+        let mut handler = PacketHandler::new();
+        handler.feed(&connection.take_outgoing());
+        let frame = handler.next_frame().unwrap().unwrap();
+        assert!(matches!(frame.read(), Ok(ServerPlayPacket::KeepAlive(_))));
+    }
+
+    #[test]
+    fn rate_counter_starts_over_each_second() {
+        let start = Instant::now();
+        let mut counter = RateCounter {
+            max: 100,
+            window_start: start,
+            count: 0,
+        };
+        assert!(!counter.add(60, start));
+        assert!(!counter.add(40, start + Duration::from_millis(500)));
+        assert!(counter.add(1, start + Duration::from_millis(999)));
+
+        // a new window forgets everything before it
+        assert!(!counter.add(100, start + Duration::from_secs(1)));
+        assert!(counter.add(usize::MAX, start + Duration::from_secs(1)));
+    }
+}
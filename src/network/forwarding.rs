@@ -0,0 +1,161 @@
+use crate::network::auth::ProfileProperty;
+use crate::protocol::structs::{ProtocolVersion, Readable, VarInt};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{io::Cursor, net::IpAddr};
+use uuid::Uuid;
+
+/// The login plugin channel Velocity answers with the forwarded player info
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+
+/// The Velocity forwarding version we ask for. Later versions only add 1.19 chat signing keys.
+pub const VELOCITY_VERSION: u8 = 1;
+
+/// The length of the HMAC-SHA256 signature in front of Velocity's forwarded data
+const SIGNATURE_LEN: usize = 32;
+
+/// How a proxy in front of the server tells us who is connecting
+#[derive(Clone)]
+pub enum ForwardingMode {
+    /// Players connect directly, or through a proxy that doesn't forward anything
+    None,
+    /// BungeeCord's legacy forwarding, packed into the handshake's server address
+    BungeeCord,
+    /// Velocity's modern forwarding over a login plugin message, signed with a shared secret
+    Velocity { secret: String },
+}
+
+/// The player a proxy logged in, as it was forwarded to us
+pub struct ForwardedPlayer {
+    /// The player's real address, rather than the proxy's
+    pub address: IpAddr,
+    pub id: Uuid,
+    /// Only Velocity forwards the name, BungeeCord players go by the name they log in with
+    pub name: Option<String>,
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// Splits BungeeCord's forwarded info out of a handshake's server address.
+///
+/// BungeeCord sends `host\0address\0uuid` with an optional `\0properties` JSON array, and returns the host
+/// the client actually connected to along with the player.
+pub fn parse_bungeecord(server_address: &str) -> anyhow::Result<(String, ForwardedPlayer)> {
+    let mut parts = server_address.split('\0');
+    let host = parts.next().unwrap_or_default().to_owned();
+
+    let address = parts
+        .next()
+        .context("no forwarded address")?
+        .parse()
+        .context("invalid forwarded address")?;
+    let id = Uuid::parse_str(parts.next().context("no forwarded UUID")?).context("invalid forwarded UUID")?;
+    let properties = match parts.next() {
+        Some(json) => serde_json::from_str(json).context("invalid forwarded properties")?,
+        None => Vec::new(),
+    };
+
+    Ok((
+        host,
+        ForwardedPlayer {
+            address,
+            id,
+            name: None,
+            properties,
+        },
+    ))
+}
+
+/// Verifies and reads the data Velocity sent back on the player info channel
+pub fn parse_velocity(secret: &str, data: &[u8]) -> anyhow::Result<ForwardedPlayer> {
+    if data.len() < SIGNATURE_LEN {
+        anyhow::bail!("forwarded data is too short to be signed");
+    }
+    let (signature, data) = data.split_at(SIGNATURE_LEN);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.verify_slice(signature)
+        .map_err(|_| anyhow::anyhow!("forwarded data has an invalid signature"))?;
+
+    let mut cursor = Cursor::new(data);
+    let version = VarInt::read(&mut cursor, ProtocolVersion::LATEST)?.0;
+    if version != VELOCITY_VERSION as i32 {
+        anyhow::bail!("unsupported forwarding version {}", version);
+    }
+
+    let address = String::read(&mut cursor, ProtocolVersion::LATEST)?
+        .parse()
+        .context("invalid forwarded address")?;
+    let id = Uuid::read(&mut cursor, ProtocolVersion::LATEST)?;
+    let name = String::read(&mut cursor, ProtocolVersion::LATEST)?;
+
+    let property_count = VarInt::read(&mut cursor, ProtocolVersion::LATEST)?.0;
+    let mut properties = Vec::new();
+    for _ in 0..property_count {
+        properties.push(ProfileProperty {
+            name: String::read(&mut cursor, ProtocolVersion::LATEST)?,
+            value: String::read(&mut cursor, ProtocolVersion::LATEST)?,
+            signature: Option::read(&mut cursor, ProtocolVersion::LATEST)?,
+        });
+    }
+
+    Ok(ForwardedPlayer {
+        address,
+        id,
+        name: Some(name),
+        properties,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::structs::Writeable;
+
+    #[test]
+    fn bungeecord_address_is_split() {
+        let (host, player) = parse_bungeecord(
+            "play.example.com\u{0}203.0.113.7\u{0}069a79f444e94726a5befca90e38aaf5\u{0}[{\"name\":\"textures\",\"value\":\"e30=\"}]",
+        )
+        .unwrap();
+
+        assert_eq!(host, "play.example.com");
+        assert_eq!(player.address, "203.0.113.7".parse::<IpAddr>().unwrap());
+        assert_eq!(player.id.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(player.properties[0].value, "e30=");
+        assert!(parse_bungeecord("play.example.com").is_err());
+    }
+
+    fn velocity_data(secret: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        VarInt(VELOCITY_VERSION as i32).write(&mut data, ProtocolVersion::LATEST).unwrap();
+        "2001:db8::1".to_owned().write(&mut data, ProtocolVersion::LATEST).unwrap();
+        Uuid::from_u128(7).write(&mut data, ProtocolVersion::LATEST).unwrap();
+        "Notch".to_owned().write(&mut data, ProtocolVersion::LATEST).unwrap();
+        VarInt(1).write(&mut data, ProtocolVersion::LATEST).unwrap();
+        "textures".to_owned().write(&mut data, ProtocolVersion::LATEST).unwrap();
+        "e30=".to_owned().write(&mut data, ProtocolVersion::LATEST).unwrap();
+        Some("c2ln".to_owned()).write(&mut data, ProtocolVersion::LATEST).unwrap();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(&data);
+        [&mac.finalize().into_bytes()[..], &data].concat()
+    }
+
+    #[test]
+    fn velocity_data_is_verified_and_read() {
+        let player = parse_velocity("hunter2", &velocity_data("hunter2")).unwrap();
+
+        assert_eq!(player.address, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(player.id, Uuid::from_u128(7));
+        assert_eq!(player.name.as_deref(), Some("Notch"));
+        assert_eq!(player.properties[0].signature.as_deref(), Some("c2ln"));
+    }
+
+    #[test]
+    fn velocity_data_with_wrong_secret_is_rejected() {
+        assert!(parse_velocity("hunter2", &velocity_data("letmein")).is_err());
+        assert!(parse_velocity("hunter2", &[0; 8]).is_err());
+    }
+}
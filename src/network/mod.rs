@@ -0,0 +1,166 @@
+use crate::protocol::packet_handler::CompressionThreshold;
+use crate::util::log;
+use crate::world::thread::WorldThread;
+use anyhow::Context;
+use std::{
+    net::SocketAddr,
+    sync::{atomic::AtomicUsize, Arc},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+    sync::mpsc,
+    task, time,
+};
+
+pub mod auth;
+pub mod connection;
+pub mod forwarding;
+pub mod status;
+
+use auth::{AuthSettings, ServerKey};
+use connection::Connection;
+use forwarding::ForwardingMode;
+use status::StatusSettings;
+
+/// How many batches of packets can be waiting to be written to a connection before whoever is queueing them
+/// has to wait for the client to catch up
+const OUTGOING_QUEUE_LEN: usize = 64;
+
+/// How long a single write can take before the client is considered too slow to keep and gets dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often each connection picks up what the world has for it, like chunks that finished generating
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// State shared between every connection
+pub struct ServerState {
+    pub status: StatusSettings,
+    pub auth: AuthSettings,
+    /// The login keypair, only generated in online mode
+    pub key: Option<ServerKey>,
+    /// Packets at least this long are compressed once a player logs in, `None` to leave compression off
+    pub compression_threshold: Option<CompressionThreshold>,
+    /// How many connections have made it into the play state
+    pub online_players: AtomicUsize,
+    /// The thread the dimensions run on, `None` to only ever get as far as logging in
+    pub world: Option<WorldThread>,
+}
+
+impl ServerState {
+    pub fn new(
+        status: StatusSettings,
+        auth: AuthSettings,
+        compression_threshold: Option<CompressionThreshold>,
+    ) -> anyhow::Result<Self> {
+        let key = if auth.online_mode && matches!(auth.forwarding, ForwardingMode::None) {
+            Some(ServerKey::generate()?)
+        } else {
+            None
+        };
+
+        Ok(ServerState {
+            status,
+            auth,
+            key,
+            compression_threshold,
+            online_players: AtomicUsize::new(0),
+            world: None,
+        })
+    }
+}
+
+/// Accepts connections on `address` forever, giving each one its own tasks.
+///
+/// Only returns if the listener can't be bound.
+pub async fn listen(address: SocketAddr, state: Arc<ServerState>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    log::info_net(&format!("Listening on {}", address));
+
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::warn_net(&format!("Unable to accept connection: {}", e));
+                continue;
+            }
+        };
+
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(e) = handle_stream(stream, address, state).await {
+                log::warn_net(&format!("Connection closed with error: {:#}", e));
+            }
+        });
+    }
+}
+
+/// Reads from a connection and feeds it to its `Connection`, while a separate writer task sends whatever it
+/// queues. The queue is bounded, so a client that stops reading only ever holds up its own reader.
+async fn handle_stream(stream: TcpStream, address: SocketAddr, state: Arc<ServerState>) -> anyhow::Result<()> {
+    let (mut reader, writer) = stream.into_split();
+    let (outgoing, queue) = mpsc::channel(OUTGOING_QUEUE_LEN);
+    let writer = tokio::spawn(write_outgoing(writer, queue));
+
+    let mut connection = Connection::new(address, state);
+    let mut buf = [0u8; 4096];
+    let mut ticks = time::interval(TICK_INTERVAL);
+    ticks.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+    let result = async {
+        while !connection.is_closed() {
+            let read = tokio::select! {
+                read = reader.read(&mut buf) => read?,
+                _ = ticks.tick() => {
+                    let result = connection.tick();
+                    let bytes = connection.take_outgoing();
+                    if !bytes.is_empty() && outgoing.send(bytes).await.is_err() {
+                        break;
+                    }
+                    result?;
+                    continue;
+                }
+                // the writer gave up on the client, so there's no point reading any more
+                _ = outgoing.closed() => break,
+            };
+            if read == 0 {
+                break;
+            }
+
+            let mut result = connection.receive(&buf[..read]);
+            if let Some(check) = connection.take_join_check() {
+                // the session server can take its time, which a worker thread shouldn't spend waiting
+                let joined = task::spawn_blocking(move || check.send())
+                    .await
+                    .unwrap_or_else(|e| Err(anyhow::anyhow!("session server check panicked: {}", e)));
+                result = result.and_then(|_| connection.finish_join_check(joined));
+            }
+            // queue whatever was written before an error, so kick messages still reach the client
+            let bytes = connection.take_outgoing();
+            if !bytes.is_empty() && outgoing.send(bytes).await.is_err() {
+                break;
+            }
+            result?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+
+    // let the writer flush what's left in the queue and close the connection
+    drop(outgoing);
+    let written = writer.await.context("writer task panicked")?;
+    result.and(written)
+}
+
+/// Writes queued bytes to the client until the queue is closed
+async fn write_outgoing(mut writer: OwnedWriteHalf, mut queue: mpsc::Receiver<Vec<u8>>) -> anyhow::Result<()> {
+    while let Some(bytes) = queue.recv().await {
+        time::timeout(WRITE_TIMEOUT, writer.write_all(&bytes))
+            .await
+            .context("client stopped reading")??;
+    }
+
+    writer.shutdown().await?;
+    Ok(())
+}
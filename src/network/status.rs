@@ -0,0 +1,147 @@
+use crate::protocol::structs::ProtocolVersion;
+use anyhow::Context;
+use serde::Serialize;
+use std::{fs, path::Path};
+
+/// The first byte of a pre-netty (1.6 and older) server list ping
+pub const LEGACY_PING: u8 = 0xFE;
+
+/// The plugin channel 1.6 clients send their ping's host and port on, right after `0xFE 0x01`
+const LEGACY_PING_CHANNEL: &str = "MC|PingHost";
+
+/// The protocol number we advertise to pre-netty clients. It's never theirs, so they list us as incompatible.
+const LEGACY_PROTOCOL: i32 = 127;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// What the server shows up as in a client's server list
+pub struct StatusSettings {
+    pub motd: String,
+    pub max_players: usize,
+    /// The favicon as a `data:` URI, ready to go into the status response
+    pub favicon: Option<String>,
+}
+
+impl Default for StatusSettings {
+    fn default() -> Self {
+        StatusSettings {
+            motd: "A Ferrous server".to_owned(),
+            max_players: 20,
+            favicon: None,
+        }
+    }
+}
+
+impl StatusSettings {
+    /// Loads a 64x64 PNG from disk and encodes it as a favicon
+    pub fn load_favicon(path: impl AsRef<Path>) -> anyhow::Result<String> {
+        let path = path.as_ref();
+        let image = fs::read(path).with_context(|| format!("unable to read favicon {}", path.display()))?;
+
+        // the IHDR chunk always comes first, with the width and height as its first 8 bytes
+        if image.len() < 24 || image[..8] != PNG_SIGNATURE {
+            anyhow::bail!("favicon {} is not a PNG", path.display());
+        }
+        let width = u32::from_be_bytes([image[16], image[17], image[18], image[19]]);
+        let height = u32::from_be_bytes([image[20], image[21], image[22], image[23]]);
+        if width != 64 || height != 64 {
+            anyhow::bail!("favicon {} must be 64x64, found {}x{}", path.display(), width, height);
+        }
+
+        Ok(format!("data:image/png;base64,{}", base64::encode(&image)))
+    }
+}
+
+#[derive(Serialize)]
+struct StatusResponse<'a> {
+    version: StatusVersion,
+    players: StatusPlayers,
+    description: StatusDescription<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    favicon: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct StatusVersion {
+    name: &'static str,
+    protocol: i32,
+}
+
+#[derive(Serialize)]
+struct StatusPlayers {
+    max: usize,
+    online: usize,
+}
+
+#[derive(Serialize)]
+struct StatusDescription<'a> {
+    text: &'a str,
+}
+
+/// Builds the JSON sent in response to a Status Request
+pub fn response_json(settings: &StatusSettings, online_players: usize, version: ProtocolVersion) -> String {
+    let response = StatusResponse {
+        version: StatusVersion {
+            name: version.name(),
+            protocol: version.protocol_id(),
+        },
+        players: StatusPlayers {
+            max: settings.max_players,
+            online: online_players,
+        },
+        description: StatusDescription { text: &settings.motd },
+        favicon: settings.favicon.as_deref(),
+    };
+
+    serde_json::to_string(&response).expect("status response is always serializable")
+}
+
+/// Whether the first bytes a client sent are a pre-netty server list ping. A modern frame can start with `0xFE` too
+/// when its length does, so like vanilla only a lone `0xFE`, `0xFE 0x01`, or `0xFE 0x01 0xFA` followed by the
+/// `MC|PingHost` plugin message counts.
+pub fn is_legacy_ping(bytes: &[u8]) -> bool {
+    match bytes {
+        [LEGACY_PING] | [LEGACY_PING, 0x01] => true,
+        [LEGACY_PING, 0x01, 0xFA, message @ ..] => {
+            let mut channel = (LEGACY_PING_CHANNEL.len() as u16).to_be_bytes().to_vec();
+            channel.extend(LEGACY_PING_CHANNEL.encode_utf16().flat_map(u16::to_be_bytes));
+            message.starts_with(&channel)
+        }
+        _ => false,
+    }
+}
+
+/// Builds the kick packet that answers a pre-netty server list ping.
+///
+/// `request` is everything the client sent: Beta 1.8 to 1.3 send a lone `0xFE`, while 1.4 and up follow
+/// it with `0x01` and expect the newer `§1` format.
+pub fn legacy_response(
+    settings: &StatusSettings,
+    online_players: usize,
+    version: ProtocolVersion,
+    request: &[u8],
+) -> Vec<u8> {
+    let message = if request.get(1) == Some(&0x01) {
+        format!(
+            "§1\0{}\0{}\0{}\0{}\0{}",
+            LEGACY_PROTOCOL,
+            version.name(),
+            settings.motd,
+            online_players,
+            settings.max_players
+        )
+    } else {
+        // these clients split on §, so it can't be in the MOTD
+        format!("{}§{}§{}", settings.motd.replace('§', ""), online_players, settings.max_players)
+    };
+
+    let chars: Vec<u16> = message.encode_utf16().collect();
+    let mut packet = Vec::with_capacity(3 + chars.len() * 2);
+    packet.push(0xFF);
+    packet.extend_from_slice(&(chars.len() as u16).to_be_bytes());
+    for c in chars {
+        packet.extend_from_slice(&c.to_be_bytes());
+    }
+
+    packet
+}
@@ -0,0 +1,50 @@
+use crate::protocol::{
+    packets::{packet_enum, Packet},
+    error::ProtocolError,
+    structs::{ProtocolVersion, Readable, VarInt, Writeable},
+};
+use std::io::Cursor;
+
+packet_enum!(ClientHandshakePacket {
+    Handshake,
+});
+
+/// The state a client asks to move to once the handshake is done
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeState {
+    Status,
+    Login,
+}
+
+impl<'de> Readable<'de> for HandshakeState {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
+    where
+        Self: Sized,
+    {
+        let state = VarInt::read(buffer, version)?.0;
+        match state {
+            1 => Ok(HandshakeState::Status),
+            2 => Ok(HandshakeState::Login),
+            _ => Err(ProtocolError::InvalidValue { what: "handshake state", value: state.to_string() }),
+        }
+    }
+}
+
+impl Writeable for HandshakeState {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        let state = match self {
+            HandshakeState::Status => 1,
+            HandshakeState::Login => 2,
+        };
+        VarInt(state).write(buffer, version)
+    }
+}
+
+#[derive(Packet)]
+#[packet(id = 0x00)]
+pub struct Handshake {
+    pub protocol_version: VarInt,
+    pub server_address: String,
+    pub server_port: u16,
+    pub next_state: HandshakeState,
+}
@@ -0,0 +1,180 @@
+use crate::protocol::{
+    packets::{packet_enum, Packet},
+    error::ProtocolError,
+    structs::{GreedyVecU8, ProtocolVersion, Readable, VarInt, VarIntPrefixedVec, Writeable},
+};
+use anyhow::bail;
+use std::io::Cursor;
+use uuid::Uuid;
+
+packet_enum!(ClientLoginPacket<'a> {
+    LoginStart<'a>,
+    EncryptionResponse<'a>,
+    LoginPluginResponse<'a>,
+});
+
+#[derive(Packet)]
+#[packet(id = 0x00)]
+pub struct LoginStart<'a> {
+    pub name: String,
+    /// The player's chat signing key, which 1.19 to 1.19.2 clients send if they have one
+    #[packet(since = V1_19, before = V1_19_3)]
+    pub public_key: Option<PlayerPublicKey<'a>>,
+    /// The UUID the client thinks it has, which it's been sending since 1.19.1
+    #[packet(since = V1_19_2)]
+    pub uuid: Option<Uuid>,
+}
+
+/// A player's chat signing key, as issued to them by Mojang
+#[derive(Clone, Packet)]
+pub struct PlayerPublicKey<'a> {
+    /// When the key stops being valid, in milliseconds since the epoch
+    pub expires_at: i64,
+    /// The RSA public key, DER encoded
+    pub key: VarIntPrefixedVec<'a, u8>,
+    /// Mojang's signature over the expiry and key
+    pub signature: VarIntPrefixedVec<'a, u8>,
+}
+
+impl PlayerPublicKey<'_> {
+    /// Copies the key out of the buffer it was read from, so it can be kept around
+    pub fn into_owned(self) -> PlayerPublicKey<'static> {
+        PlayerPublicKey {
+            expires_at: self.expires_at,
+            key: self.key.into_owned(),
+            signature: self.signature.into_owned(),
+        }
+    }
+}
+
+#[derive(Packet)]
+#[packet(id = 0x01)]
+pub struct EncryptionResponse<'a> {
+    pub shared_secret: VarIntPrefixedVec<'a, u8>,
+    pub verification: Verification<'a>,
+}
+
+/// How the client proves it could decrypt the verify token from Encryption Request
+pub enum Verification<'a> {
+    /// The verify token, encrypted with the server's public key
+    Token(VarIntPrefixedVec<'a, u8>),
+    /// The verify token and salt signed with the player's chat key, which 1.19 to 1.19.2 clients that sent one
+    /// use instead
+    Signature {
+        salt: i64,
+        signature: VarIntPrefixedVec<'a, u8>,
+    },
+}
+
+/// Whether `version` lets the client sign the verify token rather than encrypting it
+fn signs_verify_token(version: ProtocolVersion) -> bool {
+    version >= ProtocolVersion::V1_19 && version < ProtocolVersion::V1_19_3
+}
+
+impl<'de> Readable<'de> for Verification<'de> {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
+    where
+        Self: Sized,
+    {
+        if signs_verify_token(version) && !bool::read(buffer, version)? {
+            return Ok(Verification::Signature {
+                salt: i64::read(buffer, version)?,
+                signature: VarIntPrefixedVec::read(buffer, version)?,
+            });
+        }
+
+        Ok(Verification::Token(VarIntPrefixedVec::read(buffer, version)?))
+    }
+}
+
+impl<'a> Writeable for Verification<'a> {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        match self {
+            Verification::Token(token) => {
+                if signs_verify_token(version) {
+                    true.write(buffer, version)?;
+                }
+                token.write(buffer, version)
+            }
+            Verification::Signature { salt, signature } => {
+                if !signs_verify_token(version) {
+                    bail!("{} clients can't sign the verify token", version.name());
+                }
+                false.write(buffer, version)?;
+                salt.write(buffer, version)?;
+                signature.write(buffer, version)
+            }
+        }
+    }
+}
+
+#[derive(Packet)]
+#[packet(id = 0x02)]
+pub struct LoginPluginResponse<'a> {
+    pub message_id: VarInt,
+    /// The response payload, or `None` if the client didn't understand the request
+    pub data: Option<GreedyVecU8<'a>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login_start(version: ProtocolVersion) -> Vec<u8> {
+        let packet = LoginStart {
+            name: "Notch".to_owned(),
+            public_key: Some(PlayerPublicKey {
+                expires_at: 1,
+                key: vec![2].into(),
+                signature: vec![3].into(),
+            }),
+            uuid: Some(Uuid::from_u128(4)),
+        };
+        let mut buffer = Vec::new();
+        packet.write(&mut buffer, version).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn login_start_fields_depend_on_version() {
+        // the name is "Notch" behind its length
+        assert_eq!(login_start(ProtocolVersion::V1_18_2).len(), 6);
+        // a present flag, the expiry and the key and signature behind their lengths
+        assert_eq!(login_start(ProtocolVersion::V1_19).len(), 6 + 1 + 8 + 2 + 2);
+        // then a present flag and the UUID too
+        assert_eq!(login_start(ProtocolVersion::V1_19_2).len(), 6 + 13 + 17);
+        assert_eq!(login_start(ProtocolVersion::V1_20_1).len(), 6 + 17);
+
+        let buffer = login_start(ProtocolVersion::V1_19_2);
+        let read = LoginStart::read(&mut Cursor::new(&buffer[..]), ProtocolVersion::V1_19_2).unwrap();
+        assert_eq!(read.public_key.unwrap().expires_at, 1);
+        assert_eq!(read.uuid, Some(Uuid::from_u128(4)));
+
+        let read = LoginStart::read(&mut Cursor::new(&buffer[..6]), ProtocolVersion::V1_18_2).unwrap();
+        assert!(read.public_key.is_none() && read.uuid.is_none());
+    }
+
+    #[test]
+    fn verify_token_can_be_signed_on_1_19() {
+        let signed = Verification::Signature {
+            salt: 5,
+            signature: vec![6, 7].into(),
+        };
+        assert!(signed.write(&mut Vec::new(), ProtocolVersion::V1_19_3).is_err());
+
+        let mut buffer = Vec::new();
+        signed.write(&mut buffer, ProtocolVersion::V1_19).unwrap();
+        match Verification::read(&mut Cursor::new(&buffer[..]), ProtocolVersion::V1_19).unwrap() {
+            Verification::Signature { salt, signature } => assert_eq!((salt, &signature.0[..]), (5, &[6, 7][..])),
+            Verification::Token(_) => panic!("expected a signature"),
+        }
+
+        // older and newer clients only ever send the token, without a flag in front
+        let mut buffer = Vec::new();
+        Verification::Token(vec![8].into()).write(&mut buffer, ProtocolVersion::V1_18_2).unwrap();
+        assert_eq!(buffer, [1, 8]);
+        let mut buffer = Vec::new();
+        Verification::Token(vec![8].into()).write(&mut buffer, ProtocolVersion::V1_19_2).unwrap();
+        assert_eq!(buffer, [1, 1, 8]);
+    }
+}
@@ -0,0 +1,11 @@
+//! Packets sent by the client to the server.
+
+pub mod handshake;
+pub mod login;
+pub mod play;
+pub mod status;
+
+pub use handshake::*;
+pub use login::*;
+pub use play::*;
+pub use status::*;
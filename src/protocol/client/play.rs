@@ -0,0 +1,169 @@
+use crate::protocol::{
+    packets::{packet_enum, Packet},
+    structs::{GreedyVecU8, VarInt, VarIntPrefixedVec},
+};
+use uuid::Uuid;
+
+packet_enum!(ClientPlayPacket<'a> {
+    ChatMessage<'a>,
+    PluginMessage<'a>,
+    SetPlayerPosition,
+    SetPlayerPositionAndRotation,
+});
+
+/// A chat message the player typed, which borrows its text from the frame it came in
+#[derive(Packet)]
+#[packet(id = 0x03, since(V1_19 = 0x04, V1_19_2 = 0x05))]
+pub struct ChatMessage<'a> {
+    pub message: &'a str,
+    /// When the message was sent, in milliseconds since the epoch
+    #[packet(since = V1_19)]
+    pub timestamp: i64,
+    #[packet(since = V1_19)]
+    pub salt: i64,
+    /// The message signed with the player's chat key, empty if it isn't signed
+    #[packet(since = V1_19, before = V1_19_3)]
+    pub signature: VarIntPrefixedVec<'a, u8>,
+    #[packet(since = V1_19, before = V1_19_3)]
+    pub signed_preview: bool,
+    /// The messages the client had on screen when it sent this one
+    #[packet(since = V1_19_2, before = V1_19_3)]
+    pub last_seen: VarIntPrefixedVec<'a, SeenMessage<'a>>,
+    #[packet(since = V1_19_2, before = V1_19_3)]
+    pub last_received: Option<SeenMessage<'a>>,
+    #[packet(since = V1_19_3)]
+    pub message_signature: Option<&'a [u8; 256]>,
+    /// How many messages the client has seen since it last acknowledged any
+    #[packet(since = V1_19_3)]
+    pub message_count: VarInt,
+    /// A bit for each of the last 20 messages, set if the client saw it
+    #[packet(since = V1_19_3)]
+    pub acknowledged: [u8; 3],
+}
+
+/// A signed message from another player, as 1.19.1 and 1.19.2 clients refer back to it
+#[derive(Clone, Packet)]
+pub struct SeenMessage<'a> {
+    pub profile_id: Uuid,
+    pub signature: VarIntPrefixedVec<'a, u8>,
+}
+
+/// Custom data on a channel of the client's (or a mod's) choosing, such as `minecraft:brand`
+#[derive(Packet)]
+#[packet(id = 0x0A, since(V1_19 = 0x0C, V1_19_2 = 0x0D, V1_19_3 = 0x0C, V1_19_4 = 0x0D))]
+pub struct PluginMessage<'a> {
+    pub channel: &'a str,
+    /// Everything else in the packet, in whatever format the channel uses
+    pub data: GreedyVecU8<'a>,
+}
+
+/// Where the player has moved to, sent every tick they move
+#[derive(Packet)]
+#[packet(id = 0x11, since(V1_19 = 0x13, V1_19_2 = 0x14, V1_19_3 = 0x13, V1_19_4 = 0x14))]
+pub struct SetPlayerPosition {
+    pub x: f64,
+    /// The Y of the player's feet
+    pub y: f64,
+    pub z: f64,
+    pub on_ground: bool,
+}
+
+/// Like Set Player Position, for when the player has turned as well
+#[derive(Packet)]
+#[packet(id = 0x12, since(V1_19 = 0x14, V1_19_2 = 0x15, V1_19_3 = 0x14, V1_19_4 = 0x15))]
+pub struct SetPlayerPositionAndRotation {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        packet_handler::PacketHandler,
+        structs::{ProtocolVersion, Readable, Writeable},
+    };
+    use std::{borrow::Cow, io::Cursor};
+
+    fn chat_message() -> ChatMessage<'static> {
+        ChatMessage {
+            message: "hello",
+            timestamp: 1,
+            salt: 2,
+            signature: vec![3].into(),
+            signed_preview: false,
+            last_seen: vec![SeenMessage {
+                profile_id: Uuid::from_u128(4),
+                signature: vec![5].into(),
+            }]
+            .into(),
+            last_received: None,
+            message_signature: Some(&[6; 256]),
+            message_count: VarInt(7),
+            acknowledged: [8, 9, 10],
+        }
+    }
+
+    #[test]
+    fn packets_borrow_from_their_frame() {
+        let mut handler = PacketHandler::new();
+        let mut stream = Vec::new();
+        let plugin_message = PluginMessage {
+            channel: "minecraft:brand",
+            data: vec![7, b'v', b'a', b'n', b'i', b'l', b'l', b'a'].into(),
+        };
+        handler.write_packet(&ClientPlayPacket::from(plugin_message), &mut stream).unwrap();
+        handler.write_packet(&ClientPlayPacket::from(chat_message()), &mut stream).unwrap();
+        handler.feed(&stream);
+
+        let frame = handler.next_frame().unwrap().unwrap();
+        let body = frame.body().as_ptr_range();
+        match frame.read::<ClientPlayPacket>().unwrap() {
+            ClientPlayPacket::PluginMessage(packet) => {
+                assert_eq!(packet.channel, "minecraft:brand");
+                assert!(body.contains(&packet.channel.as_ptr()));
+                assert!(matches!(packet.data.0, Cow::Borrowed(data) if data.len() == 8));
+            }
+            _ => panic!("expected a plugin message"),
+        }
+
+        let frame = handler.next_frame().unwrap().unwrap();
+        let body = frame.body().as_ptr_range();
+        match frame.read::<ClientPlayPacket>().unwrap() {
+            ClientPlayPacket::ChatMessage(packet) => {
+                assert_eq!(packet.message, "hello");
+                assert!(body.contains(&packet.message.as_ptr()));
+                assert!(body.contains(&packet.message_signature.unwrap().as_ptr()));
+                assert_eq!(packet.acknowledged, [8, 9, 10]);
+            }
+            _ => panic!("expected a chat message"),
+        }
+    }
+
+    #[test]
+    fn chat_message_fields_depend_on_version() {
+        let length = |version| {
+            let mut buffer = Vec::new();
+            chat_message().write(&mut buffer, version).unwrap();
+            buffer.len()
+        };
+        // the message behind its length
+        assert_eq!(length(ProtocolVersion::V1_18_2), 6);
+        // the timestamp and salt, the signature behind its length and the preview flag
+        assert_eq!(length(ProtocolVersion::V1_19), 6 + 16 + 2 + 1);
+        // one last seen message and an absent last received one
+        assert_eq!(length(ProtocolVersion::V1_19_2), 6 + 16 + 2 + 1 + 1 + 18 + 1);
+        // a present flag and the fixed size signature, the count and the acknowledged bits
+        assert_eq!(length(ProtocolVersion::V1_19_3), 6 + 16 + 257 + 1 + 3);
+
+        let mut buffer = Vec::new();
+        chat_message().write(&mut buffer, ProtocolVersion::V1_19_2).unwrap();
+        let read = ChatMessage::read(&mut Cursor::new(&buffer[..]), ProtocolVersion::V1_19_2).unwrap();
+        assert_eq!(read.last_seen.0[0].profile_id, Uuid::from_u128(4));
+        assert!(read.message_signature.is_none());
+    }
+}
@@ -0,0 +1,17 @@
+use crate::protocol::packets::{packet_enum, Packet};
+
+packet_enum!(ClientStatusPacket {
+    Request,
+    Ping,
+});
+
+/// Asks the server for its server list response. Has no fields.
+#[derive(Packet)]
+#[packet(id = 0x00)]
+pub struct Request;
+
+#[derive(Packet)]
+#[packet(id = 0x01)]
+pub struct Ping {
+    pub payload: i64,
+}
@@ -0,0 +1,61 @@
+use std::{io, str::Utf8Error};
+use thiserror::Error;
+
+/// Why bytes from a client couldn't be read as a packet.
+///
+/// `IncompleteFrame` only means more bytes have to arrive first. Everything else is the client breaking the
+/// protocol, which is worth dropping it for.
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    /// The frame, or the VarInt giving its length, hasn't fully arrived yet
+    #[error("incomplete frame")]
+    IncompleteFrame,
+
+    /// The packet ended before everything in it was read
+    #[error("packet ended while reading {0}")]
+    UnexpectedEof(&'static str),
+
+    /// A length prefix was negative or bigger than we allow for what it's the length of
+    #[error("{what} length {length} is out of range, the most allowed is {max}")]
+    OversizedLength { what: &'static str, length: i64, max: usize },
+
+    /// A VarInt or VarLong kept going past its maximum length
+    #[error("{0} is too long")]
+    InvalidVarInt(&'static str),
+
+    #[error("string is not valid UTF-8")]
+    InvalidUtf8(#[from] Utf8Error),
+
+    #[error("unknown packet ID 0x{id:02X} for {packets}")]
+    UnknownPacketId { id: i32, packets: &'static str },
+
+    /// A compressed packet that decompresses to less than the threshold, which the client should have sent as-is
+    #[error("compressed packet of {length} bytes is below the compression threshold of {threshold}")]
+    BelowCompressionThreshold { length: usize, threshold: usize },
+
+    #[error("unable to decompress packet")]
+    Decompression(#[source] io::Error),
+
+    /// The client made us decompress more than we're willing to in too short a time
+    #[error("decompressed more than {max} bytes of packets in a second")]
+    DecompressionRate { max: usize },
+
+    /// Something the client encrypted with our public key during login wouldn't decrypt
+    #[error("unable to decrypt {0}")]
+    Decryption(&'static str, #[source] rsa::Error),
+
+    /// A value that's the right shape but not one the protocol allows, like a boolean of 2
+    #[error("invalid {what} {value}")]
+    InvalidValue { what: &'static str, value: String },
+
+    /// Anything that's read with code outside of the protocol module, like NBT
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ProtocolError {
+    /// Whether the client should be dropped for this, rather than just waiting on more bytes
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, ProtocolError::IncompleteFrame)
+    }
+}
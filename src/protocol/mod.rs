@@ -1,5 +1,6 @@
-mod packets;
-mod client;
-mod server;
-pub(crate) mod structs;
-pub(crate) mod packet_handler;
\ No newline at end of file
+pub mod packets;
+pub mod client;
+pub mod server;
+pub mod structs;
+pub mod error;
+pub mod packet_handler;
@@ -1,23 +1,28 @@
-use crate::{VarInt, ProtocolVersion, Readable, Writeable};
+use crate::protocol::error::ProtocolError;
+use crate::protocol::structs::{ProtocolVersion, Readable, VarInt, Writeable};
 use aes::Aes128;
-use aes::cipher::{AsyncStreamCipher, KeyIvInit};
-use bytes::BytesMut;
-use cfb8::{Encryptor, Decryptor};
+use aes::cipher::{generic_array::GenericArray, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use bytes::{Buf, Bytes, BytesMut};
 use flate2::{
     bufread::{ZlibDecoder, ZlibEncoder},
     Compression,
 };
-use std::io::{Cursor, Read};
+use std::{
+    convert::TryFrom,
+    io::{self, Cursor, ErrorKind, Read},
+};
 
 pub type EncryptionKey = [u8; 16];
 pub type CompressionThreshold = usize;
 
-pub type Cfb8Enc = cfb8::Encryptor<Aes128>;
-pub type Cfb8Dec = cfb8::Decryptor<Aes128>;
+/// The longest frame a client can send, which is as much as the 3 byte VarInt vanilla reads lengths as can hold
+pub const MAX_FRAME_LENGTH: usize = (1 << 21) - 1;
 
-pub const PROTOCOL: ProtocolVersion = ProtocolVersion::V1_18_2;
+/// The most a compressed packet can say it decompresses to, the same as vanilla allows
+pub const MAX_DATA_LENGTH: usize = 8 * 1024 * 1024;
 
-//NOTE: A lot of this will be flagged by the compiler as "unused" since the netty thread isn't implemeneted yet.
+pub type Cfb8Enc = cfb8::Encryptor<Aes128>;
+pub type Cfb8Dec = cfb8::Decryptor<Aes128>;
 
 pub struct EncryptionHandler {
     key: EncryptionKey,
@@ -28,13 +33,48 @@ pub struct EncryptionHandler {
 impl EncryptionHandler {
     pub fn new(key: EncryptionKey) -> Self {
         EncryptionHandler { 
-            key: key, 
+            key, 
             encryptor: Cfb8Enc::new_from_slices(&key, &key)
                 .expect("invalid key size!"), 
             decryption: Cfb8Dec::new_from_slices(&key, &key)
                 .expect("invalid key size!") 
         }
     }
+
+    /// Encrypts `data` in place, carrying the cipher stream on from previous calls
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        // CFB8 has a block size of one byte
+        for byte in data.chunks_mut(1) {
+            self.encryptor.encrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+
+    /// Decrypts `data` in place, carrying the cipher stream on from previous calls
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data.chunks_mut(1) {
+            self.decryption.decrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+}
+
+/// The body of one packet, with its framing and any compression taken off
+pub struct Frame {
+    body: Bytes,
+    version: ProtocolVersion,
+}
+
+impl Frame {
+    /// Reads the packet in this frame, which can borrow strings and byte arrays from it
+    pub fn read<'a, T>(&'a self) -> Result<T, ProtocolError>
+    where
+        T: Readable<'a>,
+    {
+        T::read(&mut Cursor::new(&self.body[..]), self.version)
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
 }
 
 #[derive(Default)]
@@ -43,6 +83,10 @@ pub struct PacketHandler {
     encryption_handler: Option<EncryptionHandler>,
     //Compression settings
     compression: Option<CompressionThreshold>,
+    /// The version packets are read and written as
+    version: ProtocolVersion,
+    /// How many bytes frames have decompressed to since this was last taken
+    decompressed: usize,
 
     //Buffers
     incoming_buf: BytesMut,
@@ -51,62 +95,415 @@ pub struct PacketHandler {
 }
 
 impl PacketHandler {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self::default()
     }
 
     pub fn clone_keep_settings(&self) -> Self {
         PacketHandler {
             encryption_handler: self.encryption_handler.as_ref().map(|v|EncryptionHandler::new(v.key)),
+            compression: self.compression,
+            version: self.version,
             ..Default::default()
         }
     }
 
+    pub fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    /// Reads and writes packets as `version` from now on, once the handshake has told us which one the client speaks
+    pub fn set_version(&mut self, version: ProtocolVersion) {
+        self.version = version
+    }
+
+    /// Encrypts everything written and decrypts everything fed from now on.
+    ///
+    /// Bytes that were already fed but haven't been read as packets yet arrived after the packet that
+    /// turned encryption on, so they get decrypted too.
     pub fn enable_encryption(&mut self, key: EncryptionKey) {
-        self.encryption_handler = Some(EncryptionHandler::new(key))
+        let mut encryption = EncryptionHandler::new(key);
+        encryption.decrypt(&mut self.incoming_buf);
+        self.encryption_handler = Some(encryption)
+    }
+
+    /// Switches to the compressed packet format, compressing packets of at least `threshold` bytes.
+    ///
+    /// Like with encryption, this has to happen right after Set Compression is written, and frames fed but not yet
+    /// read are taken to be in the compressed format.
+    pub fn enable_compression(&mut self, threshold: CompressionThreshold) {
+        self.compression = Some(threshold)
     }
 
-    //this function could probably be more readable
-    pub fn next_packet<T>(&mut self) -> anyhow::Result<Option<T>>
+    /// How many bytes frames have decompressed to since the last call, to keep track of how much work a client is
+    /// making us do
+    pub fn take_decompressed(&mut self) -> usize {
+        std::mem::take(&mut self.decompressed)
+    }
+
+    /// Queues bytes received from the socket to be framed by `next_packet`, decrypting them if encryption is on.
+    ///
+    /// The bytes don't have to line up with packet boundaries.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let start = self.incoming_buf.len();
+        self.incoming_buf.extend_from_slice(bytes);
+
+        if let Some(encryption) = &mut self.encryption_handler {
+            encryption.decrypt(&mut self.incoming_buf[start..]);
+        }
+    }
+
+    /// Reads the next packet out of the incoming buffer, or `None` if a full frame hasn't arrived yet.
+    ///
+    /// Any error means the client broke the protocol, and nothing more should be read from it. Only packets that
+    /// own everything in them can be read like this, use `next_frame` for ones that borrow.
+    pub fn next_packet<T>(&mut self) -> Result<Option<T>, ProtocolError>
     where
-        T: Readable,
+        T: for<'de> Readable<'de>,
     {
-        let mut cursor = Cursor::new(&self.incoming_buf[..]);
-        let length: i32;
+        match self.next_frame()? {
+            Some(frame) => frame.read().map(Some),
+            None => Ok(None),
+        }
+    }
 
-        if let Ok(len) = VarInt::read(&mut cursor, PROTOCOL) {
-            length = len.0;
-        } else {
-            return Err(anyhow::anyhow!("unable to read packet."));
+    /// Splits the next frame off the incoming buffer, or `None` if it hasn't all arrived yet.
+    ///
+    /// The frame shares the buffer's memory rather than being copied out of it, unless it had to be decompressed.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, ProtocolError> {
+        match self.split_frame() {
+            Err(ProtocolError::IncompleteFrame) => Ok(None),
+            result => result.map(Some),
         }
+    }
+
+    /// Takes the next frame, failing with `IncompleteFrame` if it hasn't all arrived
+    fn split_frame(&mut self) -> Result<Frame, ProtocolError> {
+        let mut cursor = Cursor::new(&self.incoming_buf[..]);
+
+        let length = match VarInt::read_from(&mut cursor) {
+            Ok(len) => len.0,
+            // the length prefix itself is still in flight
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(ProtocolError::IncompleteFrame),
+            Err(_) => return Err(ProtocolError::InvalidVarInt("frame length")),
+        };
+        // checked before waiting on the rest of the frame, so a huge length can't make us buffer it all
+        let length = match usize::try_from(length) {
+            Ok(length) if length <= MAX_FRAME_LENGTH => length,
+            _ => {
+                return Err(ProtocolError::OversizedLength {
+                    what: "frame",
+                    length: length.into(),
+                    max: MAX_FRAME_LENGTH,
+                })
+            }
+        };
 
         //the length (in bytes) of the VarInt that describes length
         let length_field_len: usize = cursor.position() as usize;
 
-        return if self.incoming_buf.len() - length_field_len >= length as usize {
-            cursor = Cursor::new(
-                                         // starting after    // ending with the 
-                                         // length encoding   // current section
-                &self.incoming_buf[length_field_len .. length_field_len + length as usize]
-            );
-
-            if self.compression.is_some() {
-                let data_length: i32 = VarInt::read(&mut cursor, PROTOCOL)?.0;
-                if data_length != 0 {
-                    let mut decompressor = ZlibDecoder::new(&cursor.get_ref()[cursor.position() as usize.. ]);
-                    decompressor.read_to_end(&mut self.compressed_buf);
-                    cursor = Cursor::new(&self.compressed_buf);
+        if self.incoming_buf.len() - length_field_len < length {
+            return Err(ProtocolError::IncompleteFrame);
+        }
+
+        let mut body = self.incoming_buf.split_to(length_field_len + length).freeze();
+        body.advance(length_field_len);
+
+        if let Some(threshold) = self.compression {
+            let mut cursor = Cursor::new(&body[..]);
+            let data_length: i32 = VarInt::read(&mut cursor, self.version)?.0;
+            let data_start = cursor.position() as usize;
+            if data_length != 0 {
+                let data_length = match usize::try_from(data_length) {
+                    Ok(length) if length <= MAX_DATA_LENGTH => length,
+                    _ => {
+                        return Err(ProtocolError::OversizedLength {
+                            what: "decompressed packet",
+                            length: data_length.into(),
+                            max: MAX_DATA_LENGTH,
+                        })
+                    }
+                };
+                // anything smaller should have been sent uncompressed
+                if data_length < threshold {
+                    return Err(ProtocolError::BelowCompressionThreshold { length: data_length, threshold });
                 }
+
+                // one byte past the data length is enough to tell it was lying, without inflating the rest
+                let mut decompressed = Vec::new();
+                ZlibDecoder::new(&body[data_start..])
+                    .take(data_length as u64 + 1)
+                    .read_to_end(&mut decompressed)
+                    .map_err(ProtocolError::Decompression)?;
+                self.decompressed += decompressed.len();
+                if decompressed.len() != data_length {
+                    let length = if decompressed.len() > data_length {
+                        format!("over {}", data_length)
+                    } else {
+                        format!("{}, expected {}", decompressed.len(), data_length)
+                    };
+                    return Err(ProtocolError::InvalidValue { what: "decompressed packet length", value: length });
+                }
+                body = Bytes::from(decompressed);
+            } else {
+                body.advance(data_start);
             }
+        }
+
+        Ok(Frame { body, version: self.version })
+    }
+
+    /// Serializes `packet` and appends it to `output` as a frame, the exact inverse of `next_packet`:
+    /// length-prefixed, compressed if it's enabled and the packet is big enough, then encrypted.
+    pub fn write_packet<T>(&mut self, packet: &T, output: &mut Vec<u8>) -> anyhow::Result<()>
+    where
+        T: Writeable,
+    {
+        self.writing_buf.clear();
+        packet.write(&mut self.writing_buf, self.version)?;
+
+        let frame_start = output.len();
+        let data_length = self.writing_buf.len();
 
-            let packet = T::read(&mut cursor, PROTOCOL)?;
-            let bytes_read = length as usize + length_field_len;
-            self.incoming_buf = self.incoming_buf.split_off(bytes_read);
+        match self.compression {
+            Some(threshold) if data_length >= threshold => {
+                self.compressed_buf.clear();
+                ZlibEncoder::new(&self.writing_buf[..], Compression::default())
+                    .read_to_end(&mut self.compressed_buf)?;
 
-            self.compressed_buf.clear();
-            Ok(Some(packet))
-        }else {
-            Err(anyhow::anyhow!("packet had invalid length."))
+                let data_length = VarInt(data_length as i32);
+                let data_length_len = data_length.write_to(io::sink())?;
+
+                VarInt((data_length_len + self.compressed_buf.len()) as i32).write(output, self.version)?;
+                data_length.write(output, self.version)?;
+                output.extend_from_slice(&self.compressed_buf);
+                self.compressed_buf.clear();
+            }
+            Some(_) => {
+                // a data length of 0 marks the packet as uncompressed, and takes up one byte
+                VarInt(data_length as i32 + 1).write(output, self.version)?;
+                VarInt(0).write(output, self.version)?;
+                output.extend_from_slice(&self.writing_buf);
+            }
+            None => {
+                VarInt(data_length as i32).write(output, self.version)?;
+                output.extend_from_slice(&self.writing_buf);
+            }
         }
+
+        if let Some(encryption) = &mut self.encryption_handler {
+            encryption.encrypt(&mut output[frame_start..]);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{packets::RawPacket, server::ServerLoginPacket, structs::GreedyVecU8};
+
+    fn packet(len: usize) -> RawPacket<'static> {
+        RawPacket {
+            id: VarInt(0x0F),
+            data: GreedyVecU8((0..len).map(|i| (i % 7) as u8).collect::<Vec<u8>>().into()),
+        }
+    }
+
+    fn round_trip(handler: &mut PacketHandler, len: usize) -> (Vec<u8>, RawPacket<'static>) {
+        let mut frame = Vec::new();
+        handler.write_packet(&packet(len), &mut frame).unwrap();
+
+        handler.feed(&frame);
+        let read = handler.next_frame().unwrap().expect("frame should be complete");
+        let packet = read.read::<RawPacket>().unwrap();
+        (frame, RawPacket { id: packet.id, data: GreedyVecU8(packet.data.0.into_owned().into()) })
+    }
+
+    /// The body length of the next raw packet, if a whole frame has arrived
+    fn next_len(handler: &mut PacketHandler) -> Option<usize> {
+        let frame = handler.next_frame().unwrap()?;
+        Some(frame.read::<RawPacket>().unwrap().data.0.len())
+    }
+
+    #[test]
+    fn uncompressed_round_trip() {
+        let mut handler = PacketHandler::new();
+        let (frame, read) = round_trip(&mut handler, 20);
+
+        assert_eq!(frame[0] as usize, frame.len() - 1);
+        assert_eq!(read.id.0, 0x0F);
+        assert_eq!(read.data.0, packet(20).data.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn compressed_round_trip() {
+        let mut handler = PacketHandler::new();
+        handler.enable_compression(64);
+
+        // below the threshold, the packet is sent as-is behind a data length of 0
+        let (frame, read) = round_trip(&mut handler, 10);
+        assert_eq!(frame[1], 0);
+        assert_eq!(&frame[2..], &[&[0x0F], &packet(10).data.0[..]].concat()[..]);
+        assert_eq!(read.data.0, packet(10).data.0);
+
+        // above it, the data length is the uncompressed size and the body shrinks
+        let (frame, read) = round_trip(&mut handler, 300);
+        let mut cursor = Cursor::new(&frame[..]);
+        VarInt::read_from(&mut cursor).unwrap();
+        assert_eq!(VarInt::read_from(&mut cursor).unwrap().0, 301);
+        assert!(frame.len() < 300);
+        assert_eq!(read.data.0, packet(300).data.0);
+    }
+
+    #[test]
+    fn encrypted_frames_decrypt_to_plain_frames() {
+        let key = [7u8; 16];
+        let mut encrypted = PacketHandler::new();
+        encrypted.enable_encryption(key);
+        let mut plain = PacketHandler::new();
+
+        // the cipher stream must carry on across frames
+        let mut decryptor = Cfb8Dec::new_from_slices(&key, &key).unwrap();
+        for len in [5, 30, 12] {
+            let mut expected = Vec::new();
+            plain.write_packet(&packet(len), &mut expected).unwrap();
+
+            let mut frame = Vec::new();
+            encrypted.write_packet(&packet(len), &mut frame).unwrap();
+            assert_ne!(frame, expected);
+
+            for byte in frame.chunks_mut(1) {
+                decryptor.decrypt_block_mut(GenericArray::from_mut_slice(byte));
+            }
+            assert_eq!(frame, expected);
+        }
+    }
+
+    #[test]
+    fn encrypted_round_trip_across_partial_reads() {
+        let key = [3u8; 16];
+        let mut server = PacketHandler::new();
+        server.enable_encryption(key);
+        server.enable_compression(64);
+        let mut client = server.clone_keep_settings();
+
+        let mut stream = Vec::new();
+        for len in [5, 300, 40] {
+            server.write_packet(&packet(len), &mut stream).unwrap();
+        }
+
+        // awkward read sizes, so frames and cipher bytes get split everywhere
+        let mut read = Vec::new();
+        for chunk in stream.chunks(7) {
+            client.feed(chunk);
+            while let Some(len) = next_len(&mut client) {
+                read.push(len);
+            }
+        }
+        assert_eq!(read, [5, 300, 40]);
+    }
+
+    #[test]
+    fn encryption_enabled_mid_read() {
+        let key = [9u8; 16];
+        let mut sender = PacketHandler::new();
+        let mut stream = Vec::new();
+        sender.write_packet(&packet(4), &mut stream).unwrap();
+        sender.enable_encryption(key);
+        sender.write_packet(&packet(8), &mut stream).unwrap();
+
+        // the plaintext packet and the first encrypted one arrive in the same read
+        let mut receiver = PacketHandler::new();
+        receiver.feed(&stream);
+        assert_eq!(next_len(&mut receiver), Some(4));
+        receiver.enable_encryption(key);
+        assert_eq!(next_len(&mut receiver), Some(8));
+        assert_eq!(next_len(&mut receiver), None);
+    }
+
+    #[test]
+    fn violations_are_told_apart_from_incomplete_frames() {
+        let mut handler = PacketHandler::new();
+        // half a length prefix, then half a frame
+        handler.feed(&[0x80]);
+        assert!(handler.next_frame().unwrap().is_none());
+        handler.feed(&[0x01, 0x0F]);
+        assert!(handler.next_frame().unwrap().is_none());
+
+        let error = |bytes: &[u8], compression: bool| {
+            let mut handler = PacketHandler::new();
+            if compression {
+                handler.enable_compression(64);
+            }
+            handler.feed(bytes);
+            handler
+                .next_frame()
+                .and_then(|frame| frame.expect("frame should be complete").read::<ServerLoginPacket>().map(drop))
+                .expect_err("frame should be rejected")
+        };
+        assert!(matches!(error(&[0xFF; 6], false), ProtocolError::InvalidVarInt(_)));
+        assert!(matches!(
+            error(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F], false),
+            ProtocolError::OversizedLength { length: -1, .. }
+        ));
+        // too long to even wait for
+        assert!(matches!(
+            error(&[0x80, 0x80, 0x80, 0x01], false),
+            ProtocolError::OversizedLength { what: "frame", length: 0x200000, .. }
+        ));
+        assert!(matches!(error(&[1, 0x7F], false), ProtocolError::UnknownPacketId { id: 0x7F, .. }));
+        // a login Disconnect whose reason is cut short, then one that isn't UTF-8
+        assert!(matches!(error(&[3, 0x00, 5, b'a'], false), ProtocolError::UnexpectedEof(_)));
+        assert!(matches!(error(&[3, 0x00, 1, 0xFF], false), ProtocolError::InvalidUtf8(_)));
+        assert!(matches!(error(&[4, 100, 1, 2, 3], true), ProtocolError::Decompression(_)));
+        assert!(error(&[1, 0x7F], false).is_violation());
+    }
+
+    #[test]
+    fn compressed_data_lengths_are_checked() {
+        let frame = |data_length: i32, body: &[u8]| {
+            let mut frame = Vec::new();
+            VarInt(data_length).write_to(&mut frame).unwrap();
+            frame.extend_from_slice(body);
+            let mut prefixed = Vec::new();
+            VarInt(frame.len() as i32).write_to(&mut prefixed).unwrap();
+            prefixed.extend_from_slice(&frame);
+            prefixed
+        };
+        let read = |bytes: &[u8]| {
+            let mut handler = PacketHandler::new();
+            handler.enable_compression(64);
+            handler.feed(bytes);
+            handler.next_frame().map(|frame| frame.unwrap().body().len())
+        };
+
+        let mut compressed = Vec::new();
+        ZlibEncoder::new(&[0u8; 100][..], Compression::default()).read_to_end(&mut compressed).unwrap();
+        assert_eq!(read(&frame(100, &compressed)).unwrap(), 100);
+
+        let mut handler = PacketHandler::new();
+        handler.enable_compression(64);
+        handler.feed(&[frame(100, &compressed), frame(100, &compressed)].concat());
+        while handler.next_frame().unwrap().is_some() {}
+        assert_eq!(handler.take_decompressed(), 200);
+        assert_eq!(handler.take_decompressed(), 0);
+
+        // compressing what's under the threshold is left to the server
+        let mut small = Vec::new();
+        ZlibEncoder::new(&[0u8; 10][..], Compression::default()).read_to_end(&mut small).unwrap();
+        assert!(matches!(
+            read(&frame(10, &small)),
+            Err(ProtocolError::BelowCompressionThreshold { length: 10, threshold: 64 })
+        ));
+        assert!(matches!(
+            read(&frame(MAX_DATA_LENGTH as i32 + 1, &compressed)),
+            Err(ProtocolError::OversizedLength { .. })
+        ));
+        assert!(matches!(read(&frame(-1, &compressed)), Err(ProtocolError::OversizedLength { length: -1, .. })));
+        // the data length has to be what the packet actually decompresses to, and it's never inflated past it
+        assert!(matches!(read(&frame(99, &compressed)), Err(ProtocolError::InvalidValue { .. })));
+        assert!(matches!(read(&frame(101, &compressed)), Err(ProtocolError::InvalidValue { .. })));
+    }
+}
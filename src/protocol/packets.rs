@@ -0,0 +1,134 @@
+use crate::protocol::structs::{GreedyVecU8, ProtocolVersion, VarInt};
+
+pub use ferrous_macros::Packet;
+
+/// A type sent over the wire as a packet, identified by its packet ID.
+///
+/// Usually implemented through `#[derive(Packet)]` with a `#[packet(id = ...)]` attribute, along with
+/// `Readable` and `Writeable`.
+pub trait Packet {
+    /// The ID this packet is sent with on the given protocol version
+    fn id(version: ProtocolVersion) -> i32;
+}
+
+/// Declares an enum over every packet one side can send in a single connection state.
+///
+/// Reading the enum consumes the packet ID and dispatches to the matching packet's `Readable`
+/// impl, writing it emits the ID followed by the packet body.
+///
+/// Packets that borrow from the buffer they're read from are listed with their lifetime, like `Chat<'a>`, which
+/// the enum then takes too: `packet_enum!(Packets<'a> { Chat<'a>, KeepAlive })`.
+macro_rules! packet_enum {
+    ($name:ident<$lt:lifetime> { $($packet:ident $(<$packet_lt:lifetime>)?),* $(,)? }) => {
+        // packets are written out as soon as they're made, so boxing the big ones wouldn't save anything
+        #[allow(clippy::large_enum_variant)]
+        pub enum $name<$lt> {
+            $($packet($packet $(<$packet_lt>)?),)*
+        }
+
+        $crate::protocol::packets::packet_enum!(@impls $lt $name<$lt> { $($packet $(<$packet_lt>)?),* });
+    };
+    ($name:ident { $($packet:ident),* $(,)? }) => {
+        #[allow(clippy::large_enum_variant)]
+        pub enum $name {
+            $($packet($packet),)*
+        }
+
+        $crate::protocol::packets::packet_enum!(@impls 'de $name { $($packet),* });
+    };
+    // `$de` is the lifetime of the buffer, which the enum borrows from if it has a lifetime at all
+    (@impls $de:lifetime $ty:ty { $($packet:ident $(<$packet_lt:lifetime>)?),* }) => {
+        impl<$de> $crate::protocol::structs::Readable<$de> for $ty {
+            fn read(
+                buffer: &mut std::io::Cursor<&$de [u8]>,
+                version: $crate::protocol::structs::ProtocolVersion,
+            ) -> Result<Self, $crate::protocol::error::ProtocolError> {
+                let id = $crate::protocol::structs::VarInt::read(buffer, version)?.0;
+                $(
+                    if id == <$packet as $crate::protocol::packets::Packet>::id(version) {
+                        return Ok(Self::$packet($packet::read(buffer, version)?));
+                    }
+                )*
+                Err($crate::protocol::error::ProtocolError::UnknownPacketId { id, packets: stringify!($ty) })
+            }
+        }
+
+        impl<$de> $crate::protocol::structs::Writeable for $ty {
+            fn write(
+                &self,
+                buffer: &mut Vec<u8>,
+                version: $crate::protocol::structs::ProtocolVersion,
+            ) -> anyhow::Result<()> {
+                match self {
+                    $(Self::$packet(packet) => {
+                        let id = <$packet as $crate::protocol::packets::Packet>::id(version);
+                        $crate::protocol::structs::VarInt(id).write(buffer, version)?;
+                        packet.write(buffer, version)
+                    })*
+                }
+            }
+        }
+
+        $(
+            impl<$de> From<$packet $(<$packet_lt>)?> for $ty {
+                fn from(packet: $packet $(<$packet_lt>)?) -> Self {
+                    Self::$packet(packet)
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use packet_enum;
+
+/// A packet we don't decode yet, kept as its ID and raw body
+#[derive(Packet)]
+pub struct RawPacket<'a> {
+    pub id: VarInt,
+    pub data: GreedyVecU8<'a>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::structs::{Readable, Writeable};
+    use std::io::Cursor;
+
+    #[derive(Packet, Debug, PartialEq)]
+    #[packet(id = 0x10, since(V1_19 = 0x11, V1_19_4 = 0x12))]
+    struct Gated {
+        always: u8,
+        #[packet(since = V1_19_3)]
+        added: u8,
+        #[packet(before = V1_19_3)]
+        removed: u8,
+    }
+
+    fn written(packet: &Gated, version: ProtocolVersion) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        packet.write(&mut buffer, version).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn ids_change_from_the_version_they_are_given_for() {
+        assert_eq!(Gated::id(ProtocolVersion::V1_18_2), 0x10);
+        assert_eq!(Gated::id(ProtocolVersion::V1_19), 0x11);
+        assert_eq!(Gated::id(ProtocolVersion::V1_19_3), 0x11);
+        assert_eq!(Gated::id(ProtocolVersion::V1_19_4), 0x12);
+        assert_eq!(Gated::id(ProtocolVersion::V1_20_1), 0x12);
+    }
+
+    #[test]
+    fn fields_are_gated_on_their_versions() {
+        let packet = Gated { always: 1, added: 2, removed: 3 };
+        assert_eq!(written(&packet, ProtocolVersion::V1_19_2), [1, 3]);
+        // `since` includes the version itself and `before` doesn't
+        assert_eq!(written(&packet, ProtocolVersion::V1_19_3), [1, 2]);
+
+        let read = Gated::read(&mut Cursor::new(&[1, 3][..]), ProtocolVersion::V1_19_2).unwrap();
+        assert_eq!(read, Gated { always: 1, added: 0, removed: 3 });
+        let read = Gated::read(&mut Cursor::new(&[1, 2][..]), ProtocolVersion::V1_19_3).unwrap();
+        assert_eq!(read, Gated { always: 1, added: 2, removed: 0 });
+    }
+}
@@ -0,0 +1,66 @@
+use crate::protocol::{
+    packets::{packet_enum, Packet},
+    structs::{GreedyVecU8, VarInt, VarIntPrefixedVec},
+};
+use crate::util::Identifier;
+use uuid::Uuid;
+
+packet_enum!(ServerLoginPacket<'a> {
+    Disconnect,
+    EncryptionRequest<'a>,
+    LoginSuccess<'a>,
+    SetCompression,
+    LoginPluginRequest<'a>,
+});
+
+#[derive(Packet)]
+#[packet(id = 0x00)]
+pub struct Disconnect {
+    /// The kick message as a JSON chat component
+    pub reason: String,
+}
+
+#[derive(Packet)]
+#[packet(id = 0x01)]
+pub struct EncryptionRequest<'a> {
+    /// Always empty since 1.7, but still part of the server hash
+    pub server_id: String,
+    /// The server's RSA public key, DER encoded
+    pub public_key: VarIntPrefixedVec<'a, u8>,
+    pub verify_token: VarIntPrefixedVec<'a, u8>,
+}
+
+#[derive(Packet)]
+#[packet(id = 0x02)]
+pub struct LoginSuccess<'a> {
+    pub uuid: Uuid,
+    pub username: String,
+    /// The player's profile properties, like their skin, which 1.18.2 only gets later on in Player Info
+    #[packet(since = V1_19)]
+    pub properties: VarIntPrefixedVec<'a, Property>,
+}
+
+/// A signed profile property
+#[derive(Clone, Packet)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+/// Turns on compression for everything after it, in both directions
+#[derive(Packet)]
+#[packet(id = 0x03)]
+pub struct SetCompression {
+    /// Packets of at least this many bytes are compressed
+    pub threshold: VarInt,
+}
+
+#[derive(Packet)]
+#[packet(id = 0x04)]
+pub struct LoginPluginRequest<'a> {
+    /// Echoed back in the client's response
+    pub message_id: VarInt,
+    pub channel: Identifier,
+    pub data: GreedyVecU8<'a>,
+}
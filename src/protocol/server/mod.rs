@@ -0,0 +1,9 @@
+//! Packets sent by the server to the client.
+
+pub mod login;
+pub mod play;
+pub mod status;
+
+pub use login::*;
+pub use play::*;
+pub use status::*;
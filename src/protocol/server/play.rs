@@ -0,0 +1,222 @@
+use crate::nbt::Nbt;
+use crate::protocol::{
+    packets::{packet_enum, Packet},
+    structs::{BitSet, VarInt, VarIntPrefixedVec},
+};
+
+packet_enum!(ServerPlayPacket<'a> {
+    KeepAlive,
+    UnloadChunk,
+    ChunkDataAndUpdateLight<'a>,
+    JoinGame<'a>,
+    SynchronizePlayerPosition,
+    UpdateViewPosition,
+});
+
+/// Checks the client is still there, which it answers with the same ID. Clients that hear nothing from the server
+/// for 30 seconds give up on it
+#[derive(Packet)]
+#[packet(id = 0x21, since(V1_19 = 0x1E, V1_19_2 = 0x20, V1_19_3 = 0x1F, V1_19_4 = 0x23))]
+pub struct KeepAlive {
+    pub id: i64,
+}
+
+/// Tells the client to forget a chunk that's moved out of its view distance
+#[derive(Packet)]
+#[packet(id = 0x1D, since(V1_19 = 0x1A, V1_19_2 = 0x1C, V1_19_3 = 0x1B, V1_19_4 = 0x1E))]
+pub struct UnloadChunk {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+}
+
+#[derive(Packet)]
+#[packet(id = 0x22, since(V1_19 = 0x1F, V1_19_2 = 0x21, V1_19_3 = 0x20, V1_19_4 = 0x24))]
+pub struct ChunkDataAndUpdateLight<'a> {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// A compound of long arrays, of which the client only uses `MOTION_BLOCKING`
+    pub heightmaps: Nbt,
+    /// Every section from the bottom of the world up, one after the other
+    pub data: VarIntPrefixedVec<'a, u8>,
+    pub block_entities: VarIntPrefixedVec<'a, ChunkBlockEntity>,
+    pub light: LightData<'a>,
+}
+
+/// A block entity as it's sent with its chunk
+#[derive(Clone, Packet)]
+pub struct ChunkBlockEntity {
+    /// The X and Z within the chunk, packed as `x << 4 | z`
+    pub packed_xz: u8,
+    pub y: i16,
+    /// The block entity type's ID in the registry
+    pub kind: VarInt,
+    pub data: Nbt,
+}
+
+/// The light of a chunk column, which covers one more section above and below the world than the blocks do
+#[derive(Packet)]
+pub struct LightData<'a> {
+    /// Whether the client can skip relighting the chunk's edges. 1.20 always relights them
+    #[packet(before = V1_20_1)]
+    pub trust_edges: bool,
+    /// Which sections have a sky light array below
+    pub sky_light_mask: BitSet,
+    pub block_light_mask: BitSet,
+    /// Which sections have no light at all, without needing an array of zeroes
+    pub empty_sky_light_mask: BitSet,
+    pub empty_block_light_mask: BitSet,
+    /// A nibble per block for each section in `sky_light_mask`, lowest section first
+    pub sky_light: VarIntPrefixedVec<'a, VarIntPrefixedVec<'a, u8>>,
+    pub block_light: VarIntPrefixedVec<'a, VarIntPrefixedVec<'a, u8>>,
+}
+
+/// The first packet of the play state, which puts the player in a dimension and tells the client about the
+/// registries everything after refers to
+#[derive(Packet)]
+#[packet(id = 0x26, since(V1_19 = 0x23, V1_19_2 = 0x25, V1_19_3 = 0x24, V1_19_4 = 0x28))]
+pub struct JoinGame<'a> {
+    pub entity_id: i32,
+    pub is_hardcore: bool,
+    pub game_mode: u8,
+    /// -1 if there wasn't one
+    pub previous_game_mode: i8,
+    /// Every dimension on the server, like `minecraft:overworld`
+    pub dimension_names: VarIntPrefixedVec<'a, String>,
+    /// The dimension types and biomes, and since 1.19 the chat types
+    pub registry_codec: Nbt,
+    /// The dimension type the player is in, which 1.18.2 is sent in full
+    #[packet(before = V1_19)]
+    pub dimension: Nbt,
+    /// The name of the dimension type in the codec, since 1.19
+    #[packet(since = V1_19)]
+    pub dimension_type: String,
+    pub dimension_name: String,
+    /// The first 8 bytes of the SHA-256 of the seed, which the client uses for biome noise
+    pub hashed_seed: i64,
+    /// Unused by the client
+    pub max_players: VarInt,
+    pub view_distance: VarInt,
+    pub simulation_distance: VarInt,
+    pub reduced_debug_info: bool,
+    pub enable_respawn_screen: bool,
+    /// Whether this is a debug world, which can't be changed
+    pub is_debug: bool,
+    /// Whether this is a superflat world, which puts the horizon at y 0 rather than sea level
+    pub is_flat: bool,
+    #[packet(since = V1_19)]
+    pub death_location: Option<DeathLocation>,
+    #[packet(since = V1_20_1)]
+    pub portal_cooldown: VarInt,
+}
+
+/// Where the player last died, which recovery compasses point to
+#[derive(Packet)]
+pub struct DeathLocation {
+    pub dimension_name: String,
+    /// Packed as `x << 38 | z << 12 | y`, each masked to its width
+    pub location: i64,
+}
+
+/// Teleports the player, which the client confirms with the same teleport ID before it sends any more movement
+#[derive(Packet)]
+#[packet(id = 0x38, since(V1_19 = 0x36, V1_19_2 = 0x39, V1_19_3 = 0x38, V1_19_4 = 0x3C))]
+pub struct SynchronizePlayerPosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Which of the coords and angles are relative to where the player is, from x up to pitch in the low five bits
+    pub flags: u8,
+    pub teleport_id: VarInt,
+    #[packet(before = V1_19_4)]
+    pub dismount_vehicle: bool,
+}
+
+/// Moves the centre of the area the client keeps chunks loaded in, whenever the player crosses into another chunk
+#[derive(Packet)]
+#[packet(id = 0x49, since(V1_19 = 0x48, V1_19_2 = 0x4B, V1_19_3 = 0x4A, V1_19_4 = 0x4E))]
+pub struct UpdateViewPosition {
+    pub chunk_x: VarInt,
+    pub chunk_z: VarInt,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::{Compound, Tag};
+    use crate::protocol::structs::{ProtocolVersion, Readable, Writeable};
+    use std::io::Cursor;
+
+    #[test]
+    fn ids_follow_the_version() {
+        assert_eq!(ChunkDataAndUpdateLight::id(ProtocolVersion::V1_18_2), 0x22);
+        assert_eq!(ChunkDataAndUpdateLight::id(ProtocolVersion::V1_19), 0x1F);
+        assert_eq!(ChunkDataAndUpdateLight::id(ProtocolVersion::V1_19_3), 0x20);
+        assert_eq!(ChunkDataAndUpdateLight::id(ProtocolVersion::V1_20_1), 0x24);
+        assert_eq!(UnloadChunk::id(ProtocolVersion::V1_19_2), 0x1C);
+        assert_eq!(UpdateViewPosition::id(ProtocolVersion::V1_19_4), 0x4E);
+        assert_eq!(JoinGame::id(ProtocolVersion::V1_18_2), 0x26);
+        assert_eq!(JoinGame::id(ProtocolVersion::V1_19_2), 0x25);
+        assert_eq!(SynchronizePlayerPosition::id(ProtocolVersion::V1_19), 0x36);
+        assert_eq!(KeepAlive::id(ProtocolVersion::V1_20_1), 0x23);
+
+        let mut buffer = Vec::new();
+        let packet = ServerPlayPacket::from(UnloadChunk { chunk_x: 1, chunk_z: 2 });
+        packet.write(&mut buffer, ProtocolVersion::V1_19_3).unwrap();
+        assert_eq!(buffer[0], 0x1B);
+    }
+
+    #[test]
+    fn trust_edges_went_away_in_1_20() {
+        let light = LightData {
+            trust_edges: true,
+            sky_light_mask: BitSet::default(),
+            block_light_mask: BitSet::default(),
+            empty_sky_light_mask: BitSet::default(),
+            empty_block_light_mask: BitSet::default(),
+            sky_light: Vec::new().into(),
+            block_light: Vec::new().into(),
+        };
+
+        let (mut old, mut new) = (Vec::new(), Vec::new());
+        light.write(&mut old, ProtocolVersion::V1_19_4).unwrap();
+        light.write(&mut new, ProtocolVersion::V1_20_1).unwrap();
+        assert_eq!(old[0], 1);
+        assert_eq!(&old[1..], &new[..]);
+    }
+
+    #[test]
+    fn join_game_sends_the_dimension_type_in_full_until_1_19() {
+        let join = JoinGame {
+            entity_id: 1,
+            is_hardcore: false,
+            game_mode: 1,
+            previous_game_mode: -1,
+            dimension_names: vec!["minecraft:overworld".to_owned()].into(),
+            registry_codec: Nbt::default(),
+            dimension: Nbt::from(Compound::from([("min_y".to_owned(), Tag::Int(-64))])),
+            dimension_type: "minecraft:overworld".to_owned(),
+            dimension_name: "minecraft:overworld".to_owned(),
+            hashed_seed: 0,
+            max_players: VarInt(0),
+            view_distance: VarInt(10),
+            simulation_distance: VarInt(10),
+            reduced_debug_info: false,
+            enable_respawn_screen: true,
+            is_debug: false,
+            is_flat: false,
+            death_location: None,
+            portal_cooldown: VarInt(0),
+        };
+
+        for version in [ProtocolVersion::V1_18_2, ProtocolVersion::V1_19] {
+            let mut buffer = Vec::new();
+            join.write(&mut buffer, version).unwrap();
+            let read = JoinGame::read(&mut Cursor::new(&buffer[..]), version).unwrap();
+            assert_eq!(read.view_distance.0, 10);
+            assert_eq!(read.dimension.root.contains_key("min_y"), version == ProtocolVersion::V1_18_2);
+            assert_eq!(read.dimension_type.is_empty(), version == ProtocolVersion::V1_18_2);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use crate::protocol::packets::{packet_enum, Packet};
+
+packet_enum!(ServerStatusPacket {
+    Response,
+    Pong,
+});
+
+#[derive(Packet)]
+#[packet(id = 0x00)]
+pub struct Response {
+    /// The server list response, serialized as JSON
+    pub json: String,
+}
+
+#[derive(Packet)]
+#[packet(id = 0x01)]
+pub struct Pong {
+    pub payload: i64,
+}
@@ -1,6 +1,7 @@
 
 // use crate::{ProtocolVersion, Slot};
-use anyhow::{bail, Context, Ok};
+use crate::protocol::error::ProtocolError;
+use anyhow::bail;
 // use base::{
 //     anvil::entity::ItemNbt, metadata::MetaEntry, BlockId, BlockPosition, Direction, EntityMetadata,
 //     Gamemode, Item, ItemStackBuilder, ValidBlockPosition,
@@ -10,22 +11,115 @@ use std::{
     borrow::Cow,
     convert::{TryFrom, TryInto},
     io::{self, Cursor, Read, Write, ErrorKind},
-    iter,
     marker::PhantomData,
+    mem,
 };
-use thiserror::Error;
+use uuid::Uuid;
 
+/// A protocol version we can speak, oldest first so versions can be compared with `<` and `>=`.
+///
+/// Releases that share a protocol number share a variant, named after the newest of them.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ProtocolVersion {
     V1_18_2,
+    V1_19,
+    V1_19_2,
+    V1_19_3,
+    V1_19_4,
+    V1_20_1,
+}
+
+impl ProtocolVersion {
+    /// Every supported version, oldest first
+    pub const ALL: [ProtocolVersion; 6] = [
+        ProtocolVersion::V1_18_2,
+        ProtocolVersion::V1_19,
+        ProtocolVersion::V1_19_2,
+        ProtocolVersion::V1_19_3,
+        ProtocolVersion::V1_19_4,
+        ProtocolVersion::V1_20_1,
+    ];
+    pub const OLDEST: ProtocolVersion = ProtocolVersion::V1_18_2;
+    pub const LATEST: ProtocolVersion = ProtocolVersion::V1_20_1;
+
+    /// Returns the protocol number clients send in their handshake for this version
+    pub fn protocol_id(self) -> i32 {
+        match self {
+            ProtocolVersion::V1_18_2 => 758,
+            ProtocolVersion::V1_19 => 759,
+            ProtocolVersion::V1_19_2 => 760,
+            ProtocolVersion::V1_19_3 => 761,
+            ProtocolVersion::V1_19_4 => 762,
+            ProtocolVersion::V1_20_1 => 763,
+        }
+    }
+
+    /// Looks up the version matching a handshake protocol number, if we support it
+    pub fn from_protocol_id(id: i32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|version| version.protocol_id() == id)
+    }
+
+    /// The human readable game version, as shown in the server list
+    pub fn name(self) -> &'static str {
+        match self {
+            ProtocolVersion::V1_18_2 => "1.18.2",
+            ProtocolVersion::V1_19 => "1.19",
+            ProtocolVersion::V1_19_2 => "1.19.2",
+            ProtocolVersion::V1_19_3 => "1.19.3",
+            ProtocolVersion::V1_19_4 => "1.19.4",
+            ProtocolVersion::V1_20_1 => "1.20.1",
+        }
+    }
+}
+
+impl Default for ProtocolVersion {
+    /// The newest version, which is what's spoken until a handshake says otherwise
+    fn default() -> Self {
+        ProtocolVersion::LATEST
+    }
 }
 
-/// Trait for types which can be read from buffer
-pub trait Readable {
-    /// Reads this type from the given buffer
-    fn read(buffer: &mut Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self>
+/// Trait for types which can be read from buffer.
+///
+/// `'de` is the lifetime of the bytes being read, so types like `&'de str` can point into them instead of copying
+/// them out.
+pub trait Readable<'de>: Sized {
+    /// Reads this type from the given buffer, which holds everything the client sent for the packet. Running out of
+    /// bytes is the client's fault, so it's an error like any other.
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>;
+
+    /// Reads `len` values one after the other, for a length prefixed array. Bytes are borrowed straight out of the
+    /// buffer rather than read one at a time.
+    fn read_many(
+        buffer: &mut Cursor<&'de [u8]>,
+        len: usize,
+        version: ProtocolVersion,
+    ) -> Result<Cow<'de, [Self]>, ProtocolError>
     where
-        Self: Sized;
+        Self: Clone,
+    {
+        // every element takes at least a byte, so a length past what's left can't be honest. Only as many bytes as
+        // are left get reserved up front, since an element can take far more memory than it did on the wire
+        let capacity = remaining(buffer).len() / mem::size_of::<Self>().max(1);
+        let mut vec = Vec::with_capacity(len.min(capacity));
+        for _ in 0..len {
+            vec.push(Self::read(buffer, version)?);
+        }
+        Ok(Cow::Owned(vec))
+    }
+}
+
+/// Everything in `buffer` that hasn't been read yet
+fn remaining<'de>(buffer: &Cursor<&'de [u8]>) -> &'de [u8] {
+    let bytes: &'de [u8] = buffer.get_ref();
+    &bytes[(buffer.position() as usize).min(bytes.len())..]
+}
+
+/// Takes the next `len` bytes out of `buffer` without copying them, for reading `what`
+fn take<'de>(buffer: &mut Cursor<&'de [u8]>, len: usize, what: &'static str) -> Result<&'de [u8], ProtocolError> {
+    let bytes = remaining(buffer).get(..len).ok_or(ProtocolError::UnexpectedEof(what))?;
+    buffer.set_position(buffer.position() + len as u64);
+    Ok(bytes)
 }
 
 /// Trait implemented for types which can be written to a buffer
@@ -34,7 +128,7 @@ pub trait Writeable: Sized {
     fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()>;
 }
 
-impl<'a, T> Writeable for &'a T 
+impl<T> Writeable for &T 
 where
     T: Writeable,
 {
@@ -46,18 +140,12 @@ where
     }
 }
 
-#[derive(Debug, Error)]
-pub enum Error {
-    #[error("unexpected end of input: failed to read value of type `{0}`")]
-    UnexpectedEof(&'static str)
-}
-
 macro_rules! integer_impl {
     ($($int:ty, $read_fn:tt, $write_fn:tt),* $(,)?) => {
         $(
-            impl Readable for $int {
-                fn read(buffer: &mut Cursor<&[u8]>, _version: ProtocolVersion) -> anyhow::Result<Self> {
-                    buffer.$read_fn::<BigEndian>().map_err(anyhow::Error::from)
+            impl<'de> Readable<'de> for $int {
+                fn read(buffer: &mut Cursor<&'de [u8]>, _version: ProtocolVersion) -> Result<Self, ProtocolError> {
+                    buffer.$read_fn::<BigEndian>().map_err(|_| ProtocolError::UnexpectedEof(stringify!($int)))
                 }
             }
 
@@ -80,16 +168,59 @@ integer_impl! {
     i32, read_i32, write_i32,
     i64, read_i64, write_i64,
 
+    u128, read_u128, write_u128,
+
     f32, read_f32, write_f32,
     f64, read_f64, write_f64,
 }
 
-impl Readable for u8 {
-    fn read(buffer: &mut Cursor<&[u8]>, _version: ProtocolVersion) -> anyhow::Result<Self>
+impl<'de> Readable<'de> for Uuid {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
     where
         Self: Sized,
     {
-        buffer.read_u8().map_err(anyhow::Error::from)
+        Ok(Uuid::from_u128(u128::read(buffer, version)?))
+    }
+}
+
+impl Writeable for Uuid {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        self.as_u128().write(buffer, version)
+    }
+}
+
+impl<'de> Readable<'de> for u8 {
+    fn read(buffer: &mut Cursor<&'de [u8]>, _version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        buffer.read_u8().map_err(|_| ProtocolError::UnexpectedEof("u8"))
+    }
+
+    fn read_many(
+        buffer: &mut Cursor<&'de [u8]>,
+        len: usize,
+        _version: ProtocolVersion,
+    ) -> Result<Cow<'de, [Self]>, ProtocolError> {
+        Ok(Cow::Borrowed(take(buffer, len, "byte array")?))
+    }
+}
+
+impl<'de, const N: usize> Readable<'de> for [u8; N] {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        Ok(*<&[u8; N]>::read(buffer, version)?)
+    }
+}
+
+/// A fixed length byte array, borrowed from the buffer
+impl<'de, const N: usize> Readable<'de> for &'de [u8; N] {
+    fn read(buffer: &mut Cursor<&'de [u8]>, _version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        let bytes = take(buffer, N, "byte array")?;
+        Ok(bytes.try_into().expect("took exactly N bytes"))
+    }
+}
+
+impl<const N: usize> Writeable for [u8; N] {
+    fn write(&self, buffer: &mut Vec<u8>, _version: ProtocolVersion) -> anyhow::Result<()> {
+        buffer.extend_from_slice(self);
+        Ok(())
     }
 }
 
@@ -100,12 +231,12 @@ impl Writeable for u8 {
     }
 }
 
-impl Readable for i8 {
-    fn read(buffer: &mut Cursor<&[u8]>, _version: ProtocolVersion) -> anyhow::Result<Self>
+impl<'de> Readable<'de> for i8 {
+    fn read(buffer: &mut Cursor<&'de [u8]>, _version: ProtocolVersion) -> Result<Self, ProtocolError>
     where
         Self: Sized,
     {
-        buffer.read_i8().map_err(anyhow::Error::from)
+        buffer.read_i8().map_err(|_| ProtocolError::UnexpectedEof("i8"))
     }
 }
 
@@ -116,8 +247,8 @@ impl Writeable for i8 {
     }
 }
 
-impl Readable for bool {
-    fn read(buffer: &mut Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self> 
+impl<'de> Readable<'de> for bool {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
     where  
         Self: Sized,
     {
@@ -126,7 +257,7 @@ impl Readable for bool {
         match x {
             0 => Ok(false),
             1 => Ok(true),
-            _ => Err(anyhow::anyhow!("invalid boolean tag {}", x))
+            _ => Err(ProtocolError::InvalidValue { what: "boolean", value: x.to_string() }),
         }
     }   
 }
@@ -142,11 +273,11 @@ impl Writeable for bool {
 
 
 
-impl<T> Readable for Option<T>
+impl<'de, T> Readable<'de> for Option<T>
 where
-    T: Readable,
+    T: Readable<'de>,
 {
-    fn read(buffer: &mut Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self>
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
     where
         Self: Sized,
     {
@@ -177,10 +308,36 @@ where
     }
 }
 
-
+/// A variable-length i32 as defined in the Minecraft protocol.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct VarInt(pub i32);
 
+impl From<VarInt> for i64 {
+    fn from(value: VarInt) -> Self {
+        value.0.into()
+    }
+}
+
+impl TryFrom<VarInt> for usize {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(value: VarInt) -> Result<Self, Self::Error> {
+        value.0.try_into()
+    }
+}
+
+impl TryFrom<usize> for VarInt {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        Result::Ok(VarInt(value.try_into()?))
+    }
+}
+
 impl VarInt {
+    /// The most bytes a VarInt can take up, since each byte holds 7 bits of the value
+    pub const MAX_LEN: usize = 5;
+
     pub fn write_to(&self, mut writer: impl Write) -> io::Result<usize> {
         let mut x = self.0 as u32;
         let mut i = 0;
@@ -201,39 +358,36 @@ impl VarInt {
         io::Result::Ok(i)
     }
 
+    /// Reads a VarInt, failing if it runs past `MAX_LEN` bytes rather than reading on
     pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
-        let mut num_read = 0;
-        let mut result = 0;
+        let mut result = 0u32;
 
-        loop {
+        for i in 0..Self::MAX_LEN {
             let read = reader.read_u8()?;
-            let value = i32::from(read & 0b0111_1111);
-
-            result |= value.overflowing_shl(7 * num_read).0;
-
-            num_read += 1;
+            // bits past the 32nd in the last byte are dropped, like vanilla does
+            result |= u32::from(read & 0b0111_1111).wrapping_shl(7 * i as u32);
 
-            if num_read > 5 {
-                return Err(io::Error::new(
-                    ErrorKind::InvalidData,
-                    "VarInt too long (max length: 5)",
-                ));
-            }
-
-            if read & 0b100_000 == 0 {
-                break;
+            if read & 0b1000_0000 == 0 {
+                return io::Result::Ok(VarInt(result as i32));
             }
         }
-        io::Result::Ok(VarInt(result))
+
+        Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "VarInt too long (max length: 5)",
+        ))
     }
 }
 
-impl Readable for VarInt {
-    fn read(buffer: &mut Cursor<&[u8]>, _version: ProtocolVersion) -> anyhow::Result<Self>
+impl<'de> Readable<'de> for VarInt {
+    fn read(buffer: &mut Cursor<&'de [u8]>, _version: ProtocolVersion) -> Result<Self, ProtocolError>
     where
         Self: Sized 
     {
-        Self::read_from(buffer).map_err(Into::into)
+        Self::read_from(buffer).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => ProtocolError::UnexpectedEof("VarInt"),
+            _ => ProtocolError::InvalidVarInt("VarInt"),
+        })
     }
 }
 
@@ -260,32 +414,28 @@ impl From<i64> for VarLong {
     }
 }
 
-impl Readable for VarLong {
-    fn read(buffer: &mut Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self>
+impl VarLong {
+    /// The most bytes a VarLong can take up
+    pub const MAX_LEN: usize = 10;
+}
+
+impl<'de> Readable<'de> for VarLong {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
     where
         Self: Sized,
     {
-        let mut num_read = 0;
-        let mut result = 0;
+        let mut result = 0u64;
 
-        loop {
+        for i in 0..Self::MAX_LEN {
             let read = u8::read(buffer, version)?;
-            let value = i64::from(read & 0b0111_1111);
-            result |= value.overflowing_shl(7 * num_read).0;
-
-            num_read += 1;
+            result |= u64::from(read & 0b0111_1111).wrapping_shl(7 * i as u32);
 
-            if num_read > 10 {
-                bail!(
-                    "VarInt too long (max length: 5, value read so far: {})",
-                    result
-                );
-            }
             if read & 0b1000_0000 == 0 {
-                break;
+                return Ok(VarLong(result as i64));
             }
         }
-        Ok(VarLong(result))
+
+        Err(ProtocolError::InvalidVarInt("VarLong"))
     }
 }
 
@@ -310,84 +460,91 @@ impl Writeable for VarLong {
     }
 }
 
-impl Readable for String {
-    fn read(buffer: &mut Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self>
-    where
-        Self: Sized 
-    {
+/// A string borrowed from the buffer, for reading without allocating
+impl<'de> Readable<'de> for &'de str {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
         //Length is a VarInt
         //After the "length" bytes everything is utf8
 
-        let length = VarInt::read(buffer, version)
-            .context("failed to read string length")?
-            .0 as usize;
+        let length = VarInt::read(buffer, version)?.0;
 
-        let max_length = std::i16::MAX as usize;
-        if length > max_length {
-            bail!(
-                "string length {} exceeds maximum allowed length of {}",
-                length,
-                max_length
-            )
-        }
-
-        let mut temp = vec![0u8; length];
-        buffer
-            .read_exact(&mut temp)
-            .map_err(|_| Error::UnexpectedEof("String"))?;
+        let max_length = i16::MAX as usize;
+        let length = usize::try_from(length)
+            .ok()
+            .filter(|&length| length <= max_length)
+            .ok_or(ProtocolError::OversizedLength { what: "string", length: length.into(), max: max_length })?;
 
-        let s = std::str::from_utf8(&temp).context("String contained invalid UTF8")?;
-        Ok(s.to_owned())
+        Ok(std::str::from_utf8(take(buffer, length, "String")?)?)
     }
 }
 
-impl Writeable for String {
+impl Writeable for &str {
     fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
-        let length: VarInt = VarInt(self.len() as i32);
-
-        let max_length: usize = std::i16::MAX as usize;
-        if length.0 as usize > max_length {
+        let max_length: usize = i16::MAX as usize;
+        if self.len() > max_length {
             bail!(
                 "string length {} exceeds maximum allowed length of {}",
-                length.0,
+                self.len(),
                 max_length
             )
         }
-        length.write(buffer, version).context("VarInt format invalid!")?;
+        VarInt(self.len() as i32).write(buffer, version)?;
+        buffer.extend_from_slice(self.as_bytes());
 
-        buffer.write(self.as_bytes()).context("Unable to access string!")?;
-        
         Ok(())
     }
 }
 
+impl<'de> Readable<'de> for String {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        Ok(<&str>::read(buffer, version)?.to_owned())
+    }
+}
+
+impl Writeable for String {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        self.as_str().write(buffer, version)
+    }
+}
+
 pub const MAX_LENGTH: usize = 1024 * 1024;
 
 pub struct LengthPrefixedVec<'a, P, T>(pub Cow<'a, [T]>, PhantomData<P>)
 where
     [T]: ToOwned<Owned = Vec<T>>;
 
-impl<'a, P, T> Readable for LengthPrefixedVec<'a, P, T>
+impl<'a, P, T> Clone for LengthPrefixedVec<'a, P, T>
 where
-    T: Readable,
     [T]: ToOwned<Owned = Vec<T>>,
-    P: TryInto<usize> + Readable,
-    P::Error: std::error::Error + Send + Sync + 'static,
 {
-    fn read(buffer: &mut Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self>
-        where
-            Self: Sized 
-    {
-        let length: usize = P::read(buffer, version)?.try_into()?;
-        
-        if length > MAX_LENGTH {
-            bail!("array length too large ({} > {})", length, MAX_LENGTH);
-        }
-        let vec = iter::repeat_with(|| T::read(buffer, version))
-            .take(length)
-            .collect::<anyhow::Result<Vec<T>>>()?;
+    fn clone(&self) -> Self {
+        Self(self.0.clone(), PhantomData)
+    }
+}
+
+impl<'a, P, T> Default for LengthPrefixedVec<'a, P, T>
+where
+    [T]: ToOwned<Owned = Vec<T>>,
+{
+    fn default() -> Self {
+        Self(Cow::Owned(Vec::new()), PhantomData)
+    }
+}
 
-        Ok(Self(Cow::Owned(vec), PhantomData))
+/// Byte arrays are borrowed from the buffer, anything else is read into a new `Vec`
+impl<'de: 'a, 'a, P, T> Readable<'de> for LengthPrefixedVec<'a, P, T>
+where
+    T: Readable<'de> + Clone + 'de,
+    P: Into<i64> + Readable<'de>,
+{
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        let length: i64 = P::read(buffer, version)?.into();
+        let length = usize::try_from(length)
+            .ok()
+            .filter(|&length| length <= MAX_LENGTH)
+            .ok_or(ProtocolError::OversizedLength { what: "array", length, max: MAX_LENGTH })?;
+
+        Ok(Self(T::read_many(buffer, length, version)?, PhantomData))
     }
 }
 
@@ -403,14 +560,27 @@ where
         Self: Sized 
     {
         P::try_from(self.0.len())?.write(buffer, version)?;
-        self.0
-            .iter()
-            .for_each(|item| item.write(buffer, version).expect("failed to write to vec"));
+        for item in self.0.iter() {
+            item.write(buffer, version)?;
+        }
 
         Ok(())
     }
 }
 
+impl<'a, P, T> LengthPrefixedVec<'a, P, T>
+where
+    [T]: ToOwned<Owned = Vec<T>>,
+{
+    /// Copies the elements if they're borrowed, so the array no longer depends on what it was read from
+    pub fn into_owned(self) -> LengthPrefixedVec<'static, P, T>
+    where
+        T: 'static,
+    {
+        LengthPrefixedVec(Cow::Owned(self.0.into_owned()), PhantomData)
+    }
+}
+
 impl<'a, P, T> From<LengthPrefixedVec<'a, P, T>> for Vec<T>
 where
     [T]: ToOwned<Owned = Vec<T>>,
@@ -442,22 +612,51 @@ where
 pub type VarIntPrefixedVec<'a, T> = LengthPrefixedVec<'a, VarInt, T>;
 pub type ShortPrefixedVec<'a, T> = LengthPrefixedVec<'a, u16, T>;
 
-pub struct GreedyVecU8<'a>(pub Cow<'a, [u8]>);
-impl<'a> Readable for GreedyVecU8<'a> {
-    fn read(buffer: &mut Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self>
+/// A set of bits packed into longs, lowest bit of the first long first
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitSet(pub Vec<u64>);
+
+impl BitSet {
+    pub fn set(&mut self, index: usize) {
+        if self.0.len() <= index / 64 {
+            self.0.resize(index / 64 + 1, 0);
+        }
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        self.0.get(index / 64).is_some_and(|long| long & (1 << (index % 64)) != 0)
+    }
+}
+
+impl<'de> Readable<'de> for BitSet {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
     where
-        Self: Sized 
+        Self: Sized,
     {
-        let mut vec = Vec::new();
-        buffer.read_to_end(&mut vec)?;
-        Ok(GreedyVecU8(Cow::Owned(vec)))
+        Ok(BitSet(VarIntPrefixedVec::<u64>::read(buffer, version)?.into()))
+    }
+}
+
+impl Writeable for BitSet {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        VarIntPrefixedVec::from(&self.0[..]).write(buffer, version)
     }
 }
 
+pub struct GreedyVecU8<'a>(pub Cow<'a, [u8]>);
+/// Borrows whatever's left of the buffer
+impl<'de: 'a, 'a> Readable<'de> for GreedyVecU8<'a> {
+    fn read(buffer: &mut Cursor<&'de [u8]>, _version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        let rest = remaining(buffer);
+        buffer.set_position(buffer.get_ref().len() as u64);
+        Ok(GreedyVecU8(Cow::Borrowed(rest)))
+    }
+}
 
 impl<'a> Writeable for GreedyVecU8<'a> {
     fn write(&self, buffer: &mut Vec<u8>, _version: ProtocolVersion) -> anyhow::Result<()> {
-        buffer.extend_from_slice(&*self.0);
+        buffer.extend_from_slice(&self.0);
         Ok(())
     }
 }
@@ -468,8 +667,221 @@ impl<'a> From<&'a [u8]> for GreedyVecU8<'a> {
     }
 }
 
+impl<'a> From<Vec<u8>> for GreedyVecU8<'a> {
+    fn from(vec: Vec<u8>) -> Self {
+        GreedyVecU8(Cow::Owned(vec))
+    }
+}
+
 impl<'a> From<GreedyVecU8<'a>> for Vec<u8> {
     fn from(x: GreedyVecU8<'a>) -> Self {
         x.0.into_owned()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const VERSION: ProtocolVersion = ProtocolVersion::V1_18_2;
+
+    /// Writes `value`, reads it back, and checks the read used up exactly what was written
+    fn round_trip<T: for<'a> Readable<'a> + Writeable>(value: &T) -> T {
+        read_all(&written(value))
+    }
+
+    fn written<T: Writeable>(value: &T) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        value.write(&mut buffer, VERSION).unwrap();
+        buffer
+    }
+
+    /// Reads a `T` that may borrow from `buffer`, checking it's all used up
+    fn read_all<'a, T: Readable<'a>>(buffer: &'a [u8]) -> T {
+        let mut cursor = Cursor::new(buffer);
+        let read = T::read(&mut cursor, VERSION).unwrap();
+        assert_eq!(cursor.position() as usize, buffer.len(), "read didn't consume everything written");
+        read
+    }
+
+    #[test]
+    fn versions_map_to_protocol_numbers() {
+        for version in ProtocolVersion::ALL {
+            assert_eq!(ProtocolVersion::from_protocol_id(version.protocol_id()), Some(version));
+        }
+        assert!(ProtocolVersion::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(ProtocolVersion::from_protocol_id(760), Some(ProtocolVersion::V1_19_2));
+        assert_eq!(ProtocolVersion::from_protocol_id(757), None);
+        assert_eq!(ProtocolVersion::from_protocol_id(764), None);
+    }
+
+    #[test]
+    fn var_int_matches_known_encodings() {
+        let cases: &[(i32, &[u8])] = &[
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (255, &[0xff, 0x01]),
+            (25565, &[0xdd, 0xc7, 0x01]),
+            (2097151, &[0xff, 0xff, 0x7f]),
+            (i32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+            (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+            (i32::MIN, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+        ];
+
+        for (value, bytes) in cases {
+            let mut buffer = Vec::new();
+            VarInt(*value).write(&mut buffer, VERSION).unwrap();
+            assert_eq!(&buffer, bytes, "encoding {}", value);
+            assert_eq!(VarInt::read_from(*bytes).unwrap(), VarInt(*value));
+        }
+    }
+
+    #[test]
+    fn var_int_stops_after_five_bytes() {
+        let mut cursor = Cursor::new(&[0xff, 0xff, 0xff, 0xff, 0xff, 0x01][..]);
+        assert_eq!(
+            VarInt::read_from(&mut cursor).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(cursor.position(), 5);
+
+        assert!(VarLong::read(&mut Cursor::new(&[0xff; 11][..]), VERSION).is_err());
+    }
+
+    #[test]
+    fn var_int_reports_eof_when_incomplete() {
+        assert_eq!(
+            VarInt::read_from(&[0x80, 0x80][..]).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn length_prefixed_vec_rejects_lengths_past_the_input() {
+        // claims a million entries with nothing after it
+        let mut buffer = Vec::new();
+        VarInt(1_000_000).write(&mut buffer, VERSION).unwrap();
+        assert!(VarIntPrefixedVec::<u64>::read(&mut Cursor::new(&buffer[..]), VERSION).is_err());
+
+        let mut buffer = Vec::new();
+        VarInt(-1).write(&mut buffer, VERSION).unwrap();
+        assert!(VarIntPrefixedVec::<u8>::read(&mut Cursor::new(&buffer[..]), VERSION).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn integers_round_trip(
+            a: u8, b: i8, c: u16, d: u32, e: u64, f: i16, g: i32, h: i64, i: u128, j: bool,
+        ) {
+            prop_assert_eq!(round_trip(&a), a);
+            prop_assert_eq!(round_trip(&b), b);
+            prop_assert_eq!(round_trip(&c), c);
+            prop_assert_eq!(round_trip(&d), d);
+            prop_assert_eq!(round_trip(&e), e);
+            prop_assert_eq!(round_trip(&f), f);
+            prop_assert_eq!(round_trip(&g), g);
+            prop_assert_eq!(round_trip(&h), h);
+            prop_assert_eq!(round_trip(&i), i);
+            prop_assert_eq!(round_trip(&j), j);
+        }
+
+        #[test]
+        fn floats_round_trip(a: f32, b: f64) {
+            // compare bits so NaN still counts as equal to itself
+            prop_assert_eq!(round_trip(&a).to_bits(), a.to_bits());
+            prop_assert_eq!(round_trip(&b).to_bits(), b.to_bits());
+        }
+
+        #[test]
+        fn uuid_round_trips(value: u128) {
+            let uuid = Uuid::from_u128(value);
+            prop_assert_eq!(round_trip(&uuid), uuid);
+        }
+
+        #[test]
+        fn var_int_round_trips(value: i32) {
+            let mut buffer = Vec::new();
+            let written = VarInt(value).write_to(&mut buffer).unwrap();
+            prop_assert_eq!(written, buffer.len());
+            prop_assert!(written <= VarInt::MAX_LEN);
+            prop_assert_eq!(round_trip(&VarInt(value)), VarInt(value));
+        }
+
+        #[test]
+        fn var_long_round_trips(value: i64) {
+            let mut buffer = Vec::new();
+            VarLong(value).write(&mut buffer, VERSION).unwrap();
+            prop_assert!(buffer.len() <= VarLong::MAX_LEN);
+            prop_assert_eq!(round_trip(&VarLong(value)), VarLong(value));
+        }
+
+        #[test]
+        fn var_int_reads_any_bytes_without_panicking(bytes: Vec<u8>) {
+            let mut cursor = Cursor::new(&bytes[..]);
+            let _ = VarInt::read_from(&mut cursor);
+            prop_assert!(cursor.position() as usize <= VarInt::MAX_LEN);
+
+            let mut cursor = Cursor::new(&bytes[..]);
+            let _ = VarLong::read(&mut cursor, VERSION);
+            prop_assert!(cursor.position() as usize <= VarLong::MAX_LEN);
+        }
+
+        #[test]
+        fn strings_round_trip(value in "\\PC{0,256}") {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn options_round_trip(value: Option<i32>, text in proptest::option::of("\\PC{0,16}")) {
+            prop_assert_eq!(round_trip(&value), value);
+            prop_assert_eq!(round_trip(&text), text);
+        }
+
+        #[test]
+        fn references_write_like_their_values(value: i64) {
+            let (mut by_value, mut by_ref) = (Vec::new(), Vec::new());
+            value.write(&mut by_value, VERSION).unwrap();
+            Writeable::write(&&value, &mut by_ref, VERSION).unwrap();
+            prop_assert_eq!(by_value, by_ref);
+        }
+
+        #[test]
+        fn length_prefixed_vecs_round_trip(values: Vec<i32>, strings in proptest::collection::vec("\\PC{0,8}", 0..8)) {
+            let var_int_prefixed = written(&VarIntPrefixedVec::from(values.clone()));
+            prop_assert_eq!(read_all::<VarIntPrefixedVec<i32>>(&var_int_prefixed).0.into_owned(), values.clone());
+
+            let short_prefixed = written(&ShortPrefixedVec::from(values.clone()));
+            prop_assert_eq!(read_all::<ShortPrefixedVec<i32>>(&short_prefixed).0.into_owned(), values);
+
+            let strings_prefixed = written(&VarIntPrefixedVec::from(strings.clone()));
+            prop_assert_eq!(read_all::<VarIntPrefixedVec<String>>(&strings_prefixed).0.into_owned(), strings);
+        }
+
+        #[test]
+        fn bit_sets_round_trip(longs: Vec<u64>, bit in 0usize..1024) {
+            prop_assert_eq!(round_trip(&BitSet(longs.clone())).0, longs);
+
+            let mut set = BitSet::default();
+            set.set(bit);
+            prop_assert!(set.get(bit));
+            prop_assert!(!set.get(bit + 1));
+        }
+
+        #[test]
+        fn greedy_vecs_round_trip(bytes: Vec<u8>) {
+            let buffer = written(&GreedyVecU8::from(bytes.clone()));
+            prop_assert_eq!(read_all::<GreedyVecU8>(&buffer).0.into_owned(), bytes);
+        }
+
+        #[test]
+        fn reads_of_arbitrary_bytes_never_panic(bytes: Vec<u8>) {
+            let _ = String::read(&mut Cursor::new(&bytes[..]), VERSION);
+            let _ = VarIntPrefixedVec::<String>::read(&mut Cursor::new(&bytes[..]), VERSION);
+            let _ = ShortPrefixedVec::<u64>::read(&mut Cursor::new(&bytes[..]), VERSION);
+            let _ = Option::<Uuid>::read(&mut Cursor::new(&bytes[..]), VERSION);
+            let _ = bool::read(&mut Cursor::new(&bytes[..]), VERSION);
+        }
+    }
+}
@@ -1,4 +1,3 @@
-#![allow(dead_code)] //this will get called *eventually*
 use std::{fmt::Display};
 use colored::{Colorize};
 
@@ -1,5 +1,5 @@
 use std::convert::TryFrom;
-use crate::protocol::{structs::{Readable, ProtocolVersion, Writeable}};
+use crate::protocol::{error::ProtocolError, structs::{Readable, ProtocolVersion, Writeable}};
 
 pub mod log;
 
@@ -11,16 +11,21 @@ pub struct Identifier(String);
 
 impl Identifier {
     pub fn new(namespace: String, value: String) -> Self {
-        Identifier((namespace + ":" + &value).into())
+        Identifier(namespace + ":" + &value)
+    }
+
+    /// The full `namespace:value` string
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
 }
 
-impl Readable for Identifier {
-    fn read(buffer: &mut std::io::Cursor<&[u8]>, version: ProtocolVersion) -> anyhow::Result<Self>
+impl<'de> Readable<'de> for Identifier {
+    fn read(buffer: &mut std::io::Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
     where
         Self: Sized
     {
-        Identifier::try_from(String::read(buffer, version)?)
+        Ok(Identifier::try_from(String::read(buffer, version)?)?)
     }
 }
 
@@ -52,8 +57,8 @@ impl From<Angle> for f32 {
     }
 }
 
-impl Readable for Angle {
-    fn read(buffer: &mut std::io::Cursor<&[u8]>, version: crate::protocol::structs::ProtocolVersion) -> anyhow::Result<Self>
+impl<'de> Readable<'de> for Angle {
+    fn read(buffer: &mut std::io::Cursor<&'de [u8]>, version: crate::protocol::structs::ProtocolVersion) -> Result<Self, ProtocolError>
     where
         Self: Sized 
     {
@@ -0,0 +1,799 @@
+//! Vanilla's Anvil world format, which stores chunks as compressed NBT in region files of 32x32 chunks.
+
+use crate::nbt::{Compound, Nbt, NbtLimits, Tag};
+use crate::world::biomes::{biome_id, BIOMES, PLAINS};
+use crate::world::blocks::BlockState;
+use crate::world::palette::{Biomes, Blocks, PaletteKind, PalettedContainer};
+use crate::world::world::{
+    BlockEntity, Chunk, ChunkSection, LightSection, LIGHT_ARRAY_LEN, LIGHT_SECTION_COUNT, MIN_SECTION, SECTION_COUNT,
+};
+use anyhow::{bail, Context};
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::ZlibEncoder,
+    Compression,
+};
+use std::{
+    convert::{TryFrom, TryInto},
+    fs::{self, File, OpenOptions},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The data version 1.18.2 saves chunks with
+pub const DATA_VERSION: i32 = 2975;
+
+/// How many chunks wide and long a region is
+const REGION_SIZE: i32 = 32;
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+/// Region files are made of 4KiB sectors, the first two of which hold the chunk locations and timestamps
+const SECTOR_LEN: usize = 4096;
+const HEADER_SECTORS: usize = 2;
+/// A chunk's sector count is a single byte, so bigger chunks are saved to their own `.mcc` file instead
+const MAX_CHUNK_SECTORS: usize = 255;
+/// Sector offsets are 3 bytes
+const MAX_SECTORS: usize = 1 << 24;
+
+const GZIP: u8 = 1;
+const ZLIB: u8 = 2;
+const UNCOMPRESSED: u8 = 3;
+const LZ4: u8 = 4;
+/// Set on the compression type of chunks that live in a `.mcc` file
+const EXTERNAL: u8 = 0x80;
+/// The most a chunk's NBT can take up once it's decompressed, so a corrupt chunk can't inflate without end.
+/// Far more than any vanilla chunk needs, even one full of stuffed chests
+const MAX_CHUNK_LEN: usize = 64 * 1024 * 1024;
+
+/// The block entity type registry in vanilla's 1.18.2 order, which is what `BlockEntity::kind` refers to
+const BLOCK_ENTITY_TYPES: [&str; 34] = [
+    "minecraft:furnace",
+    "minecraft:chest",
+    "minecraft:trapped_chest",
+    "minecraft:ender_chest",
+    "minecraft:jukebox",
+    "minecraft:dispenser",
+    "minecraft:dropper",
+    "minecraft:sign",
+    "minecraft:mob_spawner",
+    "minecraft:piston",
+    "minecraft:brewing_stand",
+    "minecraft:enchanting_table",
+    "minecraft:end_portal",
+    "minecraft:beacon",
+    "minecraft:skull",
+    "minecraft:daylight_detector",
+    "minecraft:hopper",
+    "minecraft:comparator",
+    "minecraft:banner",
+    "minecraft:structure_block",
+    "minecraft:end_gateway",
+    "minecraft:command_block",
+    "minecraft:shulker_box",
+    "minecraft:bed",
+    "minecraft:conduit",
+    "minecraft:barrel",
+    "minecraft:smoker",
+    "minecraft:blast_furnace",
+    "minecraft:lectern",
+    "minecraft:bell",
+    "minecraft:jigsaw",
+    "minecraft:campfire",
+    "minecraft:beehive",
+    "minecraft:sculk_sensor",
+];
+
+/// The ID of the block entity type with the given name, like `minecraft:chest`
+pub fn block_entity_type_id(name: &str) -> Option<i32> {
+    BLOCK_ENTITY_TYPES.iter().position(|kind| *kind == name).map(|id| id as i32)
+}
+
+/// An open `.mca` file holding a 32x32 area of chunks
+pub struct Region {
+    file: File,
+    /// Where the region file is, which is also where its `.mcc` files go
+    folder: PathBuf,
+    /// For each chunk, its offset in sectors shifted up a byte, with the number of sectors it takes in the
+    /// lowest byte. 0 for chunks that have never been saved.
+    locations: [u32; CHUNKS_PER_REGION],
+    /// When each chunk was last saved, in seconds since the epoch
+    timestamps: [u32; CHUNKS_PER_REGION],
+    /// Which sectors of the file are taken, including the header
+    used: Vec<bool>,
+}
+
+impl Region {
+    /// The name of the region file that the chunk at `x`, `z` is saved in, like `r.0.-1.mca`
+    pub fn file_name(x: i32, z: i32) -> String {
+        format!("r.{}.{}.mca", x.div_euclid(REGION_SIZE), z.div_euclid(REGION_SIZE))
+    }
+
+    /// Opens the region file at `path`, creating an empty one if it doesn't exist
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("unable to open {}", path.display()))?;
+
+        let mut header = vec![0; HEADER_SECTORS * SECTOR_LEN];
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            file.write_all(&header)?;
+        } else if len < header.len() {
+            bail!("{} is too short to be a region file", path.display());
+        } else {
+            file.read_exact(&mut header)?;
+        }
+
+        let entry = |index: usize| u32::from_be_bytes(header[index * 4..index * 4 + 4].try_into().unwrap());
+        let locations = std::array::from_fn(entry);
+        let timestamps = std::array::from_fn(|index| entry(CHUNKS_PER_REGION + index));
+
+        let mut region = Region {
+            file,
+            folder: path.parent().map(Path::to_owned).unwrap_or_default(),
+            locations,
+            timestamps,
+            used: vec![false; len.max(header.len()).div_ceil(SECTOR_LEN)],
+        };
+        region.used[..HEADER_SECTORS].fill(true);
+        for index in 0..CHUNKS_PER_REGION {
+            // chunks pointing somewhere impossible are left alone until they're read, which reports them
+            if let Some((offset, count)) = region.sectors(index) {
+                region.used[offset..offset + count].fill(true);
+            }
+        }
+
+        Ok(region)
+    }
+
+    fn index(x: i32, z: i32) -> usize {
+        (x.rem_euclid(REGION_SIZE) + z.rem_euclid(REGION_SIZE) * REGION_SIZE) as usize
+    }
+
+    /// The first sector and sector count of a saved chunk, if it's been saved somewhere within the file
+    fn sectors(&self, index: usize) -> Option<(usize, usize)> {
+        let location = self.locations[index];
+        let (offset, count) = ((location >> 8) as usize, (location & 0xFF) as usize);
+        (count > 0 && offset >= HEADER_SECTORS && offset + count <= self.used.len()).then_some((offset, count))
+    }
+
+    fn external_path(&self, x: i32, z: i32) -> PathBuf {
+        self.folder.join(format!("c.{}.{}.mcc", x, z))
+    }
+
+    /// When the chunk at `x`, `z` was last saved, in seconds since the epoch, or 0 if it never has been
+    pub fn timestamp(&self, x: i32, z: i32) -> u32 {
+        self.timestamps[Self::index(x, z)]
+    }
+
+    /// Reads the NBT of the chunk at `x`, `z`, which has to be inside this region. `None` if it's never been saved.
+    pub fn read_chunk(&mut self, x: i32, z: i32) -> anyhow::Result<Option<Nbt>> {
+        let index = Self::index(x, z);
+        if self.locations[index] == 0 {
+            return Ok(None);
+        }
+        let (offset, count) = self
+            .sectors(index)
+            .with_context(|| format!("chunk {}, {} is stored outside of its region file", x, z))?;
+
+        let mut sectors = vec![0; count * SECTOR_LEN];
+        self.file.seek(SeekFrom::Start((offset * SECTOR_LEN) as u64))?;
+        self.file.read_exact(&mut sectors)?;
+
+        // the length counts the compression type, but not itself
+        let len = u32::from_be_bytes(sectors[..4].try_into().unwrap()) as usize;
+        if len == 0 || len + 4 > sectors.len() {
+            bail!("chunk {}, {} has an invalid length of {}", x, z, len);
+        }
+        let compression = sectors[4];
+
+        let bytes = if compression & EXTERNAL != 0 {
+            let path = self.external_path(x, z);
+            let data = File::open(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|file| read_limited(file, MAX_CHUNK_LEN))
+                .with_context(|| format!("unable to read {}", path.display()))?;
+            decompress(compression & !EXTERNAL, &data, MAX_CHUNK_LEN)?
+        } else {
+            decompress(compression, &sectors[5..len + 4], MAX_CHUNK_LEN)?
+        };
+
+        let nbt = Nbt::read_named(&mut Cursor::new(&bytes[..]), NbtLimits::FILE)
+            .with_context(|| format!("chunk {}, {} is invalid NBT", x, z))?;
+        Ok(Some(nbt))
+    }
+
+    /// Saves `nbt` as the chunk at `x`, `z`, which has to be inside this region
+    pub fn write_chunk(&mut self, x: i32, z: i32, nbt: &Nbt) -> anyhow::Result<()> {
+        let mut raw = Vec::new();
+        nbt.write_named(&mut raw)?;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw)?;
+        let compressed = encoder.finish()?;
+
+        let external = self.external_path(x, z);
+        let mut payload;
+        if compressed.len() + 5 > MAX_CHUNK_SECTORS * SECTOR_LEN {
+            fs::write(&external, &compressed).with_context(|| format!("unable to write {}", external.display()))?;
+            payload = 1u32.to_be_bytes().to_vec();
+            payload.push(ZLIB | EXTERNAL);
+        } else {
+            payload = (compressed.len() as u32 + 1).to_be_bytes().to_vec();
+            payload.push(ZLIB);
+            payload.extend_from_slice(&compressed);
+
+            // the chunk might have been too big the last time it was saved
+            match fs::remove_file(&external) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+                _ => {}
+            }
+        }
+
+        let count = payload.len().div_ceil(SECTOR_LEN);
+        payload.resize(count * SECTOR_LEN, 0);
+
+        // free the old sectors first, so a chunk that's still the same size is rewritten in place
+        let index = Self::index(x, z);
+        if let Some((offset, count)) = self.sectors(index) {
+            self.used[offset..offset + count].fill(false);
+        }
+        let offset = self.allocate(count)?;
+        self.file.seek(SeekFrom::Start((offset * SECTOR_LEN) as u64))?;
+        self.file.write_all(&payload)?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() as u32);
+        self.locations[index] = (offset as u32) << 8 | count as u32;
+        self.timestamps[index] = timestamp;
+        self.file.seek(SeekFrom::Start(index as u64 * 4))?;
+        self.file.write_all(&self.locations[index].to_be_bytes())?;
+        self.file.seek(SeekFrom::Start((SECTOR_LEN + index * 4) as u64))?;
+        self.file.write_all(&timestamp.to_be_bytes())?;
+
+        Ok(())
+    }
+
+    /// Finds the first run of `count` free sectors, growing the file if there isn't one, and marks them as used
+    fn allocate(&mut self, count: usize) -> anyhow::Result<usize> {
+        let mut run = 0;
+        let mut start = None;
+        for (sector, &used) in self.used.iter().enumerate() {
+            run = if used { 0 } else { run + 1 };
+            if run == count {
+                start = Some(sector + 1 - count);
+                break;
+            }
+        }
+
+        // otherwise carry on from whatever's free at the end of the file
+        let start = start.unwrap_or(self.used.len() - run);
+        if start + count > MAX_SECTORS {
+            bail!("region file is full");
+        }
+        if start + count > self.used.len() {
+            self.used.resize(start + count, false);
+        }
+        self.used[start..start + count].fill(true);
+
+        Ok(start)
+    }
+}
+
+/// Decompresses a chunk, failing if it comes to more than `limit` bytes
+fn decompress(compression: u8, data: &[u8], limit: usize) -> anyhow::Result<Vec<u8>> {
+    match compression {
+        GZIP => read_limited(GzDecoder::new(data), limit),
+        ZLIB => read_limited(ZlibDecoder::new(data), limit),
+        UNCOMPRESSED => read_limited(data, limit),
+        LZ4 => bail!("LZ4 compressed chunks aren't supported"),
+        _ => bail!("unknown chunk compression type {}", compression),
+    }
+}
+
+/// Reads everything `reader` has, failing if it comes to more than `limit` bytes
+fn read_limited(reader: impl Read, limit: usize) -> anyhow::Result<Vec<u8>> {
+    // reading a byte past the limit is enough to know it's too big, without reading the rest
+    let mut bytes = Vec::new();
+    reader.take(limit as u64 + 1).read_to_end(&mut bytes)?;
+    if bytes.len() > limit {
+        bail!("chunk is bigger than the {} bytes allowed", limit);
+    }
+    Ok(bytes)
+}
+
+/// Looks up `key` in `compound`, as the type `as_type` picks out
+fn field<'a, T>(compound: &'a Compound, key: &str, as_type: fn(&'a Tag) -> Option<T>) -> anyhow::Result<T> {
+    compound
+        .get(key)
+        .and_then(as_type)
+        .with_context(|| format!("missing or invalid `{}`", key))
+}
+
+impl Chunk {
+    /// Reads a chunk out of the NBT a region file stores it as. `None` if the chunk hasn't finished generating,
+    /// since the server can't pick up where vanilla left off.
+    ///
+    /// Light that isn't saved is taken to be full sky light and no block light.
+    pub fn from_nbt(nbt: &Nbt) -> anyhow::Result<Option<Chunk>> {
+        let root = &nbt.root;
+        if field(root, "Status", Tag::as_str)?.trim_start_matches("minecraft:") != "full" {
+            return Ok(None);
+        }
+        let mut chunk = Chunk::new(field(root, "xPos", Tag::as_int)?, field(root, "zPos", Tag::as_int)?);
+
+        for section in field(root, "sections", Tag::as_list)? {
+            let section = section.as_compound().context("sections should be compounds")?;
+            let y = field(section, "Y", Tag::as_byte)? as i32;
+            let index = y - MIN_SECTION + 1;
+            if index < 0 || index as usize >= LIGHT_SECTION_COUNT {
+                bail!("section {} is outside of the world", y);
+            }
+
+            if let Some(light) = section.get("SkyLight") {
+                chunk.set_sky_light(y, read_light(light).context("invalid SkyLight")?);
+            }
+            if let Some(light) = section.get("BlockLight") {
+                chunk.set_block_light(y, read_light(light).context("invalid BlockLight")?);
+            }
+
+            // the sections either side of the world only have light
+            if index == 0 || index as usize > SECTION_COUNT {
+                continue;
+            }
+            let blocks = match section.get("block_states") {
+                Some(states) => read_block_states(states.as_compound().context("invalid block_states")?)
+                    .with_context(|| format!("invalid block states in section {}", y))?,
+                None => PalettedContainer::filled(BlockState::AIR),
+            };
+            let biomes = match section.get("biomes") {
+                Some(biomes) => read_biomes(biomes.as_compound().context("invalid biomes")?)
+                    .with_context(|| format!("invalid biomes in section {}", y))?,
+                None => PalettedContainer::filled(PLAINS),
+            };
+            chunk.sections[index as usize - 1] = ChunkSection::new(blocks, biomes);
+        }
+
+        for block_entity in root.get("block_entities").and_then(Tag::as_list).unwrap_or_default() {
+            let mut data = block_entity.as_compound().context("block entities should be compounds")?.clone();
+            let id = data.remove("id");
+            let id = id.as_ref().and_then(Tag::as_str).context("block entity has no id")?;
+            let kind = block_entity_type_id(id).with_context(|| format!("unknown block entity {}", id))?;
+
+            let x = field(&data, "x", Tag::as_int)?;
+            let y = field(&data, "y", Tag::as_int)?;
+            let z = field(&data, "z", Tag::as_int)?;
+            for key in ["x", "y", "z", "keepPacked"] {
+                data.remove(key);
+            }
+
+            chunk.set_block_entity(
+                (x & 15) as u8,
+                y as i16,
+                (z & 15) as u8,
+                BlockEntity { kind, data },
+            );
+        }
+
+        Ok(Some(chunk))
+    }
+
+    /// The NBT that a region file stores this chunk as. Fails if a block entity has a type that isn't in the
+    /// registry
+    pub fn to_nbt(&self) -> anyhow::Result<Nbt> {
+        let mut sections = Vec::new();
+        for index in 0..LIGHT_SECTION_COUNT {
+            let mut section = Compound::new();
+            if let Some(blocks) = index.checked_sub(1).and_then(|index| self.sections.get(index)) {
+                section.insert("block_states".to_owned(), write_block_states(&blocks.blocks));
+                section.insert("biomes".to_owned(), write_biomes(&blocks.biomes));
+            }
+            // only light that differs from what `from_nbt` assumes needs saving
+            if let Some(light) = write_light(&self.sky_light[index], &LightSection::Full) {
+                section.insert("SkyLight".to_owned(), light);
+            }
+            if let Some(light) = write_light(&self.block_light[index], &LightSection::Empty) {
+                section.insert("BlockLight".to_owned(), light);
+            }
+
+            if !section.is_empty() {
+                section.insert("Y".to_owned(), Tag::Byte((index as i32 + MIN_SECTION - 1) as i8));
+                sections.push(Tag::Compound(section));
+            }
+        }
+
+        let block_entities = self
+            .block_entities
+            .iter()
+            .map(|(&(x, y, z), block_entity)| {
+                let kind = usize::try_from(block_entity.kind)
+                    .ok()
+                    .and_then(|kind| BLOCK_ENTITY_TYPES.get(kind))
+                    .with_context(|| format!("unknown block entity type {}", block_entity.kind))?;
+                let mut data = block_entity.data.clone();
+                data.insert("id".to_owned(), Tag::String((*kind).to_owned()));
+                data.insert("x".to_owned(), Tag::Int(self.x_pos() * 16 + x as i32));
+                data.insert("y".to_owned(), Tag::Int(y as i32));
+                data.insert("z".to_owned(), Tag::Int(self.z_pos() * 16 + z as i32));
+                data.insert("keepPacked".to_owned(), Tag::Byte(0));
+                Ok(Tag::Compound(data))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let heightmap = Tag::LongArray(self.heightmap().into_iter().map(|long| long as i64).collect());
+        let mut heightmaps = Compound::new();
+        heightmaps.insert("MOTION_BLOCKING".to_owned(), heightmap.clone());
+        heightmaps.insert("WORLD_SURFACE".to_owned(), heightmap);
+
+        let mut root = Compound::new();
+        root.insert("DataVersion".to_owned(), Tag::Int(DATA_VERSION));
+        root.insert("xPos".to_owned(), Tag::Int(self.x_pos()));
+        root.insert("yPos".to_owned(), Tag::Int(MIN_SECTION));
+        root.insert("zPos".to_owned(), Tag::Int(self.z_pos()));
+        root.insert("Status".to_owned(), Tag::String("full".to_owned()));
+        root.insert("LastUpdate".to_owned(), Tag::Long(0));
+        root.insert("InhabitedTime".to_owned(), Tag::Long(0));
+        // there's no light engine yet, so have vanilla light the chunk itself
+        root.insert("isLightOn".to_owned(), Tag::Byte(0));
+        root.insert("sections".to_owned(), Tag::List(sections));
+        root.insert("block_entities".to_owned(), Tag::List(block_entities));
+        root.insert("Heightmaps".to_owned(), Tag::Compound(heightmaps));
+
+        Ok(root.into())
+    }
+}
+
+fn read_light(tag: &Tag) -> anyhow::Result<LightSection> {
+    match tag.as_byte_array() {
+        Some(bytes) if bytes.len() == LIGHT_ARRAY_LEN => {
+            if bytes.iter().all(|&byte| byte == 0) {
+                return Ok(LightSection::Empty);
+            } else if bytes.iter().all(|&byte| byte == -1) {
+                return Ok(LightSection::Full);
+            }
+
+            let mut light = Box::new([0; LIGHT_ARRAY_LEN]);
+            for (nibbles, byte) in light.iter_mut().zip(bytes) {
+                *nibbles = *byte as u8;
+            }
+            Ok(LightSection::Data(light))
+        }
+        _ => bail!("light should be {} bytes", LIGHT_ARRAY_LEN),
+    }
+}
+
+fn write_light(light: &LightSection, default: &LightSection) -> Option<Tag> {
+    let bytes = match light {
+        light if light == default => return None,
+        LightSection::Empty => vec![0; LIGHT_ARRAY_LEN],
+        LightSection::Full => vec![-1; LIGHT_ARRAY_LEN],
+        LightSection::Data(data) => data.iter().map(|&nibbles| nibbles as i8).collect(),
+    };
+    Some(Tag::ByteArray(bytes))
+}
+
+/// Reads a `{palette, data}` compound, leaving the turning of palette entries into values to `read_entry`
+fn read_container<K: PaletteKind>(
+    container: &Compound,
+    read_entry: impl Fn(&Tag) -> anyhow::Result<K::Value>,
+) -> anyhow::Result<PalettedContainer<K>> {
+    let palette = field(container, "palette", Tag::as_list)?
+        .iter()
+        .map(read_entry)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let data = match container.get("data") {
+        Some(data) => data.as_long_array().context("invalid `data`")?.iter().map(|&long| long as u64).collect(),
+        None => Vec::new(),
+    };
+
+    PalettedContainer::from_palette(&palette, data).context("data doesn't match the palette")
+}
+
+fn write_container<K: PaletteKind>(container: &PalettedContainer<K>, write_entry: impl Fn(K::Value) -> Tag) -> Tag {
+    let (palette, data) = container.to_palette();
+
+    let mut compound = Compound::new();
+    compound.insert("palette".to_owned(), Tag::List(palette.into_iter().map(write_entry).collect()));
+    if !data.is_empty() {
+        compound.insert("data".to_owned(), Tag::LongArray(data.into_iter().map(|long| long as i64).collect()));
+    }
+    Tag::Compound(compound)
+}
+
+fn read_block_states(states: &Compound) -> anyhow::Result<PalettedContainer<Blocks>> {
+    read_container(states, |entry| {
+        let entry = entry.as_compound().context("palette entries should be compounds")?;
+        let name = field(entry, "Name", Tag::as_str)?;
+        let mut state = BlockState::from_name(name).with_context(|| format!("unknown block {}", name))?;
+
+        if let Some(properties) = entry.get("Properties") {
+            for (key, value) in properties.as_compound().context("invalid `Properties`")? {
+                let value = value.as_str().context("property values should be strings")?;
+                state = state
+                    .with_property(key, value)
+                    .with_context(|| format!("{} has no property {}={}", name, key, value))?;
+            }
+        }
+
+        Ok(state)
+    })
+}
+
+fn write_block_states(blocks: &PalettedContainer<Blocks>) -> Tag {
+    write_container(blocks, |state| {
+        let mut entry = Compound::new();
+        entry.insert("Name".to_owned(), Tag::String(state.name().to_owned()));
+
+        let properties: Compound = state
+            .properties()
+            .map(|(key, value)| (key.to_owned(), Tag::String(value.to_owned())))
+            .collect();
+        if !properties.is_empty() {
+            entry.insert("Properties".to_owned(), Tag::Compound(properties));
+        }
+
+        Tag::Compound(entry)
+    })
+}
+
+fn read_biomes(biomes: &Compound) -> anyhow::Result<PalettedContainer<Biomes>> {
+    read_container(biomes, |entry| {
+        let name = entry.as_str().context("biome palette entries should be strings")?;
+        biome_id(name).with_context(|| format!("unknown biome {}", name))
+    })
+}
+
+fn write_biomes(biomes: &PalettedContainer<Biomes>) -> Tag {
+    write_container(biomes, |biome| Tag::String(BIOMES[biome as usize].to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::generation::ChunkGenerator;
+    use crate::protocol::structs::ProtocolVersion;
+    use crate::world::world::Dimension;
+    use std::{env, process, thread, time::{Duration, Instant}};
+    use uuid::Uuid;
+
+    /// A fresh folder to put region files in
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("ferrous-anvil-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn test_chunk(x: i32, z: i32) -> Chunk {
+        let mut chunk = Chunk::new(x, z);
+        chunk.set_blockstate(BlockState::BEDROCK, 0, -64, 0);
+        chunk.set_blockstate("oak_stairs[facing=east,half=top]".parse().unwrap(), 3, 100, 12);
+        chunk.section(0).set_biome(50, 1, 2, 3);
+        chunk.set_block_entity(3, 101, 12, BlockEntity {
+            kind: 1,
+            data: Compound::from([("Lock".to_owned(), Tag::String("key".to_owned()))]),
+        });
+        chunk.set_block_light(2, LightSection::Data(Box::new([0x12; LIGHT_ARRAY_LEN])));
+        chunk.set_sky_light(-5, LightSection::Empty);
+        chunk
+    }
+
+    /// Bytes that zlib can't do much with
+    fn noise(len: usize) -> Vec<i8> {
+        let mut state = 0x2545F491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as i8
+            })
+            .collect()
+    }
+
+    fn assert_same_chunk(read: &Chunk, written: &Chunk) {
+        assert_eq!((read.x_pos(), read.z_pos()), (written.x_pos(), written.z_pos()));
+        for (read, written) in read.sections.iter().zip(&written.sections) {
+            assert_eq!(read.block_count(), written.block_count());
+            for index in 0..Blocks::LEN {
+                assert_eq!(read.blocks.get(index), written.blocks.get(index));
+            }
+            for index in 0..Biomes::LEN {
+                assert_eq!(read.biomes.get(index), written.biomes.get(index));
+            }
+        }
+        assert_eq!(read.sky_light, written.sky_light);
+        assert_eq!(read.block_light, written.block_light);
+        assert_eq!(read.block_entities.len(), written.block_entities.len());
+        for (position, block_entity) in &written.block_entities {
+            assert_eq!(read.block_entities[position].kind, block_entity.kind);
+            assert_eq!(read.block_entities[position].data, block_entity.data);
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip_through_nbt() {
+        let mut chunk = test_chunk(-3, 7);
+        // more distinct states than the network palette holds
+        for index in 0..4096 {
+            let state = BlockState::from_id(index as u16 % 500 + 1).unwrap();
+            chunk.section(4).set_blockstate(state, index & 15, index >> 8, index >> 4 & 15);
+        }
+
+        let nbt = chunk.to_nbt().unwrap();
+        assert_eq!(nbt.root["DataVersion"], Tag::Int(DATA_VERSION));
+        let block_entity = nbt.root["block_entities"].as_list().unwrap()[0].as_compound().unwrap();
+        assert_eq!(block_entity["id"], Tag::String("minecraft:chest".to_owned()));
+        assert_eq!(block_entity["x"], Tag::Int(-3 * 16 + 3));
+
+        assert_same_chunk(&Chunk::from_nbt(&nbt).unwrap().unwrap(), &chunk);
+    }
+
+    #[test]
+    fn unknown_block_entity_types_are_errors() {
+        let mut chunk = Chunk::new(0, 0);
+        chunk.set_block_entity(0, 0, 0, BlockEntity { kind: BLOCK_ENTITY_TYPES.len() as i32, data: Compound::new() });
+        assert!(chunk.to_nbt().is_err());
+        chunk.set_block_entity(0, 0, 0, BlockEntity { kind: -1, data: Compound::new() });
+        assert!(chunk.to_nbt().is_err());
+    }
+
+    #[test]
+    fn chunks_are_only_decompressed_up_to_the_limit() {
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&[0; 1000]).unwrap();
+        let zlib = zlib.finish().unwrap();
+
+        assert_eq!(decompress(ZLIB, &zlib, 1000).unwrap().len(), 1000);
+        assert!(decompress(ZLIB, &zlib, 999).is_err());
+        assert!(decompress(UNCOMPRESSED, &[0; 1000], 999).is_err());
+    }
+
+    #[test]
+    fn unfinished_and_invalid_chunks() {
+        let mut nbt = test_chunk(0, 0).to_nbt().unwrap();
+        nbt.root.insert("Status".to_owned(), Tag::String("minecraft:features".to_owned()));
+        assert!(Chunk::from_nbt(&nbt).unwrap().is_none());
+
+        let mut nbt = test_chunk(0, 0).to_nbt().unwrap();
+        nbt.root.remove("xPos");
+        assert!(Chunk::from_nbt(&nbt).is_err());
+
+        let mut nbt = test_chunk(0, 0).to_nbt().unwrap();
+        let palette = Tag::List(vec![Tag::Compound(Compound::from([(
+            "Name".to_owned(),
+            Tag::String("minecraft:not_a_block".to_owned()),
+        )]))]);
+        let section = Compound::from([
+            ("Y".to_owned(), Tag::Byte(0)),
+            ("block_states".to_owned(), Tag::Compound(Compound::from([("palette".to_owned(), palette)]))),
+        ]);
+        nbt.root.insert("sections".to_owned(), Tag::List(vec![Tag::Compound(section)]));
+        assert!(Chunk::from_nbt(&nbt).is_err());
+    }
+
+    #[test]
+    fn regions_store_chunks() {
+        let folder = temp_folder("store");
+        let path = folder.join(Region::file_name(33, -2));
+        assert_eq!(path.file_name().unwrap(), "r.1.-1.mca");
+
+        let small = test_chunk(33, -2).to_nbt().unwrap();
+        let mut big = test_chunk(34, -2).to_nbt().unwrap();
+        big.root.insert("Noise".to_owned(), Tag::ByteArray(noise(MAX_CHUNK_SECTORS * SECTOR_LEN)));
+
+        let mut region = Region::open(&path).unwrap();
+        assert!(region.read_chunk(33, -2).unwrap().is_none());
+        region.write_chunk(33, -2, &small).unwrap();
+        region.write_chunk(34, -2, &big).unwrap();
+        assert!(region.timestamp(33, -2) > 0);
+        assert!(folder.join("c.34.-2.mcc").exists());
+
+        // read back through a fresh handle, so nothing comes from memory
+        let mut region = Region::open(&path).unwrap();
+        assert_eq!(region.read_chunk(33, -2).unwrap(), Some(small.clone()));
+        assert_eq!(region.read_chunk(34, -2).unwrap(), Some(big));
+        let len = fs::metadata(&path).unwrap().len();
+        assert_eq!(len % SECTOR_LEN as u64, 0);
+
+        // shrinking the big chunk brings it back into the region, and rewriting chunks reuses their sectors
+        region.write_chunk(34, -2, &small).unwrap();
+        region.write_chunk(33, -2, &small).unwrap();
+        assert!(!folder.join("c.34.-2.mcc").exists());
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(region.read_chunk(34, -2).unwrap(), Some(small));
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn other_compression_types_are_read() {
+        let folder = temp_folder("compression");
+        let path = folder.join("r.0.0.mca");
+        let nbt = test_chunk(0, 0).to_nbt().unwrap();
+        let mut raw = Vec::new();
+        nbt.write_named(&mut raw).unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&raw).unwrap();
+
+        // chunk 0, 0 is gzipped and chunk 1, 0 isn't compressed at all
+        let mut file = vec![0; HEADER_SECTORS * SECTOR_LEN];
+        for (index, (compression, data)) in vec![(GZIP, gzip.finish().unwrap()), (UNCOMPRESSED, raw)].into_iter().enumerate() {
+            let offset = file.len() / SECTOR_LEN;
+            file.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+            file.push(compression);
+            file.extend_from_slice(&data);
+            let count = (file.len() / SECTOR_LEN + 1) - offset;
+            file.resize((offset + count) * SECTOR_LEN, 0);
+            file[index * 4..index * 4 + 4].copy_from_slice(&((offset as u32) << 8 | count as u32).to_be_bytes());
+        }
+        // and chunk 2, 0 points past the end of the file
+        file[8..12].copy_from_slice(&(100u32 << 8 | 1).to_be_bytes());
+        fs::write(&path, file).unwrap();
+
+        let mut region = Region::open(&path).unwrap();
+        assert_eq!(region.read_chunk(0, 0).unwrap(), Some(nbt.clone()));
+        assert_eq!(region.read_chunk(1, 0).unwrap(), Some(nbt));
+        assert!(region.read_chunk(2, 0).is_err());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn dimensions_page_chunks_in_and_out() {
+        let folder = temp_folder("dimension");
+        let mut dimension = Dimension::new(Some(folder.join("region")), ChunkGenerator::new(Chunk::new, 1), 10, 10);
+        assert!(dimension.load_chunk(5, 5).unwrap().is_none());
+
+        dimension.insert_chunk(test_chunk(5, 5));
+        let chunk = dimension.unload_chunk(5, 5).unwrap().unwrap();
+        assert!(dimension.chunk(5, 5).is_none());
+        assert!(folder.join("region").join("r.0.0.mca").exists());
+
+        let loaded = dimension.load_chunk(5, 5).unwrap().unwrap();
+        assert_same_chunk(loaded, &chunk);
+        assert!(dimension.chunk(5, 5).is_some());
+
+        // the region stays open while one of its chunks is loaded, and no longer
+        dimension.insert_chunk(test_chunk(6, 5));
+        dimension.unload_chunk(5, 5).unwrap();
+        assert_eq!(dimension.regions.len(), 1);
+        dimension.unload_chunk(6, 5).unwrap();
+        assert!(dimension.regions.is_empty());
+
+        // a chunk saved under the wrong coords is loaded where it was found, not over the chunk it names
+        let mut region = Region::open(&folder.join("region").join("r.0.0.mca")).unwrap();
+        region.write_chunk(7, 5, &test_chunk(5, 5).to_nbt().unwrap()).unwrap();
+        drop(region);
+        let loaded = dimension.load_chunk(7, 5).unwrap().unwrap();
+        assert_eq!((loaded.x_pos(), loaded.z_pos()), (7, 5));
+        assert!(dimension.chunk(5, 5).is_none());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn unreadable_chunks_are_made_again() {
+        let folder = temp_folder("unreadable");
+        let mut dimension = Dimension::new(Some(folder.join("region")), ChunkGenerator::new(Chunk::new, 1), 2, 2);
+        dimension.insert_chunk(test_chunk(0, 5));
+        dimension.unload_chunk(0, 5).unwrap();
+        // something that can't be opened as a region file, where the view spills over into the next region
+        fs::create_dir_all(folder.join("region").join("r.-1.0.mca")).unwrap();
+
+        dimension.move_viewer(Uuid::from_u128(1), ProtocolVersion::V1_18_2, 0, 5).unwrap();
+        assert_same_chunk(dimension.chunk(0, 5).unwrap(), &test_chunk(0, 5));
+
+        let start = Instant::now();
+        while dimension.chunk(-1, 5).is_none() {
+            assert!(start.elapsed() < Duration::from_secs(10), "chunk took too long to generate");
+            dimension.receive_generated().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
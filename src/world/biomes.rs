@@ -0,0 +1,58 @@
+use crate::protocol::structs::ProtocolVersion;
+
+// the registry generated by build.rs from data/1.18.2/codec.json, and what its biome IDs are on newer versions
+include!(concat!(env!("OUT_DIR"), "/biomes.rs"));
+
+/// How clients on some version number biomes, which is the order of the biome registry in the codec they're sent
+/// at login
+struct NetworkIds {
+    /// What each of our biome IDs is on that version, or `None` if it's the same
+    ids: Option<&'static [u8]>,
+    /// The bits a global biome ID takes up on that version
+    bits: u8,
+}
+
+/// The biome sections fall back to when a chunk doesn't say
+pub const PLAINS: u32 = 1;
+
+/// The ID of the biome with the given name, like `minecraft:plains`
+pub fn biome_id(name: &str) -> Option<u32> {
+    BIOMES.iter().position(|biome| *biome == name).map(|id| id as u32)
+}
+
+/// The name of the biome with the given ID
+pub fn biome_name(id: u32) -> Option<&'static str> {
+    BIOMES.get(id as usize).copied()
+}
+
+/// The ID clients on `version` know biome `id` by, if it's a biome and there's a codec for that version
+pub fn network_id(id: u32, version: ProtocolVersion) -> Option<u32> {
+    let ids = network_ids(version)?;
+    biome_name(id)?;
+    Some(ids.ids.map_or(id, |ids| ids[id as usize] as u32))
+}
+
+/// The bits a global biome ID takes up on `version`, if there's a codec for it
+pub fn network_bits(version: ProtocolVersion) -> Option<u8> {
+    network_ids(version).map(|ids| ids.bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn biomes_are_renumbered_for_newer_versions() {
+        let forest = biome_id("minecraft:forest").unwrap();
+        assert_eq!(forest, 7);
+        assert_eq!(network_id(forest, ProtocolVersion::V1_18_2), Some(7));
+        // 1.19 added mangrove swamps just before
+        assert_eq!(network_id(forest, ProtocolVersion::V1_19), Some(8));
+        assert_eq!(network_id(forest, ProtocolVersion::V1_19_2), Some(8));
+        assert_eq!(network_id(PLAINS, ProtocolVersion::V1_19), Some(1));
+
+        assert_eq!(network_id(forest, ProtocolVersion::V1_20_1), None);
+        assert_eq!(network_id(BIOMES.len() as u32, ProtocolVersion::V1_18_2), None);
+        assert_eq!(network_bits(ProtocolVersion::V1_19), Some(6));
+    }
+}
@@ -1,6 +1,290 @@
-pub struct BlockState {
-    //
+use crate::protocol::structs::ProtocolVersion;
+use crate::util::Identifier;
+use anyhow::{bail, Context};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// A block along with its properties, stored as its global state ID
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockState(u16);
+
+// the registry generated by build.rs from data/1.18.2/blocks.json, and what its state IDs are on newer versions
+include!(concat!(env!("OUT_DIR"), "/blocks.rs"));
+
+/// How clients on some version number block states. Every version since 1.18.2 added blocks, which shifts the
+/// IDs of everything after them
+struct NetworkIds {
+    /// What each of our state IDs is on that version, or `None` if it's the same
+    ids: Option<&'static [u16]>,
+    /// The bits a global state ID takes up on that version
+    bits: u8,
 }
+
+/// Whether chunks can be sent to a client on `version` without its blocks coming out wrong
+pub fn has_registry_for(version: ProtocolVersion) -> bool {
+    network_ids(version).is_some()
+}
+
+/// The versions chunks can be sent to, oldest first
+pub fn registry_versions() -> impl Iterator<Item = ProtocolVersion> {
+    ProtocolVersion::ALL.iter().copied().filter(|&version| has_registry_for(version))
+}
+
+/// The bits a global block state ID takes up on `version`, if there's a registry for it
+pub fn network_bits(version: ProtocolVersion) -> Option<u8> {
+    network_ids(version).map(|ids| ids.bits)
+}
+
+/// A block in the registry, which owns a contiguous run of state IDs
+struct BlockInfo {
+    name: &'static str,
+    properties: &'static [Property],
+    first_state: u16,
+    default_state: u16,
+}
+
+struct Property {
+    name: &'static str,
+    values: &'static [&'static str],
+}
+
+impl BlockInfo {
+    fn by_name(name: &str) -> Option<&'static BlockInfo> {
+        BLOCKS_BY_NAME
+            .binary_search_by_key(&name, |&(name, _)| name)
+            .ok()
+            .map(|index| &BLOCKS[BLOCKS_BY_NAME[index].1 as usize])
+    }
+
+    /// How many states apart two neighbouring values of property `index` are. The last property changes fastest.
+    fn stride(&self, index: usize) -> usize {
+        self.properties[index + 1..]
+            .iter()
+            .map(|property| property.values.len())
+            .product()
+    }
+}
+
 impl BlockState {
-    //
-}
\ No newline at end of file
+    /// Looks up a state by its global ID, if there's a state with that ID
+    pub fn from_id(id: u16) -> Option<Self> {
+        (id < BLOCK_STATE_COUNT).then_some(BlockState(id))
+    }
+
+    /// The global ID, which is what the protocol and palettes refer to states by
+    pub fn id(self) -> u16 {
+        self.0
+    }
+
+    /// The ID clients on `version` know this state by, if there's a registry for that version
+    pub fn network_id(self, version: ProtocolVersion) -> Option<u16> {
+        network_ids(version).map(|ids| ids.ids.map_or(self.0, |ids| ids[self.0 as usize]))
+    }
+
+    /// The default state of the block called `name`, like `minecraft:stone`
+    pub fn from_name(name: &str) -> Option<Self> {
+        BlockInfo::by_name(name).map(|block| BlockState(block.default_state))
+    }
+
+    fn block(self) -> &'static BlockInfo {
+        let index = BLOCKS.partition_point(|block| block.first_state <= self.0) - 1;
+        &BLOCKS[index]
+    }
+
+    /// The identifier of this state's block, like `minecraft:oak_stairs`
+    pub fn name(self) -> &'static str {
+        self.block().name
+    }
+
+    /// Whether this is one of the air blocks, which don't count towards a section's block count
+    pub fn is_air(self) -> bool {
+        matches!(self, BlockState::AIR | BlockState::VOID_AIR | BlockState::CAVE_AIR)
+    }
+
+    /// Whether this is the block's default state
+    pub fn is_default(self) -> bool {
+        self.block().default_state == self.0
+    }
+
+    /// This state's value for `property`, if its block has that property
+    pub fn get_property(self, property: &str) -> Option<&'static str> {
+        let block = self.block();
+        let index = block.properties.iter().position(|p| p.name == property)?;
+        let values = block.properties[index].values;
+        let offset = (self.0 - block.first_state) as usize;
+
+        Some(values[offset / block.stride(index) % values.len()])
+    }
+
+    /// The state of the same block with `property` set to `value`, if the block has that property and value
+    pub fn with_property(self, property: &str, value: &str) -> Option<Self> {
+        let block = self.block();
+        let index = block.properties.iter().position(|p| p.name == property)?;
+        let values = block.properties[index].values;
+        let new = values.iter().position(|v| *v == value)?;
+        let stride = block.stride(index);
+
+        let offset = (self.0 - block.first_state) as usize;
+        let old = offset / stride % values.len();
+        Some(BlockState((offset + new * stride - old * stride) as u16 + block.first_state))
+    }
+
+    /// Every property of this state's block, along with this state's value for it
+    pub fn properties(self) -> impl Iterator<Item = (&'static str, &'static str)> {
+        self.block()
+            .properties
+            .iter()
+            .map(move |property| (property.name, self.get_property(property.name).unwrap()))
+    }
+
+    /// The block's identifier, with this state's properties dropped
+    pub fn identifier(self) -> Identifier {
+        Identifier::try_from(self.name().to_owned()).expect("block names are valid identifiers")
+    }
+}
+
+impl TryFrom<&Identifier> for BlockState {
+    type Error = anyhow::Error;
+
+    /// The default state of the block with this identifier
+    fn try_from(identifier: &Identifier) -> Result<Self, Self::Error> {
+        BlockState::from_name(identifier.as_str()).with_context(|| format!("unknown block {}", identifier.as_str()))
+    }
+}
+
+impl fmt::Display for BlockState {
+    /// Writes the state like the game does in commands, `minecraft:oak_stairs[facing=north,half=bottom,...]`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())?;
+
+        let mut properties = self.properties().peekable();
+        if properties.peek().is_some() {
+            f.write_str("[")?;
+            for (i, (name, value)) in properties.enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{}={}", name, value)?;
+            }
+            f.write_str("]")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for BlockState {
+    type Err = anyhow::Error;
+
+    /// Parses a state like `minecraft:oak_stairs[facing=north]`. Properties that aren't given keep their default,
+    /// and the namespace can be left off.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, properties) = match s.find('[') {
+            Some(start) => match s[start + 1..].strip_suffix(']') {
+                Some(properties) => (&s[..start], Some(properties)),
+                None => bail!("unclosed properties in block state {}", s),
+            },
+            None => (s, None),
+        };
+
+        let name = if name.contains(':') {
+            name.to_owned()
+        } else {
+            format!("minecraft:{}", name)
+        };
+        let mut state = BlockState::from_name(&name).with_context(|| format!("unknown block {}", name))?;
+
+        for property in properties.into_iter().flat_map(|p| p.split(',')).filter(|p| !p.is_empty()) {
+            let (key, value) = property
+                .split_once('=')
+                .with_context(|| format!("expected key=value, found {}", property))?;
+            state = state
+                .with_property(key.trim(), value.trim())
+                .with_context(|| format!("{} has no property {}={}", name, key, value))?;
+        }
+
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_matches_vanilla_ids() {
+        assert_eq!(BLOCK_STATE_COUNT, 20342);
+        assert_eq!(BLOCK_STATE_BITS, 15);
+        assert_eq!(BlockState::AIR.id(), 0);
+        assert_eq!(BlockState::STONE.id(), 1);
+        assert_eq!(BlockState::CAVE_AIR.id(), 9916);
+        assert_eq!(BlockState::from_id(20341).unwrap().name(), "minecraft:potted_flowering_azalea_bush");
+        assert!(BlockState::from_id(20342).is_none());
+    }
+
+    #[test]
+    fn states_are_renumbered_for_newer_versions() {
+        assert_eq!(BlockState::STONE.network_id(ProtocolVersion::V1_18_2), Some(1));
+        assert_eq!(BlockState::OAK_STAIRS.network_id(ProtocolVersion::V1_18_2), Some(2021));
+        assert_eq!(BlockState::OAK_STAIRS.network_id(ProtocolVersion::V1_19), Some(2219));
+        assert_eq!(BlockState::OAK_STAIRS.network_id(ProtocolVersion::V1_19_2), Some(2219));
+
+        // 1.19 made leaves waterloggable, which they aren't here
+        assert_eq!(BlockState::OAK_LEAVES.network_id(ProtocolVersion::V1_19), Some(233));
+        let persistent: BlockState = "minecraft:oak_leaves[distance=1,persistent=true]".parse().unwrap();
+        assert_eq!(persistent.network_id(ProtocolVersion::V1_19), Some(207));
+
+        assert_eq!(network_bits(ProtocolVersion::V1_19), Some(15));
+        assert_eq!(BlockState::STONE.network_id(ProtocolVersion::V1_20_1), None);
+        assert!(registry_versions().eq([ProtocolVersion::V1_18_2, ProtocolVersion::V1_19, ProtocolVersion::V1_19_2]));
+    }
+
+    #[test]
+    fn properties_can_be_read_and_changed() {
+        let stairs = BlockState::OAK_STAIRS;
+        assert_eq!(stairs.id(), 2021);
+        assert!(stairs.is_default());
+        assert_eq!(stairs.get_property("facing"), Some("north"));
+        assert_eq!(stairs.get_property("waterlogged"), Some("false"));
+        assert_eq!(stairs.get_property("color"), None);
+
+        let changed = stairs.with_property("half", "top").unwrap().with_property("shape", "outer_right").unwrap();
+        assert_eq!(changed.name(), "minecraft:oak_stairs");
+        assert_eq!(changed.get_property("half"), Some("top"));
+        assert_eq!(changed.get_property("shape"), Some("outer_right"));
+        assert_eq!(changed.get_property("facing"), Some("north"));
+        assert_eq!(changed.with_property("half", "bottom").unwrap().with_property("shape", "straight"), Some(stairs));
+
+        assert_eq!(stairs.with_property("half", "middle"), None);
+    }
+
+    #[test]
+    fn states_parse_and_print() {
+        let state: BlockState = "minecraft:oak_stairs[facing=east,waterlogged=true]".parse().unwrap();
+        assert_eq!(
+            state.to_string(),
+            "minecraft:oak_stairs[facing=east,half=bottom,shape=straight,waterlogged=true]"
+        );
+        assert_eq!(state.to_string().parse::<BlockState>().unwrap(), state);
+
+        assert_eq!("stone".parse::<BlockState>().unwrap(), BlockState::STONE);
+        assert_eq!(BlockState::STONE.to_string(), "minecraft:stone");
+        assert!("minecraft:stone[foo=bar]".parse::<BlockState>().is_err());
+        assert!("minecraft:not_a_block".parse::<BlockState>().is_err());
+        assert!("minecraft:oak_stairs[facing=east".parse::<BlockState>().is_err());
+    }
+
+    #[test]
+    fn every_state_round_trips_through_its_string() {
+        for id in 0..BLOCK_STATE_COUNT {
+            let state = BlockState::from_id(id).unwrap();
+            assert_eq!(state.to_string().parse::<BlockState>().unwrap(), state);
+        }
+    }
+
+    #[test]
+    fn identifiers_convert() {
+        let identifier = BlockState::GRASS_BLOCK.identifier();
+        assert_eq!(identifier.as_str(), "minecraft:grass_block");
+        assert_eq!(BlockState::try_from(&identifier).unwrap(), BlockState::GRASS_BLOCK);
+    }
+}
@@ -0,0 +1,110 @@
+//! The registry codec sent in Join Game, which tells the client what dimension types and biomes there are, and
+//! since 1.19 what chat types there are. Each version's is kept in `data/<version>/codec.json` as typed NBT in
+//! JSON: every tag is `{"type": ..., "value": ...}`, list elements are just their values, and longs are a pair of
+//! ints, high half first.
+
+use crate::nbt::{Compound, Nbt, Tag};
+use crate::protocol::structs::ProtocolVersion;
+use anyhow::{bail, Context};
+use serde_json::Value;
+use std::sync::OnceLock;
+
+// which codec goes with which version, generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/codec.rs"));
+
+/// The codec clients on `version` are sent, if we have one for it
+pub fn codec(version: ProtocolVersion) -> Option<&'static Nbt> {
+    static CODECS: OnceLock<Vec<Option<Nbt>>> = OnceLock::new();
+    let codecs = CODECS.get_or_init(|| {
+        ProtocolVersion::ALL
+            .iter()
+            .map(|&version| codec_json(version).map(|json| parse(json).expect("the codecs in data/ are valid")))
+            .collect()
+    });
+    codecs[version as usize].as_ref()
+}
+
+/// The dimension type called `name` in the codec clients on `version` are sent, like `minecraft:overworld`
+pub fn dimension_type(version: ProtocolVersion, name: &str) -> Option<&'static Compound> {
+    let registry = codec(version)?.root.get("minecraft:dimension_type")?.as_compound()?;
+    registry
+        .get("value")?
+        .as_list()?
+        .iter()
+        .filter_map(Tag::as_compound)
+        .find(|entry| entry.get("name").and_then(Tag::as_str) == Some(name))?
+        .get("element")?
+        .as_compound()
+}
+
+fn parse(json: &str) -> anyhow::Result<Nbt> {
+    let json: Value = serde_json::from_str(json)?;
+    let name = json["name"].as_str().unwrap_or_default().to_owned();
+    match tag("compound", &json["value"])? {
+        Tag::Compound(root) => Ok(Nbt { name, root }),
+        _ => unreachable!("compounds are read as compounds"),
+    }
+}
+
+/// Reads a tag's value, given its type
+fn tag(kind: &str, value: &Value) -> anyhow::Result<Tag> {
+    let int = || value.as_i64().with_context(|| format!("{} isn't an integer", value));
+    let float = || value.as_f64().with_context(|| format!("{} isn't a number", value));
+
+    Ok(match kind {
+        "byte" => Tag::Byte(int()? as i8),
+        "short" => Tag::Short(int()? as i16),
+        "int" => Tag::Int(int()? as i32),
+        "long" => match value.as_array().map(Vec::as_slice) {
+            Some([high, low]) => {
+                let half = |half: &Value| half.as_i64().with_context(|| format!("{} isn't a long", value));
+                Tag::Long(half(high)? << 32 | (half(low)? as u32 as i64))
+            }
+            _ => bail!("{} isn't a long", value),
+        },
+        "float" => Tag::Float(float()? as f32),
+        "double" => Tag::Double(float()?),
+        "string" => Tag::String(value.as_str().with_context(|| format!("{} isn't a string", value))?.to_owned()),
+        "list" => {
+            let inner = value["type"].as_str().context("lists need an element type")?;
+            let elements = value["value"].as_array().context("lists need elements")?;
+            Tag::List(elements.iter().map(|element| tag(inner, element)).collect::<anyhow::Result<_>>()?)
+        }
+        "compound" => {
+            let entries = value.as_object().with_context(|| format!("{} isn't a compound", value))?;
+            let mut compound = Compound::new();
+            for (name, entry) in entries {
+                let kind = entry["type"].as_str().with_context(|| format!("{} has no type", name))?;
+                let value = tag(kind, &entry["value"]).with_context(|| format!("in {}", name))?;
+                compound.insert(name.clone(), value);
+            }
+            Tag::Compound(compound)
+        }
+        _ => bail!("unknown tag type {}", kind),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_codec_parses() {
+        for version in ProtocolVersion::ALL {
+            let codec = match codec(version) {
+                Some(codec) => codec,
+                None => continue,
+            };
+            assert!(codec.root.contains_key("minecraft:worldgen/biome"));
+            assert_eq!(codec.root.contains_key("minecraft:chat_type"), version >= ProtocolVersion::V1_19);
+
+            let overworld = dimension_type(version, "minecraft:overworld").unwrap();
+            assert_eq!(overworld["min_y"], Tag::Int(-64));
+            assert_eq!(overworld["height"], Tag::Int(384));
+        }
+        assert!(codec(ProtocolVersion::V1_18_2).is_some());
+
+        let nether = dimension_type(ProtocolVersion::V1_19, "minecraft:the_nether").unwrap();
+        assert_eq!(nether["fixed_time"], Tag::Long(18000));
+    }
+}
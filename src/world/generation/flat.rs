@@ -0,0 +1,218 @@
+use crate::world::biomes::{biome_id, biome_name};
+use crate::world::blocks::BlockState;
+use crate::world::generation::Generator;
+use crate::world::palette::PalettedContainer;
+use crate::world::world::{Chunk, ChunkSection, MIN_Y, SECTION_COUNT};
+use anyhow::{bail, Context};
+use std::{fmt, str::FromStr};
+
+/// How many blocks tall the world is, which is as many as the layers can add up to
+const WORLD_HEIGHT: u32 = SECTION_COUNT as u32 * 16;
+
+/// The layers and biome of a superflat world, as written in vanilla's preset strings like
+/// `minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlatPreset {
+    /// Each layer's block and how many blocks thick it is, from the bottom of the world up
+    pub layers: Vec<(BlockState, u32)>,
+    pub biome: u32,
+    /// The structures the preset asked for, as written, or `None` to turn them off. Nothing generates structures
+    /// yet, so this only keeps the preset the same when it's written back out
+    pub structures: Option<String>,
+}
+
+impl Default for FlatPreset {
+    /// The classic flat preset
+    fn default() -> Self {
+        "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains"
+            .parse()
+            .expect("classic preset is valid")
+    }
+}
+
+impl FromStr for FlatPreset {
+    type Err = anyhow::Error;
+
+    /// Parses the layers, then the biome and then any structures, separated by semicolons. The biome defaults to
+    /// plains, and structures are off unless some are listed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+        let layers = parts.next().unwrap_or_default();
+        let biome = parts.next().map(str::trim).filter(|biome| !biome.is_empty());
+        let structures = parts
+            .next()
+            .map(str::trim)
+            .filter(|structures| !structures.is_empty())
+            .map(str::to_owned);
+        if parts.next().is_some() {
+            bail!("expected layers, biome and structures separated by semicolons, found {}", s);
+        }
+
+        let mut height: u32 = 0;
+        let mut preset = FlatPreset {
+            layers: Vec::new(),
+            biome: match biome {
+                Some(name) if name.contains(':') => biome_id(name),
+                Some(name) => biome_id(&format!("minecraft:{}", name)),
+                None => biome_id("minecraft:plains"),
+            }
+            .with_context(|| format!("unknown biome {}", biome.unwrap_or_default()))?,
+            structures,
+        };
+        for layer in split_layers(layers).map(str::trim).filter(|layer| !layer.is_empty()) {
+            let (count, block) = match layer.split_once('*') {
+                Some((count, block)) => {
+                    let count = count
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|&count| count > 0)
+                        .with_context(|| format!("invalid layer thickness in {}", layer))?;
+                    (count, block.trim())
+                }
+                None => (1, layer),
+            };
+
+            height = match height.checked_add(count).filter(|&height| height <= WORLD_HEIGHT) {
+                Some(height) => height,
+                None => bail!("layers can't add up to more than {} blocks", WORLD_HEIGHT),
+            };
+            preset.layers.push((block.parse()?, count));
+        }
+
+        Ok(preset)
+    }
+}
+
+/// Splits the layers on commas, except the ones between the properties of a block like `oak_log[axis=x]`
+fn split_layers(layers: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    layers.split(move |c| {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => {}
+        }
+        c == ',' && depth == 0
+    })
+}
+
+impl fmt::Display for FlatPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, &(block, count)) in self.layers.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if count > 1 {
+                write!(f, "{}*", count)?;
+            }
+            // vanilla presets only name blocks, so only write the properties when they need to be there
+            if block.is_default() {
+                write!(f, "{}", block.name())?;
+            } else {
+                write!(f, "{}", block)?;
+            }
+        }
+        write!(f, ";{}", biome_name(self.biome).unwrap_or("minecraft:plains"))?;
+        if let Some(structures) = &self.structures {
+            write!(f, ";{}", structures)?;
+        }
+        Ok(())
+    }
+}
+
+/// Makes every chunk the same layers of blocks, from the bottom of the world up
+pub struct FlatGenerator {
+    /// Built once up front, since every chunk is a copy of them
+    sections: [ChunkSection; SECTION_COUNT],
+}
+
+impl FlatGenerator {
+    pub fn new(preset: &FlatPreset) -> Self {
+        let mut chunk = Chunk::new(0, 0);
+        let mut y = MIN_Y;
+        for &(block, count) in &preset.layers {
+            for _ in 0..count {
+                for x in 0..16 {
+                    for z in 0..16 {
+                        chunk.set_blockstate(block, x, y, z);
+                    }
+                }
+                y += 1;
+            }
+        }
+        for section in chunk.sections.iter_mut() {
+            section.biomes = PalettedContainer::filled(preset.biome);
+        }
+
+        FlatGenerator { sections: chunk.sections }
+    }
+}
+
+impl Generator for FlatGenerator {
+    fn generate(&self, x: i32, z: i32) -> Chunk {
+        let mut chunk = Chunk::new(x, z);
+        chunk.sections = self.sections.clone();
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_parse_and_print() {
+        let preset: FlatPreset = "minecraft:bedrock,2*dirt, minecraft:grass_block;desert".parse().unwrap();
+        assert_eq!(
+            preset.layers,
+            vec![(BlockState::BEDROCK, 1), (BlockState::DIRT, 2), (BlockState::GRASS_BLOCK, 1)]
+        );
+        assert_eq!(biome_name(preset.biome), Some("minecraft:desert"));
+        assert_eq!(preset.structures, None);
+        assert_eq!(
+            preset.to_string(),
+            "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:desert"
+        );
+
+        let preset: FlatPreset = "minecraft:stone;;village,mineshaft".parse().unwrap();
+        assert_eq!(biome_name(preset.biome), Some("minecraft:plains"));
+        assert_eq!(preset.structures.as_deref(), Some("village,mineshaft"));
+        assert_eq!(preset.to_string(), "minecraft:stone;minecraft:plains;village,mineshaft");
+
+        // an empty preset is a void world
+        assert!("".parse::<FlatPreset>().unwrap().layers.is_empty());
+    }
+
+    #[test]
+    fn bad_presets_are_rejected() {
+        assert!("minecraft:bedrock;minecraft:nowhere".parse::<FlatPreset>().is_err());
+        assert!("minecraft:not_a_block".parse::<FlatPreset>().is_err());
+        assert!("0*minecraft:stone".parse::<FlatPreset>().is_err());
+        assert!("300*minecraft:stone,100*minecraft:dirt".parse::<FlatPreset>().is_err());
+        assert!("1*minecraft:stone,4294967295*minecraft:dirt".parse::<FlatPreset>().is_err());
+        assert!("384*minecraft:stone".parse::<FlatPreset>().is_ok());
+    }
+
+    #[test]
+    fn layers_can_have_properties() {
+        let preset: FlatPreset = "minecraft:oak_log[axis=x],2*minecraft:oak_stairs[facing=east,half=top]"
+            .parse()
+            .unwrap();
+        assert_eq!(preset.layers[0].0.get_property("axis"), Some("x"));
+        assert_eq!(preset.layers[1].0.get_property("half"), Some("top"));
+        assert_eq!(preset.to_string().parse::<FlatPreset>().unwrap(), preset);
+    }
+
+    #[test]
+    fn chunks_are_layered_from_the_bottom() {
+        let chunk = FlatGenerator::new(&FlatPreset::default()).generate(-3, 8);
+        assert_eq!((chunk.x_pos(), chunk.z_pos()), (-3, 8));
+        assert_eq!(chunk.get_blockstate(0, MIN_Y, 0), BlockState::BEDROCK);
+        assert_eq!(chunk.get_blockstate(7, MIN_Y + 2, 3), BlockState::DIRT);
+        assert_eq!(chunk.get_blockstate(15, MIN_Y + 3, 15), BlockState::GRASS_BLOCK);
+        assert!(chunk.get_blockstate(4, MIN_Y + 4, 4).is_air());
+        assert_eq!(chunk.sections[0].block_count(), 4 * 256);
+        assert_eq!(biome_name(chunk.sections[10].get_biome(1, 2, 3)), Some("minecraft:plains"));
+    }
+}
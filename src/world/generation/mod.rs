@@ -0,0 +1,402 @@
+use crate::util::log::{self, ThreadName};
+use crate::world::world::Chunk;
+use anyhow::{bail, Context};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    str::FromStr,
+    thread::{self, JoinHandle},
+};
+
+pub mod flat;
+pub mod noise;
+pub mod schematic;
+pub mod void;
+
+use flat::{FlatGenerator, FlatPreset};
+use noise::NoiseGenerator;
+use schematic::{Schematic, SchematicGenerator};
+use void::VoidGenerator;
+
+/// Something that can make the chunks of a world from nothing.
+///
+/// Generators are shared between worker threads, so `generate` may be called for several chunks at once.
+pub trait Generator: Send + Sync {
+    /// Makes the chunk at the given chunk coords. The same coords should always give the same chunk.
+    fn generate(&self, x: i32, z: i32) -> Chunk;
+}
+
+impl<F: Fn(i32, i32) -> Chunk + Send + Sync> Generator for F {
+    fn generate(&self, x: i32, z: i32) -> Chunk {
+        self(x, z)
+    }
+}
+
+/// Which generator a dimension uses, as picked in the config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GeneratorSettings {
+    Flat(FlatPreset),
+    /// Terrain from noise, with the seed that decides what it looks like
+    Noise(i64),
+    /// Nothing at all, apart from a platform to spawn on if `platform` is set
+    Void { platform: bool },
+    /// A schematic file with its corner at `origin`, and nothing around it
+    Schematic { path: PathBuf, origin: [i32; 3] },
+}
+
+impl GeneratorSettings {
+    /// Starts a pool of `threads` workers running this generator, or one per CPU core if that's 0.
+    ///
+    /// Only fails if the generator needs a file that can't be loaded.
+    pub fn chunk_generator(&self, threads: usize) -> anyhow::Result<ChunkGenerator> {
+        Ok(match self {
+            GeneratorSettings::Flat(preset) => ChunkGenerator::new(FlatGenerator::new(preset), threads),
+            GeneratorSettings::Noise(seed) => ChunkGenerator::new(NoiseGenerator::new(*seed), threads),
+            GeneratorSettings::Void { platform } => ChunkGenerator::new(VoidGenerator::new(*platform), threads),
+            GeneratorSettings::Schematic { path, origin } => {
+                let schematic = Schematic::read_file(path)?;
+                ChunkGenerator::new(SchematicGenerator::new(&schematic, *origin)?, threads)
+            }
+        })
+    }
+}
+
+impl FromStr for GeneratorSettings {
+    type Err = anyhow::Error;
+
+    /// Parses the generator's name, optionally followed by a colon and its settings, like `flat:<preset>`,
+    /// `noise:<seed>`, `void:platform` or `schematic:<path>;<x>,<y>,<z>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, settings) = match s.split_once(':') {
+            Some((name, settings)) => (name.trim(), Some(settings.trim())),
+            None => (s.trim(), None),
+        };
+
+        match name {
+            "flat" => Ok(GeneratorSettings::Flat(match settings {
+                Some(preset) => preset.parse()?,
+                None => FlatPreset::default(),
+            })),
+            "noise" => Ok(GeneratorSettings::Noise(match settings {
+                Some(seed) => parse_seed(seed),
+                None => 0,
+            })),
+            "void" => match settings {
+                None => Ok(GeneratorSettings::Void { platform: false }),
+                Some("platform") => Ok(GeneratorSettings::Void { platform: true }),
+                Some(settings) => bail!("unknown void setting {}, expected platform", settings),
+            },
+            "schematic" => {
+                let settings = settings.filter(|settings| !settings.is_empty()).context("expected a schematic path")?;
+                let (path, origin) = match settings.rsplit_once(';') {
+                    Some((path, origin)) => (path, parse_origin(origin)?),
+                    None => (settings, DEFAULT_ORIGIN),
+                };
+                Ok(GeneratorSettings::Schematic {
+                    path: PathBuf::from(path.trim()),
+                    origin,
+                })
+            }
+            _ => bail!("unknown generator {}, expected flat, noise, void or schematic", name),
+        }
+    }
+}
+
+/// Where schematics are placed when the config doesn't say
+const DEFAULT_ORIGIN: [i32; 3] = [0, 64, 0];
+
+/// Reads block coords written like `0,64,0`
+fn parse_origin(origin: &str) -> anyhow::Result<[i32; 3]> {
+    let coords = origin
+        .split(',')
+        .map(|coord| coord.trim().parse())
+        .collect::<Result<Vec<i32>, _>>()
+        .ok()
+        .filter(|coords| coords.len() == 3)
+        .with_context(|| format!("expected the origin as x,y,z, found {}", origin))?;
+    Ok([coords[0], coords[1], coords[2]])
+}
+
+/// Reads a seed like vanilla does: numbers are used as they are, and anything else is hashed like Java's
+/// `String.hashCode`, so `noise:glacier` makes the same world every time
+fn parse_seed(seed: &str) -> i64 {
+    seed.parse().unwrap_or_else(|_| {
+        seed.encode_utf16()
+            .fold(0i32, |hash, unit| hash.wrapping_mul(31).wrapping_add(unit as i32)) as i64
+    })
+}
+
+/// Generates chunks on a pool of worker threads, closest to the players first.
+///
+/// Chunks are requested with a priority, and once generated they wait in `finished` until the dimension that owns
+/// the generator picks them up. A chunk is only ever queued or being generated once at a time, no matter how many
+/// times it's requested.
+pub struct ChunkGenerator {
+    shared: Arc<Shared>,
+    finished: Receiver<Chunk>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Signalled whenever a chunk is queued or the workers should stop
+    available: Condvar,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// Lowest priority first. Entries that are cancelled or requested again are left in, and skipped once they
+    /// don't match `pending` anymore
+    heap: BinaryHeap<Reverse<(u32, i32, i32)>>,
+    /// The priority of each chunk waiting to be generated
+    pending: HashMap<(i32, i32), u32>,
+    /// Chunks being generated, or generated but not picked up yet
+    in_flight: HashSet<(i32, i32)>,
+    shutdown: bool,
+}
+
+impl Queue {
+    fn pop(&mut self) -> Option<(i32, i32)> {
+        while let Some(Reverse((priority, x, z))) = self.heap.pop() {
+            if self.pending.get(&(x, z)) == Some(&priority) {
+                self.pending.remove(&(x, z));
+                self.in_flight.insert((x, z));
+                return Some((x, z));
+            }
+        }
+        None
+    }
+}
+
+impl ChunkGenerator {
+    /// Starts `threads` workers, or one per CPU core if that's 0
+    pub fn new(generator: impl Generator + 'static, threads: usize) -> Self {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        };
+        let generator: Arc<dyn Generator> = Arc::new(generator);
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            available: Condvar::new(),
+        });
+        let (sender, finished) = mpsc::channel();
+
+        let workers = (0..threads)
+            .map(|i| {
+                let (generator, shared, sender) = (generator.clone(), shared.clone(), sender.clone());
+                thread::Builder::new()
+                    .name(format!("Chunk generator {}", i))
+                    .spawn(move || work(&*generator, &shared, sender))
+                    .expect("unable to start chunk generator thread")
+            })
+            .collect();
+
+        ChunkGenerator {
+            shared,
+            finished,
+            workers,
+        }
+    }
+
+    /// Queues the chunk at the given chunk coords to be generated, lowest priority first.
+    ///
+    /// Requesting a chunk that's already queued only moves it up if the new priority is lower.
+    pub fn request(&self, x: i32, z: i32, priority: u32) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        let queued = queue.pending.get(&(x, z)).is_some_and(|&queued| queued <= priority);
+        if queued || queue.in_flight.contains(&(x, z)) {
+            return;
+        }
+        queue.pending.insert((x, z), priority);
+        queue.heap.push(Reverse((priority, x, z)));
+        drop(queue);
+        self.shared.available.notify_one();
+    }
+
+    /// Takes the chunk at the given chunk coords out of the queue. Chunks already being generated still finish.
+    pub fn cancel(&self, x: i32, z: i32) {
+        self.shared.queue.lock().unwrap().pending.remove(&(x, z));
+    }
+
+    /// How many chunks have been requested but not picked up yet
+    pub fn outstanding(&self) -> usize {
+        let queue = self.shared.queue.lock().unwrap();
+        queue.pending.len() + queue.in_flight.len()
+    }
+
+    /// Takes every chunk that's finished generating, without waiting for any others
+    pub fn finished(&self) -> Vec<Chunk> {
+        let chunks: Vec<Chunk> = self.finished.try_iter().collect();
+        if !chunks.is_empty() {
+            let mut queue = self.shared.queue.lock().unwrap();
+            for chunk in &chunks {
+                queue.in_flight.remove(&(chunk.x_pos(), chunk.z_pos()));
+            }
+        }
+        chunks
+    }
+}
+
+impl Drop for ChunkGenerator {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.available.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A worker thread's loop, generating chunks until the generator is dropped
+fn work(generator: &dyn Generator, shared: &Shared, finished: Sender<Chunk>) {
+    loop {
+        let (x, z) = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                match queue.pop() {
+                    Some(chunk) => break chunk,
+                    None => queue = shared.available.wait(queue).unwrap(),
+                }
+            }
+        };
+
+        match panic::catch_unwind(AssertUnwindSafe(|| generator.generate(x, z))) {
+            Ok(chunk) => {
+                if finished.send(chunk).is_err() {
+                    return;
+                }
+            }
+            Err(_) => {
+                log::error(ThreadName::Chunk, &format!("Generating chunk {}, {} panicked", x, z));
+                shared.queue.lock().unwrap().in_flight.remove(&(x, z));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    /// Picks up chunks until `count` have finished, failing if it takes too long
+    fn wait_for(generator: &ChunkGenerator, count: usize) -> Vec<Chunk> {
+        let start = Instant::now();
+        let mut chunks = Vec::new();
+        while chunks.len() < count {
+            assert!(start.elapsed() < Duration::from_secs(10), "chunks took too long to generate");
+            chunks.extend(generator.finished());
+            thread::sleep(Duration::from_millis(1));
+        }
+        chunks
+    }
+
+    #[test]
+    fn settings_parse() {
+        assert_eq!("noise:-42".parse::<GeneratorSettings>().unwrap(), GeneratorSettings::Noise(-42));
+        // "hello".hashCode() in Java
+        assert_eq!("noise:hello".parse::<GeneratorSettings>().unwrap(), GeneratorSettings::Noise(99162322));
+        assert!(matches!("flat".parse().unwrap(), GeneratorSettings::Flat(preset) if preset.layers.len() == 3));
+        assert_eq!(
+            "void:platform".parse::<GeneratorSettings>().unwrap(),
+            GeneratorSettings::Void { platform: true }
+        );
+        assert_eq!(
+            "schematic:maps/hub.schem;10,70,-4".parse::<GeneratorSettings>().unwrap(),
+            GeneratorSettings::Schematic {
+                path: PathBuf::from("maps/hub.schem"),
+                origin: [10, 70, -4]
+            }
+        );
+        assert!(matches!(
+            "schematic:hub.schem".parse().unwrap(),
+            GeneratorSettings::Schematic { origin: DEFAULT_ORIGIN, .. }
+        ));
+        assert!("schematic:hub.schem;1,2".parse::<GeneratorSettings>().is_err());
+        assert!("amplified".parse::<GeneratorSettings>().is_err());
+    }
+
+    #[test]
+    fn closest_chunks_are_generated_first() {
+        let (release, gate) = mpsc::channel::<()>();
+        let (sender, order) = mpsc::channel();
+        let (gate, sender) = (Mutex::new(gate), Mutex::new(sender));
+        let generator = ChunkGenerator::new(
+            move |x, z| {
+                // the first chunk holds up the only worker until everything else is queued
+                if x < 0 {
+                    gate.lock().unwrap().recv().unwrap();
+                }
+                sender.lock().unwrap().send(x).unwrap();
+                Chunk::new(x, z)
+            },
+            1,
+        );
+
+        generator.request(-1, 0, 0);
+        for &(x, priority) in &[(3, 9), (1, 2), (2, 4), (0, 1)] {
+            generator.request(x, 0, priority);
+        }
+        release.send(()).unwrap();
+        wait_for(&generator, 5);
+
+        assert_eq!(order.try_iter().collect::<Vec<_>>(), vec![-1, 0, 1, 2, 3]);
+        assert_eq!(generator.outstanding(), 0);
+    }
+
+    #[test]
+    fn requests_are_deduplicated() {
+        let generated = Arc::new(AtomicUsize::new(0));
+        let counter = generated.clone();
+        let generator = ChunkGenerator::new(
+            move |x, z| {
+                counter.fetch_add(1, Ordering::Relaxed);
+                Chunk::new(x, z)
+            },
+            2,
+        );
+
+        for _ in 0..10 {
+            generator.request(4, -2, 5);
+            generator.request(4, -2, 1);
+        }
+        let chunks = wait_for(&generator, 1);
+        assert_eq!((chunks[0].x_pos(), chunks[0].z_pos()), (4, -2));
+
+        thread::sleep(Duration::from_millis(20));
+        assert!(generator.finished().is_empty());
+        assert_eq!(generated.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn panicking_generators_dont_stop_the_pool() {
+        let generator = ChunkGenerator::new(
+            |x, z| {
+                if x == 0 {
+                    panic!("bad chunk");
+                }
+                Chunk::new(x, z)
+            },
+            1,
+        );
+
+        generator.request(0, 0, 0);
+        generator.request(1, 0, 1);
+        let chunks = wait_for(&generator, 1);
+        assert_eq!(chunks[0].x_pos(), 1);
+        assert_eq!(generator.outstanding(), 0);
+    }
+}
@@ -0,0 +1,594 @@
+use crate::world::biomes::biome_id;
+use crate::world::blocks::BlockState;
+use crate::world::generation::Generator;
+use crate::world::palette::PalettedContainer;
+use crate::world::world::{Chunk, MIN_Y, SECTION_COUNT};
+
+/// Open terrain below this is flooded, so the sea's surface is the block below it
+pub const SEA_LEVEL: i32 = 63;
+
+/// One above the highest block in the world
+const MAX_Y: i32 = MIN_Y + SECTION_COUNT as i32 * 16;
+
+/// How far apart the density is sampled, horizontally and vertically. The blocks in between are interpolated.
+const CELL_WIDTH: i32 = 4;
+const CELL_HEIGHT: i32 = 4;
+const GRID_WIDTH: usize = (16 / CELL_WIDTH) as usize + 1;
+const GRID_HEIGHT: usize = ((MAX_Y - MIN_Y) / CELL_HEIGHT) as usize + 1;
+
+/// How many blocks above or below its base height the terrain has to be before the 3D noise can't flip it
+const SQUASH: f64 = 24.0;
+
+/// Caves are lava instead of air from here down
+const LAVA_LEVEL: i32 = -55;
+
+/// Deepslate takes over from stone gradually, becoming all deepslate at 0
+const DEEPSLATE_LEVEL: i32 = 8;
+
+/// A small seeded random number generator (SplitMix64), so terrain doesn't change when a dependency does
+struct Random(u64);
+
+impl Random {
+    /// A generator for one use of the world seed, like a noise layer or a chunk's ores
+    fn derive(seed: i64, a: i64, b: i64) -> Self {
+        let mut random = Random(
+            (seed as u64)
+                ^ (a as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (b as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F),
+        );
+        random.next_u64();
+        random
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// From 0 up to but not including 1
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// From `min` up to but not including `max`
+    fn range(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next_u64() % (max - min) as u64) as i32
+    }
+}
+
+/// Ken Perlin's improved gradient noise, shuffled and shifted by a seed
+struct Perlin {
+    /// The shuffled numbers 0 to 255, twice over so lookups don't need wrapping
+    permutation: [u8; 512],
+    offset: [f64; 3],
+}
+
+impl Perlin {
+    fn new(random: &mut Random) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        for i in (1..table.len()).rev() {
+            table.swap(i, (random.next_u64() % (i as u64 + 1)) as usize);
+        }
+        Perlin {
+            permutation: std::array::from_fn(|i| table[i & 255]),
+            offset: [random.next_f64() * 256.0, random.next_f64() * 256.0, random.next_f64() * 256.0],
+        }
+    }
+
+    /// Smoothly varies between about -1 and 1
+    fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (x, y, z) = (x + self.offset[0], y + self.offset[1], z + self.offset[2]);
+        let (floor_x, floor_y, floor_z) = (x.floor(), y.floor(), z.floor());
+        let (x, y, z) = (x - floor_x, y - floor_y, z - floor_z);
+        let (i, j, k) = (
+            (floor_x as i64 & 255) as usize,
+            (floor_y as i64 & 255) as usize,
+            (floor_z as i64 & 255) as usize,
+        );
+
+        let p = &self.permutation;
+        let a = p[i] as usize + j;
+        let (aa, ab) = (p[a] as usize + k, p[a + 1] as usize + k);
+        let b = p[i + 1] as usize + j;
+        let (ba, bb) = (p[b] as usize + k, p[b + 1] as usize + k);
+
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(u, grad(p[ab], x, y - 1.0, z), grad(p[bb], x - 1.0, y - 1.0, z)),
+            ),
+            lerp(
+                v,
+                lerp(u, grad(p[aa + 1], x, y, z - 1.0), grad(p[ba + 1], x - 1.0, y, z - 1.0)),
+                lerp(u, grad(p[ab + 1], x, y - 1.0, z - 1.0), grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0)),
+            ),
+        )
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// The dot product of the offset with one of 12 gradient directions, picked by the hash
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let hash = hash & 15;
+    let u = if hash < 8 { x } else { y };
+    let v = match hash {
+        0..=3 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+    (if hash & 1 == 0 { u } else { -u }) + (if hash & 2 == 0 { v } else { -v })
+}
+
+/// Layers of Perlin noise, each twice the frequency and half the strength of the one before
+struct Octaves {
+    layers: Vec<Perlin>,
+    /// How many times the first layer varies per block
+    frequency: f64,
+}
+
+impl Octaves {
+    fn new(seed: i64, salt: i64, count: usize, scale: f64) -> Self {
+        let mut random = Random::derive(seed, salt, 0);
+        Octaves {
+            layers: (0..count).map(|_| Perlin::new(&mut random)).collect(),
+            frequency: 1.0 / scale,
+        }
+    }
+
+    /// Between about -1 and 1
+    fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (mut total, mut max) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (self.frequency, 1.0);
+        for layer in &self.layers {
+            total += layer.sample(x * frequency, y * frequency, z * frequency) * amplitude;
+            max += amplitude;
+            frequency *= 2.0;
+            amplitude /= 2.0;
+        }
+        total / max
+    }
+
+    fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        self.sample(x, 0.0, z)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Biome {
+    Ocean,
+    DeepOcean,
+    FrozenOcean,
+    WarmOcean,
+    Beach,
+    SnowyBeach,
+    Plains,
+    Forest,
+    Taiga,
+    SnowyPlains,
+    SnowyTaiga,
+    Desert,
+    Savanna,
+    Jungle,
+    StonyPeaks,
+    FrozenPeaks,
+}
+
+impl Biome {
+    /// Picks a biome from the climate and roughly how high the ground is.
+    ///
+    /// Temperature and humidity are between about -1 and 1, like the noise they come from.
+    fn pick(height: f64, temperature: f64, humidity: f64) -> Biome {
+        let cold = temperature < -0.25;
+        let hot = temperature > 0.3;
+
+        if height < SEA_LEVEL as f64 - 20.0 {
+            Biome::DeepOcean
+        } else if height < SEA_LEVEL as f64 - 1.0 {
+            match () {
+                _ if cold => Biome::FrozenOcean,
+                _ if hot => Biome::WarmOcean,
+                _ => Biome::Ocean,
+            }
+        } else if height < SEA_LEVEL as f64 + 2.0 {
+            if cold {
+                Biome::SnowyBeach
+            } else {
+                Biome::Beach
+            }
+        } else if height > SEA_LEVEL as f64 + 60.0 {
+            if temperature < 0.1 {
+                Biome::FrozenPeaks
+            } else {
+                Biome::StonyPeaks
+            }
+        } else if cold {
+            if humidity > 0.0 {
+                Biome::SnowyTaiga
+            } else {
+                Biome::SnowyPlains
+            }
+        } else if hot {
+            match () {
+                _ if humidity < -0.1 => Biome::Desert,
+                _ if humidity < 0.2 => Biome::Savanna,
+                _ => Biome::Jungle,
+            }
+        } else {
+            match () {
+                _ if humidity < -0.1 => Biome::Plains,
+                _ if temperature < 0.0 => Biome::Taiga,
+                _ => Biome::Forest,
+            }
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Biome::Ocean => "minecraft:ocean",
+            Biome::DeepOcean => "minecraft:deep_ocean",
+            Biome::FrozenOcean => "minecraft:frozen_ocean",
+            Biome::WarmOcean => "minecraft:warm_ocean",
+            Biome::Beach => "minecraft:beach",
+            Biome::SnowyBeach => "minecraft:snowy_beach",
+            Biome::Plains => "minecraft:plains",
+            Biome::Forest => "minecraft:forest",
+            Biome::Taiga => "minecraft:taiga",
+            Biome::SnowyPlains => "minecraft:snowy_plains",
+            Biome::SnowyTaiga => "minecraft:snowy_taiga",
+            Biome::Desert => "minecraft:desert",
+            Biome::Savanna => "minecraft:savanna",
+            Biome::Jungle => "minecraft:jungle",
+            Biome::StonyPeaks => "minecraft:stony_peaks",
+            Biome::FrozenPeaks => "minecraft:frozen_peaks",
+        }
+    }
+
+    fn id(self) -> u32 {
+        biome_id(self.name()).expect("biome is in the registry")
+    }
+
+    fn is_snowy(self) -> bool {
+        matches!(self, Biome::SnowyBeach | Biome::SnowyPlains | Biome::SnowyTaiga | Biome::FrozenPeaks)
+    }
+
+    /// The block on top of the ground, and the one the next few blocks down are made of
+    fn surface(self, underwater: bool) -> (BlockState, BlockState) {
+        match self {
+            Biome::Desert | Biome::Beach | Biome::SnowyBeach | Biome::WarmOcean => (BlockState::SAND, BlockState::SAND),
+            Biome::Ocean | Biome::DeepOcean | Biome::FrozenOcean => (BlockState::GRAVEL, BlockState::GRAVEL),
+            Biome::StonyPeaks => (BlockState::STONE, BlockState::STONE),
+            Biome::FrozenPeaks => (BlockState::SNOW_BLOCK, BlockState::STONE),
+            // grass doesn't grow underwater
+            _ if underwater => (BlockState::DIRT, BlockState::DIRT),
+            _ => (BlockState::GRASS_BLOCK, BlockState::DIRT),
+        }
+    }
+}
+
+/// An ore, how much of it each chunk gets and where
+struct Ore {
+    stone: BlockState,
+    deepslate: BlockState,
+    min_y: i32,
+    max_y: i32,
+    veins: u32,
+    size: u32,
+}
+
+const ORES: [Ore; 7] = [
+    Ore { stone: BlockState::COAL_ORE, deepslate: BlockState::DEEPSLATE_COAL_ORE, min_y: 0, max_y: 192, veins: 20, size: 12 },
+    Ore { stone: BlockState::COPPER_ORE, deepslate: BlockState::DEEPSLATE_COPPER_ORE, min_y: -16, max_y: 112, veins: 10, size: 10 },
+    Ore { stone: BlockState::IRON_ORE, deepslate: BlockState::DEEPSLATE_IRON_ORE, min_y: -24, max_y: 56, veins: 12, size: 8 },
+    Ore { stone: BlockState::GOLD_ORE, deepslate: BlockState::DEEPSLATE_GOLD_ORE, min_y: -64, max_y: 32, veins: 4, size: 8 },
+    Ore { stone: BlockState::REDSTONE_ORE, deepslate: BlockState::DEEPSLATE_REDSTONE_ORE, min_y: -64, max_y: 16, veins: 6, size: 7 },
+    Ore { stone: BlockState::LAPIS_ORE, deepslate: BlockState::DEEPSLATE_LAPIS_ORE, min_y: -64, max_y: 64, veins: 2, size: 6 },
+    Ore { stone: BlockState::DIAMOND_ORE, deepslate: BlockState::DEEPSLATE_DIAMOND_ORE, min_y: -64, max_y: 16, veins: 3, size: 6 },
+];
+
+/// Samples taken every few blocks through a chunk, interpolated in between
+struct Grid(Vec<f64>);
+
+impl Grid {
+    fn new(mut sample: impl FnMut(usize, usize, usize) -> f64) -> Self {
+        let mut values = Vec::with_capacity(GRID_WIDTH * GRID_WIDTH * GRID_HEIGHT);
+        for x in 0..GRID_WIDTH {
+            for z in 0..GRID_WIDTH {
+                for y in 0..GRID_HEIGHT {
+                    values.push(sample(x, y, z));
+                }
+            }
+        }
+        Grid(values)
+    }
+
+    fn get(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.0[(x * GRID_WIDTH + z) * GRID_HEIGHT + y]
+    }
+
+    /// The value at a block within the chunk, with `y` counted up from the bottom of the world
+    fn interpolate(&self, x: usize, y: usize, z: usize) -> f64 {
+        let (cell_x, cell_y, cell_z) = (x / CELL_WIDTH as usize, y / CELL_HEIGHT as usize, z / CELL_WIDTH as usize);
+        let (tx, ty, tz) = (
+            (x % CELL_WIDTH as usize) as f64 / CELL_WIDTH as f64,
+            (y % CELL_HEIGHT as usize) as f64 / CELL_HEIGHT as f64,
+            (z % CELL_WIDTH as usize) as f64 / CELL_WIDTH as f64,
+        );
+        let corner = |dx, dy, dz| self.get(cell_x + dx, cell_y + dy, cell_z + dz);
+        lerp(
+            tx,
+            lerp(tz, lerp(ty, corner(0, 0, 0), corner(0, 1, 0)), lerp(ty, corner(0, 0, 1), corner(0, 1, 1))),
+            lerp(tz, lerp(ty, corner(1, 0, 0), corner(1, 1, 0)), lerp(ty, corner(1, 0, 1), corner(1, 1, 1))),
+        )
+    }
+}
+
+/// Rolling terrain from density noise, with biomes picked by climate, caves, ores and seas.
+///
+/// It's nothing like vanilla's terrain, but the same seed always gives the same chunks.
+pub struct NoiseGenerator {
+    seed: i64,
+    /// How far inland a column is, which sets the base height of the terrain
+    continentalness: Octaves,
+    /// How mountainous the land is
+    ridges: Octaves,
+    temperature: Octaves,
+    humidity: Octaves,
+    /// Bends the terrain away from its base height, for overhangs and cliffs
+    density: Octaves,
+    /// Big open caves where this is high
+    caverns: Octaves,
+    /// Winding tunnels where both of these are close to 0
+    tunnels: [Octaves; 2],
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: i64) -> Self {
+        NoiseGenerator {
+            seed,
+            continentalness: Octaves::new(seed, 1, 4, 600.0),
+            ridges: Octaves::new(seed, 2, 3, 200.0),
+            temperature: Octaves::new(seed, 3, 2, 900.0),
+            humidity: Octaves::new(seed, 4, 2, 700.0),
+            density: Octaves::new(seed, 5, 3, 80.0),
+            caverns: Octaves::new(seed, 6, 2, 60.0),
+            tunnels: [Octaves::new(seed, 7, 2, 70.0), Octaves::new(seed, 8, 2, 70.0)],
+        }
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// Roughly how high the ground is at a column, before the 3D noise moves it around
+    fn base_height(&self, x: f64, z: f64) -> f64 {
+        // the noise rarely gets far from 0, so stretch it to cover deep oceans through to inland
+        let continentalness = (self.continentalness.sample_2d(x, z) * 2.5).clamp(-1.0, 1.0);
+        let ridges = self.ridges.sample_2d(x, z).abs();
+        let height = SEA_LEVEL as f64 + 4.0 + continentalness * 40.0;
+        if continentalness > 0.0 {
+            // mountains only grow inland
+            height + ridges * ridges * continentalness * 260.0
+        } else {
+            height
+        }
+    }
+
+    fn biome(&self, x: f64, z: f64) -> Biome {
+        Biome::pick(
+            self.base_height(x, z),
+            self.temperature.sample_2d(x, z) * 2.0,
+            self.humidity.sample_2d(x, z) * 2.0,
+        )
+    }
+}
+
+impl Generator for NoiseGenerator {
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::new(chunk_x, chunk_z);
+        let mut random = Random::derive(self.seed, chunk_x as i64, chunk_z as i64);
+        let (start_x, start_z) = (chunk_x as f64 * 16.0, chunk_z as f64 * 16.0);
+        let block_x = |grid_x: usize| start_x + (grid_x as i32 * CELL_WIDTH) as f64;
+        let block_z = |grid_z: usize| start_z + (grid_z as i32 * CELL_WIDTH) as f64;
+        let block_y = |grid_y: usize| (MIN_Y + grid_y as i32 * CELL_HEIGHT) as f64;
+
+        let mut heights = [[0.0; GRID_WIDTH]; GRID_WIDTH];
+        for (x, row) in heights.iter_mut().enumerate() {
+            for (z, height) in row.iter_mut().enumerate() {
+                *height = self.base_height(block_x(x), block_z(z));
+            }
+        }
+        let density = Grid::new(|x, y, z| {
+            let (bx, by, bz) = (block_x(x), block_y(y), block_z(z));
+            (heights[x][z] - by) / SQUASH + self.density.sample(bx, by * 2.0, bz) * 0.6
+        });
+        let caverns = Grid::new(|x, y, z| self.caverns.sample(block_x(x), block_y(y) * 1.5, block_z(z)));
+        let tunnels = [
+            Grid::new(|x, y, z| self.tunnels[0].sample(block_x(x), block_y(y), block_z(z))),
+            Grid::new(|x, y, z| self.tunnels[1].sample(block_x(x), block_y(y), block_z(z))),
+        ];
+
+        let biomes: [[Biome; 4]; 4] = std::array::from_fn(|x| {
+            std::array::from_fn(|z| self.biome(start_x + (x * 4 + 2) as f64, start_z + (z * 4 + 2) as f64))
+        });
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let biome = biomes[x / 4][z / 4];
+
+                // the terrain itself, flooded up to sea level
+                let mut top = MIN_Y - 1;
+                for y in MIN_Y..MAX_Y {
+                    let index = (y - MIN_Y) as usize;
+                    if density.interpolate(x, index, z) > 0.0 {
+                        let deepslate = y < 0 || (y < DEEPSLATE_LEVEL && random.range(0, DEEPSLATE_LEVEL) >= y);
+                        let stone = if deepslate { BlockState::DEEPSLATE } else { BlockState::STONE };
+                        chunk.set_blockstate(stone, x, y, z);
+                        top = y;
+                    } else if y < SEA_LEVEL {
+                        let frozen = biome == Biome::FrozenOcean && y == SEA_LEVEL - 1;
+                        let water = if frozen { BlockState::ICE } else { BlockState::WATER };
+                        chunk.set_blockstate(water, x, y, z);
+                    }
+                }
+
+                // the surface, a few blocks of dirt or sand over the stone
+                let underwater = top < SEA_LEVEL - 1;
+                let (surface, filler) = biome.surface(underwater);
+                let depth = 3 + random.range(0, 2);
+                for (i, y) in (top - depth..=top).rev().enumerate() {
+                    if y < MIN_Y {
+                        break;
+                    }
+                    let current = chunk.get_blockstate(x, y, z);
+                    if current.is_air() || current == BlockState::WATER {
+                        break;
+                    }
+                    let block = match i {
+                        0 if surface == BlockState::GRASS_BLOCK && biome.is_snowy() => {
+                            surface.with_property("snowy", "true").unwrap_or(surface)
+                        }
+                        0 => surface,
+                        _ if filler == BlockState::SAND && i > 2 => BlockState::SANDSTONE,
+                        _ => filler,
+                    };
+                    chunk.set_blockstate(block, x, y, z);
+                }
+                if biome.is_snowy() && !underwater && top + 1 < MAX_Y {
+                    chunk.set_blockstate(BlockState::SNOW, x, top + 1, z);
+                }
+
+                // caves, kept a few blocks under the surface so they don't open up into the sea
+                for y in MIN_Y + 5..top - 6 {
+                    let index = (y - MIN_Y) as usize;
+                    let cavern = caverns.interpolate(x, index, z) > 0.45;
+                    let tunnel = tunnels[0].interpolate(x, index, z).abs() < 0.05
+                        && tunnels[1].interpolate(x, index, z).abs() < 0.05;
+                    if cavern || tunnel {
+                        let block = if y <= LAVA_LEVEL { BlockState::LAVA } else { BlockState::AIR };
+                        chunk.set_blockstate(block, x, y, z);
+                    }
+                }
+
+                // bedrock, solid at the bottom and thinning out over the next few blocks
+                for y in MIN_Y..MIN_Y + 5 {
+                    if y == MIN_Y || random.range(0, 5) >= y - MIN_Y {
+                        chunk.set_blockstate(BlockState::BEDROCK, x, y, z);
+                    }
+                }
+            }
+        }
+
+        for ore in &ORES {
+            for _ in 0..ore.veins {
+                let (mut x, mut y, mut z) = (random.range(0, 16), random.range(ore.min_y, ore.max_y), random.range(0, 16));
+                for _ in 0..ore.size {
+                    if (0..16).contains(&x) && (0..16).contains(&z) && (MIN_Y..MAX_Y).contains(&y) {
+                        let (bx, bz) = (x as usize, z as usize);
+                        match chunk.get_blockstate(bx, y, bz) {
+                            BlockState::STONE => chunk.set_blockstate(ore.stone, bx, y, bz),
+                            BlockState::DEEPSLATE => chunk.set_blockstate(ore.deepslate, bx, y, bz),
+                            _ => {}
+                        }
+                    }
+                    match random.range(0, 3) {
+                        0 => x += random.range(-1, 2),
+                        1 => y += random.range(-1, 2),
+                        _ => z += random.range(-1, 2),
+                    }
+                }
+            }
+        }
+
+        for (x, row) in biomes.iter().enumerate() {
+            for (z, biome) in row.iter().enumerate() {
+                let id = biome.id();
+                for section in chunk.sections.iter_mut() {
+                    for y in 0..4 {
+                        section.set_biome(id, x, y, z);
+                    }
+                }
+            }
+        }
+        // most chunks are a single biome, which saves the palette
+        for section in chunk.sections.iter_mut() {
+            let first = section.get_biome(0, 0, 0);
+            if (0..64).all(|i| section.biomes.get(i) == first) {
+                section.biomes = PalettedContainer::filled(first);
+            }
+        }
+
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::biomes::biome_name;
+
+    #[test]
+    fn noise_is_smooth_and_bounded() {
+        let noise = Octaves::new(42, 0, 4, 32.0);
+        let mut previous = noise.sample(0.0, 10.0, 0.0);
+        for i in 1..2000 {
+            let value = noise.sample(i as f64 * 0.25, 10.0, 0.0);
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((value - previous).abs() < 0.1);
+            previous = value;
+        }
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_chunks() {
+        let generator = NoiseGenerator::new(1234);
+        let (first, second) = (generator.generate(5, -9), NoiseGenerator::new(1234).generate(5, -9));
+        assert_eq!(first.to_nbt().unwrap(), second.to_nbt().unwrap());
+
+        let other = NoiseGenerator::new(4321).generate(5, -9);
+        assert_ne!(first.to_nbt().unwrap(), other.to_nbt().unwrap());
+    }
+
+    #[test]
+    fn chunks_fill_the_world_height() {
+        let generator = NoiseGenerator::new(0);
+        for &(x, z) in &[(0, 0), (40, -12), (-25, 60)] {
+            let chunk = generator.generate(x, z);
+            for bx in 0..16 {
+                for bz in 0..16 {
+                    assert_eq!(chunk.get_blockstate(bx, MIN_Y, bz), BlockState::BEDROCK);
+                    assert!(chunk.get_blockstate(bx, MAX_Y - 1, bz).is_air());
+                    // deep caves are full of lava
+                    assert!((MIN_Y..=LAVA_LEVEL).all(|y| !chunk.get_blockstate(bx, y, bz).is_air()));
+                }
+            }
+            assert!(biome_name(chunk.sections[10].get_biome(0, 0, 0)).is_some());
+        }
+    }
+
+    #[test]
+    fn seas_are_flooded() {
+        let generator = NoiseGenerator::new(0);
+        let (x, z) = (0..1000)
+            .map(|i| (i, 0))
+            .find(|&(x, z)| generator.base_height(x as f64 * 16.0 + 8.0, z as f64 * 16.0 + 8.0) < SEA_LEVEL as f64 - 25.0)
+            .expect("there should be an ocean somewhere");
+
+        let chunk = generator.generate(x, z);
+        assert_eq!(chunk.get_blockstate(8, SEA_LEVEL - 1, 8), BlockState::WATER);
+        assert!(chunk.get_blockstate(8, SEA_LEVEL, 8).is_air());
+        assert_eq!(biome_name(chunk.sections[0].get_biome(2, 0, 2)), Some("minecraft:deep_ocean"));
+    }
+}
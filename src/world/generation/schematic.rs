@@ -0,0 +1,446 @@
+use crate::nbt::{Compound, Nbt, Tag};
+use crate::world::anvil::block_entity_type_id;
+use crate::world::biomes::biome_id;
+use crate::world::blocks::BlockState;
+use crate::world::generation::Generator;
+use crate::world::world::{BlockEntity, Chunk, MIN_Y, SECTION_COUNT};
+use anyhow::{bail, Context};
+use std::{collections::HashMap, convert::TryFrom, path::Path};
+
+/// The most blocks a schematic can cover, so a bad header can't make us allocate more than the world could hold.
+/// That's a 256x256 area of the world from top to bottom, with room to spare
+const MAX_VOLUME: usize = 1 << 25;
+
+/// A structure saved in the Sponge schematic format (`.schem`), version 2 or 3
+pub struct Schematic {
+    width: usize,
+    height: usize,
+    length: usize,
+    /// Where the schematic sits relative to where it's pasted
+    offset: [i32; 3],
+    /// Every block, X fastest then Z then Y
+    blocks: Vec<BlockState>,
+    block_entities: Vec<([i32; 3], BlockEntity)>,
+    /// A biome for every block, in the same order as `blocks`, if the schematic has any
+    biomes: Option<Vec<u32>>,
+}
+
+impl Schematic {
+    pub fn read_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        Schematic::from_nbt(&Nbt::read_file(path)?)
+            .with_context(|| format!("{} is not a valid schematic", path.display()))
+    }
+
+    pub fn from_nbt(nbt: &Nbt) -> anyhow::Result<Self> {
+        // version 3 nests everything in a compound called Schematic, where version 2 names the root that instead
+        let root = nbt.root.get("Schematic").and_then(Tag::as_compound).unwrap_or(&nbt.root);
+        let version = field(root, "Version", Tag::as_int)?;
+        let size = |key| -> anyhow::Result<usize> {
+            // the sizes are unsigned shorts
+            Ok(field(root, key, Tag::as_short)? as u16 as usize)
+        };
+        let (width, height, length) = (size("Width")?, size("Height")?, size("Length")?);
+        let volume = width
+            .checked_mul(height)
+            .and_then(|area| area.checked_mul(length))
+            .filter(|&volume| volume <= MAX_VOLUME)
+            .with_context(|| format!("{}x{}x{} is more than the {} blocks allowed", width, height, length, MAX_VOLUME))?;
+
+        let offset = match root.get("Offset") {
+            Some(offset) => match offset.as_int_array() {
+                Some(&[x, y, z]) => [x, y, z],
+                _ => bail!("Offset should be 3 ints"),
+            },
+            None => [0, 0, 0],
+        };
+
+        let compound = |key| {
+            root.get(key)
+                .map(|tag| tag.as_compound().with_context(|| format!("{} should be a compound", key)))
+                .transpose()
+        };
+        // version 2 keeps everything at the top level, with different names for the biomes
+        let (blocks, biomes, data_key) = match version {
+            2 => (Some(root), root.get("BiomePalette").map(|_| root), "BlockData"),
+            3 => (compound("Blocks")?, compound("Biomes")?, "Data"),
+            _ => bail!("unsupported schematic version {}", version),
+        };
+        let block_entities = blocks.and_then(|blocks| blocks.get("BlockEntities"));
+
+        let blocks = match blocks {
+            Some(blocks) => {
+                let palette = read_palette(field(blocks, "Palette", Tag::as_compound)?, |state| state.parse())?;
+                let indices = read_indices(field(blocks, data_key, Tag::as_byte_array)?, volume)?;
+                look_up(&palette, indices, "block")?
+            }
+            None => vec![BlockState::AIR; volume],
+        };
+
+        let biomes = match biomes {
+            Some(biomes) => {
+                let (palette_key, data_key) = match version {
+                    2 => ("BiomePalette", "BiomeData"),
+                    _ => ("Palette", "Data"),
+                };
+                let palette = read_palette(field(biomes, palette_key, Tag::as_compound)?, |name| {
+                    biome_id(name).with_context(|| format!("unknown biome {}", name))
+                })?;
+                // version 2 only has a biome per column
+                let columns = if version == 2 { width * length } else { volume };
+                let indices = read_indices(field(biomes, data_key, Tag::as_byte_array)?, columns)?;
+                let biomes = look_up(&palette, indices, "biome")?;
+                Some((0..volume).map(|index| biomes[index % columns]).collect())
+            }
+            None => None,
+        };
+
+        let block_entities = block_entities
+            .map(|list| list.as_list().context("BlockEntities should be a list"))
+            .transpose()?
+            .unwrap_or_default()
+            .iter()
+            .map(|block_entity| read_block_entity(block_entity, version))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for ([x, y, z], _) in &block_entities {
+            let inside = |position: i32, size: usize| usize::try_from(position).is_ok_and(|position| position < size);
+            if !(inside(*x, width) && inside(*y, height) && inside(*z, length)) {
+                bail!("block entity at {}, {}, {} is outside the schematic", x, y, z);
+            }
+        }
+
+        Ok(Schematic {
+            width,
+            height,
+            length,
+            offset,
+            blocks,
+            block_entities,
+            biomes,
+        })
+    }
+
+    /// How many blocks the schematic covers along X, Y and Z
+    pub fn size(&self) -> (usize, usize, usize) {
+        (self.width, self.height, self.length)
+    }
+
+    /// The block at the given coords within the schematic
+    pub fn block(&self, x: usize, y: usize, z: usize) -> BlockState {
+        self.blocks[self.index(x, y, z)]
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (y * self.length + z) * self.width + x
+    }
+}
+
+fn field<'a, T>(compound: &'a Compound, key: &str, as_type: fn(&'a Tag) -> Option<T>) -> anyhow::Result<T> {
+    compound
+        .get(key)
+        .and_then(as_type)
+        .with_context(|| format!("{} is missing or the wrong type", key))
+}
+
+/// Reads a palette of names to indices, turning it around so indices can be looked up
+fn read_palette<T>(palette: &Compound, parse: impl Fn(&str) -> anyhow::Result<T>) -> anyhow::Result<HashMap<u32, T>> {
+    palette
+        .iter()
+        .map(|(name, index)| {
+            let index = index.as_int().with_context(|| format!("{} should have an int index", name))?;
+            Ok((index as u32, parse(name)?))
+        })
+        .collect()
+}
+
+/// Turns palette indices into what they stand for
+fn look_up<T: Copy>(palette: &HashMap<u32, T>, indices: Vec<u32>, what: &str) -> anyhow::Result<Vec<T>> {
+    indices
+        .into_iter()
+        .map(|index| {
+            palette
+                .get(&index)
+                .copied()
+                .with_context(|| format!("{} {} isn't in the palette", what, index))
+        })
+        .collect()
+}
+
+/// Reads `count` palette indices, which are packed as VarInts
+fn read_indices(data: &[i8], count: usize) -> anyhow::Result<Vec<u32>> {
+    // every index takes at least a byte, so a short array can be turned away before anything is allocated
+    if data.len() < count {
+        bail!("not enough blocks in the data, expected {} but there are at most {}", count, data.len());
+    }
+    let mut indices = Vec::with_capacity(data.len().min(count));
+    let mut bytes = data.iter().map(|&byte| byte as u8);
+    while indices.len() < count {
+        let mut value = 0u32;
+        for shift in (0..).step_by(7) {
+            if shift >= 35 {
+                bail!("palette index is too long");
+            }
+            let byte = bytes.next().context("not enough blocks in the data")?;
+            value |= ((byte & 0x7F) as u32) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        indices.push(value);
+    }
+    Ok(indices)
+}
+
+/// Reads a block entity along with where it is in the schematic
+fn read_block_entity(tag: &Tag, version: i32) -> anyhow::Result<([i32; 3], BlockEntity)> {
+    let compound = tag.as_compound().context("block entities should be compounds")?;
+    let position = match field(compound, "Pos", Tag::as_int_array)? {
+        &[x, y, z] => [x, y, z],
+        _ => bail!("block entity Pos should be 3 ints"),
+    };
+    let id = field(compound, "Id", Tag::as_str)?;
+    let kind = block_entity_type_id(id).with_context(|| format!("unknown block entity {}", id))?;
+
+    // version 3 keeps the block entity's own data apart from its position and ID
+    let mut data = match version {
+        2 => compound.clone(),
+        _ => compound.get("Data").and_then(Tag::as_compound).cloned().unwrap_or_default(),
+    };
+    for key in &["Pos", "Id", "id", "x", "y", "z"] {
+        data.remove(*key);
+    }
+
+    Ok((position, BlockEntity { kind, data }))
+}
+
+/// Stamps a schematic into the world, leaving everything around it empty
+pub struct SchematicGenerator {
+    /// The chunks the schematic touches, already filled in
+    chunks: HashMap<(i32, i32), Chunk>,
+}
+
+impl SchematicGenerator {
+    /// Places the schematic with its corner at `origin`, moved by the schematic's own offset
+    pub fn new(schematic: &Schematic, origin: [i32; 3]) -> anyhow::Result<Self> {
+        // both corners have to be real coords, which every block in between then is too
+        let (width, height, length) = (schematic.width as i32, schematic.height as i32, schematic.length as i32);
+        let corner = (0..3)
+            .map(|axis| origin[axis].checked_add(schematic.offset[axis]))
+            .collect::<Option<Vec<i32>>>()
+            .filter(|corner| {
+                corner[0].checked_add(width).is_some()
+                    && corner[1].checked_add(height).is_some()
+                    && corner[2].checked_add(length).is_some()
+            })
+            .context("the schematic goes past the edge of the world")?;
+        let max_y = MIN_Y + SECTION_COUNT as i32 * 16;
+        if corner[1] < MIN_Y || corner[1] + height > max_y {
+            bail!("the schematic doesn't fit between y={} and y={} when placed at y={}", MIN_Y, max_y, corner[1]);
+        }
+
+        let mut chunks = HashMap::new();
+
+        for y in 0..schematic.height {
+            for z in 0..schematic.length {
+                for x in 0..schematic.width {
+                    let (world_x, world_y, world_z) = (corner[0] + x as i32, corner[1] + y as i32, corner[2] + z as i32);
+                    let chunk = chunk_at(&mut chunks, world_x, world_z);
+                    let (local_x, local_z) = (world_x.rem_euclid(16) as usize, world_z.rem_euclid(16) as usize);
+
+                    let block = schematic.block(x, y, z);
+                    if !block.is_air() {
+                        chunk.set_blockstate(block, local_x, world_y, local_z);
+                    }
+                    if let Some(biomes) = &schematic.biomes {
+                        let section = &mut chunk.sections[((world_y - MIN_Y) >> 4) as usize];
+                        let biome = biomes[schematic.index(x, y, z)];
+                        section.set_biome(biome, local_x / 4, (world_y & 15) as usize / 4, local_z / 4);
+                    }
+                }
+            }
+        }
+
+        for ([x, y, z], block_entity) in &schematic.block_entities {
+            let (world_x, world_y, world_z) = (corner[0] + x, corner[1] + y, corner[2] + z);
+            chunk_at(&mut chunks, world_x, world_z).set_block_entity(
+                world_x.rem_euclid(16) as u8,
+                world_y as i16,
+                world_z.rem_euclid(16) as u8,
+                block_entity.clone(),
+            );
+        }
+
+        Ok(SchematicGenerator { chunks })
+    }
+}
+
+/// The chunk the given block coords are in, adding it if it isn't there yet
+fn chunk_at(chunks: &mut HashMap<(i32, i32), Chunk>, x: i32, z: i32) -> &mut Chunk {
+    let (chunk_x, chunk_z) = (x.div_euclid(16), z.div_euclid(16));
+    chunks
+        .entry((chunk_x, chunk_z))
+        .or_insert_with(|| Chunk::new(chunk_x, chunk_z))
+}
+
+impl Generator for SchematicGenerator {
+    fn generate(&self, x: i32, z: i32) -> Chunk {
+        let mut chunk = Chunk::new(x, z);
+        if let Some(stamped) = self.chunks.get(&(x, z)) {
+            chunk.sections = stamped.sections.clone();
+            chunk.block_entities = stamped.block_entities.clone();
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::biomes::biome_name;
+
+    /// A 3x2x2 schematic of stone with a chest on top, in the layout of the given version
+    fn schematic_nbt(version: i32) -> Nbt {
+        let palette = Compound::from([
+            ("minecraft:air".to_owned(), Tag::Int(0)),
+            ("minecraft:stone".to_owned(), Tag::Int(1)),
+            ("minecraft:chest[facing=east]".to_owned(), Tag::Int(2)),
+        ]);
+        // the bottom layer is stone, the top is empty apart from a chest in the corner
+        let data = vec![1, 1, 1, 1, 1, 1, 2, 0, 0, 0, 0, 0];
+        let chest = Compound::from([
+            ("Pos".to_owned(), Tag::IntArray(vec![0, 1, 0])),
+            ("Id".to_owned(), Tag::String("minecraft:chest".to_owned())),
+        ]);
+        let biome_palette = Compound::from([("minecraft:desert".to_owned(), Tag::Int(0))]);
+
+        let mut root = Compound::from([
+            ("Version".to_owned(), Tag::Int(version)),
+            ("DataVersion".to_owned(), Tag::Int(2975)),
+            ("Width".to_owned(), Tag::Short(3)),
+            ("Height".to_owned(), Tag::Short(2)),
+            ("Length".to_owned(), Tag::Short(2)),
+            ("Offset".to_owned(), Tag::IntArray(vec![-1, 0, 0])),
+        ]);
+        if version == 2 {
+            let mut chest = chest;
+            chest.insert("CustomName".to_owned(), Tag::String("\"Loot\"".to_owned()));
+            root.insert("Palette".to_owned(), Tag::Compound(palette));
+            root.insert("BlockData".to_owned(), Tag::ByteArray(data));
+            root.insert("BlockEntities".to_owned(), Tag::List(vec![Tag::Compound(chest)]));
+            root.insert("BiomePalette".to_owned(), Tag::Compound(biome_palette));
+            root.insert("BiomeData".to_owned(), Tag::ByteArray(vec![0; 6]));
+            Nbt {
+                name: "Schematic".to_owned(),
+                root,
+            }
+        } else {
+            let mut chest = chest;
+            let custom = Compound::from([("CustomName".to_owned(), Tag::String("\"Loot\"".to_owned()))]);
+            chest.insert("Data".to_owned(), Tag::Compound(custom));
+            root.insert(
+                "Blocks".to_owned(),
+                Tag::Compound(Compound::from([
+                    ("Palette".to_owned(), Tag::Compound(palette)),
+                    ("Data".to_owned(), Tag::ByteArray(data)),
+                    ("BlockEntities".to_owned(), Tag::List(vec![Tag::Compound(chest)])),
+                ])),
+            );
+            root.insert(
+                "Biomes".to_owned(),
+                Tag::Compound(Compound::from([
+                    ("Palette".to_owned(), Tag::Compound(biome_palette)),
+                    ("Data".to_owned(), Tag::ByteArray(vec![0; 12])),
+                ])),
+            );
+            Nbt {
+                name: String::new(),
+                root: Compound::from([("Schematic".to_owned(), Tag::Compound(root))]),
+            }
+        }
+    }
+
+    #[test]
+    fn both_versions_read_the_same() {
+        for &version in &[2, 3] {
+            let schematic = Schematic::from_nbt(&schematic_nbt(version)).unwrap();
+            assert_eq!(schematic.size(), (3, 2, 2));
+            assert_eq!(schematic.block(2, 0, 1), BlockState::STONE);
+            assert_eq!(schematic.block(0, 1, 0).name(), "minecraft:chest");
+            assert_eq!(schematic.block(0, 1, 0).get_property("facing"), Some("east"));
+            assert!(schematic.block(1, 1, 0).is_air());
+
+            let (position, chest) = &schematic.block_entities[0];
+            assert_eq!(*position, [0, 1, 0]);
+            assert_eq!(chest.kind, block_entity_type_id("minecraft:chest").unwrap());
+            assert_eq!(chest.data.keys().collect::<Vec<_>>(), vec!["CustomName"]);
+        }
+    }
+
+    #[test]
+    fn schematics_are_stamped_across_chunks() {
+        let schematic = Schematic::from_nbt(&schematic_nbt(3)).unwrap();
+        // the offset moves it one block west, so it straddles the chunk border at x=0
+        let generator = SchematicGenerator::new(&schematic, [0, 64, 15]).unwrap();
+
+        let west = generator.generate(-1, 0);
+        assert_eq!(west.get_blockstate(15, 64, 15), BlockState::STONE);
+        assert_eq!(west.get_blockstate(15, 65, 15).name(), "minecraft:chest");
+        assert!(west.block_entities.contains_key(&(15, 65, 15)));
+        assert_eq!(biome_name(west.sections[8].get_biome(3, 0, 3)), Some("minecraft:desert"));
+
+        let east = generator.generate(0, 0);
+        assert_eq!(east.get_blockstate(1, 64, 15), BlockState::STONE);
+        assert!(east.get_blockstate(2, 64, 15).is_air());
+        assert_eq!(generator.generate(0, 1).get_blockstate(0, 64, 0), BlockState::STONE);
+
+        // everywhere else is left empty
+        assert!(generator.generate(5, 5).sections.iter().all(|section| section.block_count() == 0));
+    }
+
+    #[test]
+    fn oversized_schematics_are_rejected() {
+        // a huge header with a tiny body fails before anything's allocated for it
+        let mut nbt = schematic_nbt(2);
+        for key in &["Width", "Height", "Length"] {
+            nbt.root.insert((*key).to_owned(), Tag::Short(-1));
+        }
+        assert!(Schematic::from_nbt(&nbt).is_err());
+
+        let mut nbt = schematic_nbt(2);
+        nbt.root.insert("Width".to_owned(), Tag::Short(4));
+        assert!(Schematic::from_nbt(&nbt).is_err());
+
+        assert!(read_indices(&[0; 10], 11).is_err());
+        assert_eq!(read_indices(&[0; 10], 10).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn block_entities_and_corners_have_to_be_in_range() {
+        let mut nbt = schematic_nbt(2);
+        let chest = Compound::from([
+            ("Pos".to_owned(), Tag::IntArray(vec![3, 0, 0])),
+            ("Id".to_owned(), Tag::String("minecraft:chest".to_owned())),
+        ]);
+        nbt.root.insert("BlockEntities".to_owned(), Tag::List(vec![Tag::Compound(chest)]));
+        assert!(Schematic::from_nbt(&nbt).is_err());
+
+        let schematic = Schematic::from_nbt(&schematic_nbt(2)).unwrap();
+        // the offset of -1 takes it past the edge
+        assert!(SchematicGenerator::new(&schematic, [i32::MIN, 64, 0]).is_err());
+        assert!(SchematicGenerator::new(&schematic, [0, 64, i32::MAX]).is_err());
+    }
+
+    #[test]
+    fn bad_schematics_are_rejected() {
+        let mut nbt = schematic_nbt(2);
+        nbt.root.insert("BlockData".to_owned(), Tag::ByteArray(vec![1, 1]));
+        assert!(Schematic::from_nbt(&nbt).is_err());
+
+        let mut nbt = schematic_nbt(2);
+        nbt.root.insert("Version".to_owned(), Tag::Int(1));
+        assert!(Schematic::from_nbt(&nbt).is_err());
+
+        let schematic = Schematic::from_nbt(&schematic_nbt(2)).unwrap();
+        assert!(SchematicGenerator::new(&schematic, [0, 319, 0]).is_err());
+    }
+}
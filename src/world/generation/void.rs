@@ -0,0 +1,71 @@
+use crate::world::blocks::BlockState;
+use crate::world::generation::Generator;
+use crate::world::world::Chunk;
+
+/// The height of the spawn platform's surface
+const PLATFORM_Y: i32 = 63;
+
+/// How far the spawn platform reaches from its centre at 0, 0, making it 33 blocks across like vanilla's
+const PLATFORM_RADIUS: i32 = 16;
+
+/// Makes nothing but air, with an optional stone platform to spawn on
+pub struct VoidGenerator {
+    platform: bool,
+}
+
+impl VoidGenerator {
+    pub fn new(platform: bool) -> Self {
+        VoidGenerator { platform }
+    }
+}
+
+impl Generator for VoidGenerator {
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::new(chunk_x, chunk_z);
+        if !self.platform {
+            return chunk;
+        }
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let (block_x, block_z) = (chunk_x * 16 + x as i32, chunk_z * 16 + z as i32);
+                if block_x.abs() > PLATFORM_RADIUS || block_z.abs() > PLATFORM_RADIUS {
+                    continue;
+                }
+                // cobblestone marks the middle, where players spawn
+                let block = if block_x == 0 && block_z == 0 {
+                    BlockState::COBBLESTONE
+                } else {
+                    BlockState::STONE
+                };
+                chunk.set_blockstate(block, x, PLATFORM_Y, z);
+            }
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::world::SECTION_COUNT;
+
+    #[test]
+    fn platforms_are_centred_on_spawn() {
+        let generator = VoidGenerator::new(true);
+        let chunk = generator.generate(0, 0);
+        assert_eq!(chunk.get_blockstate(0, PLATFORM_Y, 0), BlockState::COBBLESTONE);
+        assert_eq!(chunk.get_blockstate(15, PLATFORM_Y, 15), BlockState::STONE);
+        assert!(chunk.get_blockstate(0, PLATFORM_Y + 1, 0).is_air());
+
+        assert_eq!(generator.generate(-1, -1).get_blockstate(0, PLATFORM_Y, 0), BlockState::STONE);
+        let corner = generator.generate(1, 1);
+        assert_eq!(corner.get_blockstate(0, PLATFORM_Y, 0), BlockState::STONE);
+        assert!(corner.get_blockstate(1, PLATFORM_Y, 0).is_air());
+        assert!(generator.generate(-2, 0).sections.iter().all(|section| section.block_count() == 0));
+
+        let empty = VoidGenerator::new(false).generate(0, 0);
+        assert_eq!(empty.sections.len(), SECTION_COUNT);
+        assert!(empty.sections.iter().all(|section| section.block_count() == 0));
+    }
+}
@@ -1,2 +1,10 @@
-pub(crate) mod world;
-pub(crate) mod blocks;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod world;
+pub mod anvil;
+pub mod biomes;
+pub mod blocks;
+pub mod codec;
+pub mod generation;
+pub mod palette;
+pub mod thread;
+pub mod view;
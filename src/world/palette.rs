@@ -0,0 +1,575 @@
+use crate::protocol::error::ProtocolError;
+use crate::protocol::structs::{ProtocolVersion, Readable, VarInt, Writeable};
+use crate::world::biomes;
+use crate::world::blocks::{self, BlockState, BLOCK_STATE_BITS};
+use anyhow::Context;
+use std::{collections::HashMap, convert::TryFrom, io::Cursor, marker::PhantomData};
+
+/// What a `PalettedContainer` holds, and how its palette behaves on the wire
+pub trait PaletteKind {
+    type Value: Copy + Eq;
+
+    /// How many entries the container holds
+    const LEN: usize;
+    /// The fewest bits an indirect palette uses per entry, even if it could use fewer
+    const MIN_INDIRECT_BITS: u8;
+    /// The most bits an indirect palette uses, past which entries are global IDs instead
+    const MAX_INDIRECT_BITS: u8;
+    /// The bits a global ID takes up, which the client works out from the size of its registry
+    const GLOBAL_BITS: u8;
+
+    fn to_id(value: Self::Value) -> u32;
+    fn from_id(id: u32) -> Option<Self::Value>;
+
+    /// What clients on `version` call the value with ID `id`, if there's a registry for that version
+    fn network_id(id: u32, version: ProtocolVersion) -> Option<u32>;
+    /// The bits a global ID takes up on `version`, if there's a registry for it
+    fn network_bits(version: ProtocolVersion) -> Option<u8>;
+}
+
+/// The 16x16x16 block states of a chunk section
+pub struct Blocks;
+
+impl PaletteKind for Blocks {
+    type Value = BlockState;
+
+    const LEN: usize = 16 * 16 * 16;
+    const MIN_INDIRECT_BITS: u8 = 4;
+    const MAX_INDIRECT_BITS: u8 = 8;
+    const GLOBAL_BITS: u8 = BLOCK_STATE_BITS;
+
+    fn to_id(value: BlockState) -> u32 {
+        value.id() as u32
+    }
+
+    fn from_id(id: u32) -> Option<BlockState> {
+        u16::try_from(id).ok().and_then(BlockState::from_id)
+    }
+
+    fn network_id(id: u32, version: ProtocolVersion) -> Option<u32> {
+        Self::from_id(id)?.network_id(version).map(u32::from)
+    }
+
+    fn network_bits(version: ProtocolVersion) -> Option<u8> {
+        blocks::network_bits(version)
+    }
+}
+
+/// The 4x4x4 biomes of a chunk section, as IDs in the biome registry
+pub struct Biomes;
+
+impl PaletteKind for Biomes {
+    type Value = u32;
+
+    const LEN: usize = 4 * 4 * 4;
+    const MIN_INDIRECT_BITS: u8 = 1;
+    const MAX_INDIRECT_BITS: u8 = 3;
+    /// Enough for the 61 biomes in vanilla's 1.18.2 registry
+    const GLOBAL_BITS: u8 = 6;
+
+    fn to_id(value: u32) -> u32 {
+        value
+    }
+
+    fn from_id(id: u32) -> Option<u32> {
+        (id < 1 << Self::GLOBAL_BITS).then_some(id)
+    }
+
+    fn network_id(id: u32, version: ProtocolVersion) -> Option<u32> {
+        biomes::network_id(id, version)
+    }
+
+    fn network_bits(version: ProtocolVersion) -> Option<u8> {
+        biomes::network_bits(version)
+    }
+}
+
+/// Fixed width entries packed into longs, the way 1.16+ lays them out: entries never straddle two longs,
+/// and whatever bits are left over at the top of each long go unused.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BitStorage {
+    bits: u8,
+    data: Vec<u64>,
+}
+
+impl BitStorage {
+    pub(crate) fn new(bits: u8, len: usize) -> Self {
+        BitStorage {
+            bits,
+            data: vec![0; Self::longs_needed(bits, len)],
+        }
+    }
+
+    fn longs_needed(bits: u8, len: usize) -> usize {
+        let per_long = 64 / bits as usize;
+        len.div_ceil(per_long)
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    /// The long `index` is packed into, and how far up it the entry starts
+    fn position(&self, index: usize) -> (usize, usize) {
+        let per_long = 64 / self.bits as usize;
+        (index / per_long, (index % per_long) * self.bits as usize)
+    }
+
+    pub(crate) fn get(&self, index: usize) -> u32 {
+        let (long, offset) = self.position(index);
+        ((self.data[long] >> offset) & self.mask()) as u32
+    }
+
+    pub(crate) fn set(&mut self, index: usize, value: u32) {
+        let (long, offset) = self.position(index);
+        let mask = self.mask();
+        self.data[long] = (self.data[long] & !(mask << offset)) | ((value as u64 & mask) << offset);
+    }
+
+    pub(crate) fn into_data(self) -> Vec<u64> {
+        self.data
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Palette {
+    /// Every entry is the same, so there's no storage at all
+    Single(u32),
+    /// Storage holds indexes into a list of the IDs actually in use
+    Indirect(Vec<u32>),
+    /// Storage holds global IDs directly
+    Global,
+}
+
+/// Stores a section's worth of values in as few bits as the variety of values allows, switching between a
+/// single value, an indirect palette and global IDs as values are set. This is the layout 1.18 sends in
+/// Chunk Data, so it's written as-is apart from renumbering IDs for newer clients.
+pub struct PalettedContainer<K: PaletteKind> {
+    palette: Palette,
+    /// `None` while there's a single value
+    storage: Option<BitStorage>,
+    kind: PhantomData<K>,
+}
+
+impl<K: PaletteKind> Clone for PalettedContainer<K> {
+    fn clone(&self) -> Self {
+        PalettedContainer {
+            palette: self.palette.clone(),
+            storage: self.storage.clone(),
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<K: PaletteKind> PalettedContainer<K> {
+    /// A container with every entry set to `value`
+    pub fn filled(value: K::Value) -> Self {
+        PalettedContainer {
+            palette: Palette::Single(K::to_id(value)),
+            storage: None,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> K::Value {
+        assert!(index < K::LEN, "index {} out of bounds", index);
+
+        let id = match (&self.palette, &self.storage) {
+            (Palette::Single(id), _) => *id,
+            (Palette::Indirect(palette), Some(storage)) => palette[storage.get(index) as usize],
+            (Palette::Global, Some(storage)) => storage.get(index),
+            _ => unreachable!("only a single value palette has no storage"),
+        };
+        K::from_id(id).expect("container only holds valid IDs")
+    }
+
+    pub fn set(&mut self, index: usize, value: K::Value) {
+        assert!(index < K::LEN, "index {} out of bounds", index);
+        let id = K::to_id(value);
+        let bits = self.bits();
+
+        let entry = match &mut self.palette {
+            Palette::Single(single) if *single == id => return,
+            Palette::Single(single) => {
+                let single = *single;
+                self.palette = Palette::Indirect(vec![single]);
+                self.storage = Some(BitStorage::new(K::MIN_INDIRECT_BITS, K::LEN));
+                return self.set(index, value);
+            }
+            Palette::Indirect(palette) => match palette.iter().position(|&entry| entry == id) {
+                Some(entry) => entry as u32,
+                None => {
+                    if palette.len() == 1 << bits {
+                        self.resize(bits + 1);
+                        return self.set(index, value);
+                    }
+                    palette.push(id);
+                    (palette.len() - 1) as u32
+                }
+            },
+            Palette::Global => id,
+        };
+
+        self.storage.as_mut().expect("only a single value palette has no storage").set(index, entry);
+    }
+
+    /// How many bits each entry takes up, 0 for a single value
+    pub fn bits(&self) -> u8 {
+        self.storage.as_ref().map_or(0, |storage| storage.bits)
+    }
+
+    /// Builds a container out of a palette of any size and entries packed as indexes into it, which is how world
+    /// files store sections. `None` if the data is the wrong length or points past the end of the palette.
+    pub fn from_palette(palette: &[K::Value], data: Vec<u64>) -> Option<Self> {
+        let mut container = PalettedContainer::filled(*palette.first()?);
+        if palette.len() == 1 {
+            return Some(container);
+        }
+
+        let bits = Self::palette_bits(palette.len());
+        if data.len() != BitStorage::longs_needed(bits, K::LEN) {
+            return None;
+        }
+        let storage = BitStorage { bits, data };
+        for index in 0..K::LEN {
+            container.set(index, *palette.get(storage.get(index) as usize)?);
+        }
+
+        Some(container)
+    }
+
+    /// The values in the container in the order they first appear, along with every entry packed as an index into
+    /// them. The data is empty when there's only one value.
+    pub fn to_palette(&self) -> (Vec<K::Value>, Vec<u64>) {
+        let mut palette = Vec::new();
+        let mut indexes = HashMap::new();
+        let entries: Vec<u32> = (0..K::LEN)
+            .map(|index| {
+                let value = self.get(index);
+                *indexes.entry(K::to_id(value)).or_insert_with(|| {
+                    palette.push(value);
+                    (palette.len() - 1) as u32
+                })
+            })
+            .collect();
+
+        if palette.len() == 1 {
+            return (palette, Vec::new());
+        }
+        let mut storage = BitStorage::new(Self::palette_bits(palette.len()), K::LEN);
+        for (index, entry) in entries.into_iter().enumerate() {
+            storage.set(index, entry);
+        }
+
+        (palette, storage.into_data())
+    }
+
+    /// How many bits world files pack a palette of `len` entries into, which unlike the network format never
+    /// switches to global IDs
+    fn palette_bits(len: usize) -> u8 {
+        let bits = (usize::BITS - (len - 1).leading_zeros()) as u8;
+        bits.max(K::MIN_INDIRECT_BITS)
+    }
+
+    /// Repacks an indirect palette's storage with `bits` per entry, switching to global IDs if that's too many
+    fn resize(&mut self, bits: u8) {
+        let (palette, old) = match (&self.palette, self.storage.take()) {
+            (Palette::Indirect(palette), Some(old)) => (palette, old),
+            _ => unreachable!("only indirect palettes are resized"),
+        };
+
+        let mut storage;
+        if bits > K::MAX_INDIRECT_BITS {
+            storage = BitStorage::new(K::GLOBAL_BITS, K::LEN);
+            for index in 0..K::LEN {
+                storage.set(index, palette[old.get(index) as usize]);
+            }
+            self.palette = Palette::Global;
+        } else {
+            // the palette stays the same, so entries just need more room
+            storage = BitStorage::new(bits, K::LEN);
+            for index in 0..K::LEN {
+                storage.set(index, old.get(index));
+            }
+        }
+
+        self.storage = Some(storage);
+    }
+}
+
+impl<'de, K: PaletteKind> Readable<'de> for PalettedContainer<K> {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError>
+    where
+        Self: Sized,
+    {
+        let invalid = |what, value: String| ProtocolError::InvalidValue { what, value };
+        let read_id = |buffer: &mut Cursor<&'de [u8]>| -> Result<u32, ProtocolError> {
+            let id = VarInt::read(buffer, version)?.0 as u32;
+            match K::from_id(id) {
+                Some(_) => Ok(id),
+                None => Err(invalid("palette entry", id.to_string())),
+            }
+        };
+
+        let bits = u8::read(buffer, version)?;
+        let (palette, bits) = match bits {
+            0 => (Palette::Single(read_id(buffer)?), 0),
+            bits if bits <= K::MAX_INDIRECT_BITS => {
+                // the palette can only be as long as the entries are able to index
+                let bits = bits.max(K::MIN_INDIRECT_BITS);
+                let len = VarInt::read(buffer, version)?.0;
+                if len <= 0 || len > 1 << bits {
+                    return Err(invalid("palette length", format!("{} for {} bits", len, bits)));
+                }
+                let palette = (0..len).map(|_| read_id(buffer)).collect::<Result<_, _>>()?;
+                (Palette::Indirect(palette), bits)
+            }
+            // the client ignores the width sent for global IDs and uses its own
+            _ => (Palette::Global, K::GLOBAL_BITS),
+        };
+
+        let len = VarInt::read(buffer, version)?.0;
+        let expected = if bits == 0 { 0 } else { BitStorage::longs_needed(bits, K::LEN) };
+        if len as usize != expected {
+            return Err(invalid("data length", format!("{}, expected {} longs", len, expected)));
+        }
+        let data = (0..len).map(|_| u64::read(buffer, version)).collect::<Result<_, _>>()?;
+
+        let storage = match bits {
+            0 => None,
+            bits => Some(BitStorage { bits, data }),
+        };
+
+        // make sure every entry points at something real, so `get` can't fail later
+        if let Some(storage) = &storage {
+            for index in 0..K::LEN {
+                let entry = storage.get(index);
+                let valid = match &palette {
+                    Palette::Indirect(palette) => (entry as usize) < palette.len(),
+                    _ => K::from_id(entry).is_some(),
+                };
+                if !valid {
+                    return Err(invalid("entry", format!("{} at index {}", entry, index)));
+                }
+            }
+        }
+
+        Ok(PalettedContainer {
+            palette,
+            storage,
+            kind: PhantomData,
+        })
+    }
+}
+
+impl<K: PaletteKind> Writeable for PalettedContainer<K> {
+    /// Writes the container with IDs as clients on `version` number them, which for global IDs means packing
+    /// them again at that version's width
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        let network_id = |id: u32| {
+            K::network_id(id, version).with_context(|| format!("no ID for {} on {}", id, version.name()))
+        };
+
+        let mut repacked = None;
+        match &self.palette {
+            Palette::Single(id) => {
+                self.bits().write(buffer, version)?;
+                VarInt(network_id(*id)? as i32).write(buffer, version)?;
+            }
+            Palette::Indirect(palette) => {
+                self.bits().write(buffer, version)?;
+                VarInt(palette.len() as i32).write(buffer, version)?;
+                for id in palette {
+                    VarInt(network_id(*id)? as i32).write(buffer, version)?;
+                }
+            }
+            Palette::Global => {
+                let bits = K::network_bits(version).with_context(|| format!("no registry for {}", version.name()))?;
+                let storage = self.storage.as_ref().expect("global palettes have storage");
+                let mut global = BitStorage::new(bits, K::LEN);
+                for index in 0..K::LEN {
+                    global.set(index, network_id(storage.get(index))?);
+                }
+                bits.write(buffer, version)?;
+                repacked = Some(global);
+            }
+        }
+
+        let storage = repacked.as_ref().or(self.storage.as_ref());
+        let data = storage.map_or(&[][..], |storage| &storage.data[..]);
+        VarInt(data.len() as i32).write(buffer, version)?;
+        for long in data {
+            long.write(buffer, version)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSION: ProtocolVersion = ProtocolVersion::V1_18_2;
+
+    fn state(id: u16) -> BlockState {
+        BlockState::from_id(id).unwrap()
+    }
+
+    fn round_trip(container: &PalettedContainer<Blocks>) -> PalettedContainer<Blocks> {
+        let mut buffer = Vec::new();
+        container.write(&mut buffer, VERSION).unwrap();
+        PalettedContainer::read(&mut Cursor::new(&buffer[..]), VERSION).unwrap()
+    }
+
+    #[test]
+    fn single_value_takes_no_storage() {
+        let container = PalettedContainer::<Blocks>::filled(state(1));
+        assert_eq!(container.bits(), 0);
+        assert_eq!(container.get(4095), state(1));
+
+        let mut buffer = Vec::new();
+        container.write(&mut buffer, VERSION).unwrap();
+        // no bits, a palette of just state 1, and no data
+        assert_eq!(buffer, [0, 1, 0]);
+    }
+
+    #[test]
+    fn ids_are_renumbered_for_the_clients_version() {
+        let single = PalettedContainer::<Blocks>::filled(BlockState::OAK_STAIRS);
+        let mut buffer = Vec::new();
+        single.write(&mut buffer, ProtocolVersion::V1_19).unwrap();
+        // 2021 here, 2219 on 1.19
+        assert_eq!(buffer, [0, 0xAB, 0x11, 0]);
+        assert!(single.write(&mut Vec::new(), ProtocolVersion::V1_20_1).is_err());
+
+        let mut global = PalettedContainer::<Blocks>::filled(state(0));
+        for id in 0..300 {
+            global.set(id as usize, state(id));
+        }
+        global.set(4095, BlockState::OAK_STAIRS);
+        let mut buffer = Vec::new();
+        global.write(&mut buffer, ProtocolVersion::V1_19).unwrap();
+        let read = PalettedContainer::<Blocks>::read(&mut Cursor::new(&buffer[..]), VERSION).unwrap();
+        assert_eq!(read.get(1), state(1));
+        assert_eq!(read.get(4095).id(), 2219);
+    }
+
+    #[test]
+    fn grows_through_every_palette() {
+        let mut container = PalettedContainer::<Blocks>::filled(state(0));
+
+        container.set(0, state(1));
+        assert_eq!(container.bits(), 4);
+
+        for id in 0..17 {
+            container.set(id as usize, state(id));
+        }
+        assert_eq!(container.bits(), 5);
+
+        for id in 0..300 {
+            container.set(id as usize * 13, state(id * 50));
+        }
+        assert_eq!(container.bits(), BLOCK_STATE_BITS);
+        assert_eq!(container.get(13 * 299), state(299 * 50));
+        assert_eq!(container.get(4095), state(0));
+    }
+
+    #[test]
+    fn entries_survive_round_trips_and_resizes() {
+        let mut container = PalettedContainer::<Blocks>::filled(state(0));
+        let expected: Vec<BlockState> = (0..4096).map(|i| state((i * 7 % 40) as u16)).collect();
+        for (index, value) in expected.iter().enumerate() {
+            container.set(index, *value);
+        }
+
+        let read = round_trip(&container);
+        assert_eq!(read.bits(), 6);
+        for (index, value) in expected.iter().enumerate() {
+            assert_eq!(read.get(index), *value);
+        }
+    }
+
+    #[test]
+    fn packing_matches_vanilla() {
+        // 4 bits per entry, so 16 entries to a long, with entry 0 in the lowest bits
+        let mut container = PalettedContainer::<Blocks>::filled(state(0));
+        container.set(0, state(9));
+        container.set(15, state(9));
+        container.set(16, state(9));
+
+        let storage = container.storage.as_ref().unwrap();
+        assert_eq!(storage.data.len(), 256);
+        assert_eq!(storage.data[0], 1 | 1 << 60);
+        assert_eq!(storage.data[1], 1);
+
+        // 5 bits leaves 4 unused at the top of each long instead of splitting an entry
+        assert_eq!(BitStorage::longs_needed(5, 4096), 342);
+    }
+
+    #[test]
+    fn bad_data_is_rejected() {
+        let read = |bytes: &[u8]| PalettedContainer::<Blocks>::read(&mut Cursor::new(bytes), VERSION);
+
+        // indirect palette of one entry, but data that points past it
+        let mut bytes = vec![4, 1, 0, 0x80, 0x02];
+        bytes.extend_from_slice(&[0xff; 256 * 8]);
+        assert!(read(&bytes).is_err());
+
+        // wrong amount of data
+        assert!(read(&[4, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn palettes_longer_than_their_bits_are_rejected() {
+        let palette = |len: u8| {
+            // 4 bits, `len` states, then data that only uses the first of them
+            let mut bytes = vec![4, len];
+            bytes.extend(0..len);
+            bytes.extend_from_slice(&[0x80, 0x02]);
+            bytes.extend_from_slice(&[0; 256 * 8]);
+            PalettedContainer::<Blocks>::read(&mut Cursor::new(&bytes[..]), VERSION)
+        };
+
+        let mut read = palette(16).unwrap();
+        assert_eq!(read.bits(), 4);
+        // a full palette has to grow before taking anything new
+        read.set(1, state(16));
+        assert_eq!(read.bits(), 5);
+        assert_eq!(round_trip(&read).get(1), state(16));
+
+        assert!(palette(17).is_err());
+    }
+
+    #[test]
+    fn palettes_of_any_size_convert() {
+        let mut container = PalettedContainer::<Blocks>::filled(state(5));
+        assert_eq!(container.to_palette(), (vec![state(5)], Vec::new()));
+
+        // more values than an indirect palette holds, which world files still store as a palette
+        for index in 0..4096 {
+            container.set(index, state((index % 300) as u16 + 1));
+        }
+        let (palette, data) = container.to_palette();
+        assert_eq!(palette.len(), 300);
+        assert_eq!(data.len(), BitStorage::longs_needed(9, 4096));
+
+        let read = PalettedContainer::<Blocks>::from_palette(&palette, data.clone()).unwrap();
+        assert_eq!(read.bits(), BLOCK_STATE_BITS);
+        assert_eq!(read.get(299), state(300));
+        assert!(PalettedContainer::<Blocks>::from_palette(&palette[..200], data).is_none());
+        assert!(PalettedContainer::<Blocks>::from_palette(&palette, vec![0; 3]).is_none());
+    }
+
+    #[test]
+    fn biomes_use_their_own_widths() {
+        let mut container = PalettedContainer::<Biomes>::filled(0);
+        container.set(1, 1);
+        assert_eq!(container.bits(), 1);
+        for id in 0..9 {
+            container.set(id as usize, id);
+        }
+        assert_eq!(container.bits(), Biomes::GLOBAL_BITS);
+        assert_eq!(container.get(8), 8);
+    }
+}
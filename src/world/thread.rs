@@ -0,0 +1,290 @@
+//! The world runs on a thread of its own, so region IO, compression and chunk encoding never hold up a network
+//! task. Connections talk to it over channels.
+
+use crate::nbt::Nbt;
+use crate::protocol::server::{JoinGame, ServerPlayPacket, SynchronizePlayerPosition};
+use crate::protocol::structs::{ProtocolVersion, VarInt};
+use crate::util::log::{self, ThreadName};
+use crate::world::codec;
+use crate::world::world::Dimension;
+use anyhow::Context;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// How long the world waits for a request before checking for generated chunks anyway
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The names of vanilla's dimensions, which are also the names of their dimension types
+pub const OVERWORLD: &str = "minecraft:overworld";
+pub const THE_NETHER: &str = "minecraft:the_nether";
+pub const THE_END: &str = "minecraft:the_end";
+
+/// Where everyone spawns for now: the middle of chunk 0, 0, at the top of the world to fall onto whatever's there
+const SPAWN: (f64, f64, f64) = (0.5, 320.0, 0.5);
+
+/// Creative, since there's nothing to do in survival yet, and it means falling from the spawn doesn't hurt
+const GAME_MODE: u8 = 1;
+
+enum Request {
+    Join {
+        player: Uuid,
+        version: ProtocolVersion,
+        packets: Sender<ServerPlayPacket<'static>>,
+    },
+    Move {
+        player: Uuid,
+        x: i32,
+        z: i32,
+    },
+    Leave {
+        player: Uuid,
+    },
+}
+
+/// A player in the world, as far as the world thread cares
+struct Player {
+    version: ProtocolVersion,
+    packets: Sender<ServerPlayPacket<'static>>,
+}
+
+/// The thread that owns the world. Requests are handled in the order they're made, and each player's packets come
+/// out in the order they're sent, so a chunk can never turn up after it's been unloaded.
+///
+/// Dropping it saves everything and waits for the thread to finish.
+pub struct WorldThread {
+    requests: Option<Sender<Request>>,
+    thread: Option<JoinHandle<Dimensions>>,
+}
+
+/// Every dimension along with its name, the first being the one players spawn into
+pub type Dimensions = Vec<(&'static str, Dimension)>;
+
+impl WorldThread {
+    /// Starts the thread with `dimensions`, of which there has to be at least one. Players spawn into the first,
+    /// and there's no way to the others yet
+    pub fn start(dimensions: Dimensions) -> Self {
+        assert!(!dimensions.is_empty(), "the world needs a dimension to spawn players into");
+        let (requests, receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("World".to_owned())
+            .spawn(move || run(dimensions, receiver))
+            .expect("unable to start world thread");
+
+        WorldThread {
+            requests: Some(requests),
+            thread: Some(thread),
+        }
+    }
+
+    /// Spawns a player into the world, whose packets come out of the returned receiver encoded for `version`,
+    /// starting with Join Game and their position
+    pub fn join(&self, player: Uuid, version: ProtocolVersion) -> Receiver<ServerPlayPacket<'static>> {
+        let (packets, receiver) = mpsc::channel();
+        self.request(Request::Join { player, version, packets });
+        receiver
+    }
+
+    /// Centres a player's view on the given chunk coords
+    pub fn move_player(&self, player: Uuid, x: i32, z: i32) {
+        self.request(Request::Move { player, x, z });
+    }
+
+    /// Takes a player out of the world, unloading whatever only they could see
+    pub fn leave(&self, player: Uuid) {
+        self.request(Request::Leave { player });
+    }
+
+    fn request(&self, request: Request) {
+        // the thread only stops once this is dropped, unless it panicked, which has already been reported
+        if let Some(requests) = &self.requests {
+            let _ = requests.send(request);
+        }
+    }
+
+    /// Saves everything and stops the thread, handing back the world once every request before this is done
+    pub fn stop(mut self) -> Option<Dimensions> {
+        self.requests = None;
+        self.thread.take().and_then(|thread| thread.join().ok())
+    }
+}
+
+impl Drop for WorldThread {
+    fn drop(&mut self) {
+        self.requests = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The world thread's loop, which goes until every sender of requests is dropped
+fn run(mut dimensions: Dimensions, requests: Receiver<Request>) -> Dimensions {
+    let mut players: HashMap<Uuid, Player> = HashMap::new();
+    let mut last_entity_id = 0;
+    let names: Vec<&str> = dimensions.iter().map(|&(name, _)| name).collect();
+
+    loop {
+        let (spawn_name, spawn) = &mut dimensions[0];
+        match requests.recv_timeout(TICK_INTERVAL) {
+            Ok(Request::Join { player, version, packets }) => {
+                let joined = Player { version, packets };
+                last_entity_id += 1;
+                // the client needs to be in a world and somewhere in it before it can do anything with chunks
+                let spawned = join_game(&names, spawn_name, spawn, version, last_entity_id).and_then(|join| {
+                    send(&joined, vec![join.into(), spawn_position().into()]);
+                    let (x, _, z) = SPAWN;
+                    spawn.move_viewer(player, version, (x.floor() as i32) >> 4, (z.floor() as i32) >> 4)
+                });
+                match spawned {
+                    Ok(packets) => send(&joined, packets),
+                    Err(e) => log::warn(ThreadName::Chunk, &format!("Unable to spawn {}: {:#}", player, e)),
+                }
+                players.insert(player, joined);
+            }
+            Ok(Request::Move { player, x, z }) => {
+                if let Some(joined) = players.get(&player) {
+                    match spawn.move_viewer(player, joined.version, x, z) {
+                        Ok(packets) => send(joined, packets),
+                        Err(e) => log::warn(ThreadName::Chunk, &format!("Unable to move {}'s view: {:#}", player, e)),
+                    }
+                }
+            }
+            Ok(Request::Leave { player }) => {
+                if players.remove(&player).is_some() {
+                    spawn.remove_viewer(player);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        for (name, dimension) in &mut dimensions {
+            match dimension.receive_generated() {
+                Ok(packets) => {
+                    for (player, packet) in packets {
+                        if let Some(player) = players.get(&player) {
+                            send(player, vec![packet]);
+                        }
+                    }
+                }
+                Err(e) => log::warn(ThreadName::Chunk, &format!("Unable to send {}'s generated chunks: {:#}", name, e)),
+            }
+        }
+    }
+
+    for (name, dimension) in &mut dimensions {
+        if let Err(e) = dimension.save_all() {
+            log::error(ThreadName::Chunk, &format!("Unable to save {}: {:#}", name, e));
+        }
+    }
+    dimensions
+}
+
+/// Join Game for a player on `version`, putting them in the dimension called `name`
+fn join_game(
+    names: &[&str],
+    name: &str,
+    dimension: &Dimension,
+    version: ProtocolVersion,
+    entity_id: i32,
+) -> anyhow::Result<JoinGame<'static>> {
+    let registry_codec = codec::codec(version).with_context(|| format!("no registry codec for {}", version.name()))?;
+    let dimension_type = codec::dimension_type(version, name).with_context(|| format!("no dimension type {}", name))?;
+
+    Ok(JoinGame {
+        entity_id,
+        is_hardcore: false,
+        game_mode: GAME_MODE,
+        previous_game_mode: -1,
+        dimension_names: names.iter().map(|&name| name.to_owned()).collect::<Vec<_>>().into(),
+        registry_codec: registry_codec.clone(),
+        dimension: Nbt::from(dimension_type.clone()),
+        dimension_type: name.to_owned(),
+        dimension_name: name.to_owned(),
+        hashed_seed: 0,
+        max_players: VarInt(0),
+        view_distance: VarInt(dimension.tracker().view_distance() as i32),
+        simulation_distance: VarInt(dimension.tracker().simulation_distance() as i32),
+        reduced_debug_info: false,
+        enable_respawn_screen: true,
+        is_debug: false,
+        is_flat: false,
+        death_location: None,
+        portal_cooldown: VarInt(0),
+    })
+}
+
+fn spawn_position() -> SynchronizePlayerPosition {
+    let (x, y, z) = SPAWN;
+    SynchronizePlayerPosition {
+        x,
+        y,
+        z,
+        yaw: 0.0,
+        pitch: 0.0,
+        flags: 0,
+        teleport_id: VarInt(0),
+        dismount_vehicle: false,
+    }
+}
+
+fn send(player: &Player, packets: Vec<ServerPlayPacket<'static>>) {
+    for packet in packets {
+        // a player whose connection is gone is about to leave
+        if player.packets.send(packet).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::generation::ChunkGenerator;
+    use crate::world::world::Chunk;
+
+    #[test]
+    fn players_spawn_before_chunks_and_chunks_out_of_view_stay_unloaded() {
+        let overworld = Dimension::new(None, ChunkGenerator::new(Chunk::new, 2), 2, 2);
+        let nether = Dimension::new(None, ChunkGenerator::new(Chunk::new, 1), 2, 2);
+        let world = WorldThread::start(vec![(OVERWORLD, overworld), (THE_NETHER, nether)]);
+        let player = Uuid::from_u128(1);
+        let packets = world.join(player, ProtocolVersion::V1_18_2);
+        let first = packets.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(&first, ServerPlayPacket::JoinGame(join) if join.view_distance.0 == 2));
+        assert!(matches!(first, ServerPlayPacket::JoinGame(join) if join.dimension_names.0.len() == 2));
+        let second = packets.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(second, ServerPlayPacket::SynchronizePlayerPosition(position) if position.y == SPAWN.1));
+        thread::sleep(Duration::from_millis(20));
+        world.move_player(player, 10, 0);
+
+        // whatever was generated in between, the last word on every chunk of the old view is that it's gone
+        let mut loaded = HashMap::new();
+        let mut track = |packet| {
+            match packet {
+                ServerPlayPacket::ChunkDataAndUpdateLight(chunk) => loaded.insert((chunk.chunk_x, chunk.chunk_z), true),
+                ServerPlayPacket::UnloadChunk(chunk) => loaded.insert((chunk.chunk_x, chunk.chunk_z), false),
+                _ => None,
+            };
+        };
+        while let Ok(packet) = packets.recv_timeout(Duration::from_secs(10)) {
+            if matches!(&packet, ServerPlayPacket::ChunkDataAndUpdateLight(chunk) if chunk.chunk_x == 10) {
+                track(packet);
+                break;
+            }
+            track(packet);
+        }
+        world.leave(player);
+        let dimensions = world.stop().unwrap();
+        packets.try_iter().for_each(&mut track);
+
+        assert_eq!(dimensions[0].1.tracker().viewer(player), None);
+        assert!(loaded.iter().any(|(&(x, _), &loaded)| x == 10 && loaded));
+        assert!(loaded.iter().all(|(&(x, _), &loaded)| loaded == (x >= 8)));
+    }
+}
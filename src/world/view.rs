@@ -0,0 +1,243 @@
+use std::collections::{hash_map::Entry, HashMap};
+use uuid::Uuid;
+
+/// Every chunk within `radius` chunks of `center`, ring by ring from the centre outwards.
+///
+/// This is the order chunks are loaded and sent in, so the ones right around the player show up first.
+pub fn spiral(center: (i32, i32), radius: u8) -> impl Iterator<Item = (i32, i32)> {
+    let (x, z) = center;
+    std::iter::once(center).chain((1..=radius as i32).flat_map(move |ring| {
+        // each side of the ring, walked clockwise from the north west corner
+        (0..8 * ring).map(move |i| {
+            let step = i % (2 * ring);
+            match i / (2 * ring) {
+                0 => (x - ring + step, z - ring),
+                1 => (x + ring, z - ring + step),
+                2 => (x + ring - step, z + ring),
+                _ => (x - ring, z + ring - step),
+            }
+        })
+    }))
+}
+
+/// Whether a chunk is within `distance` chunks of `center`, counting diagonals like the client does
+fn within(center: (i32, i32), chunk: (i32, i32), distance: u8) -> bool {
+    let distance = distance as i64;
+    (chunk.0 as i64 - center.0 as i64).abs() <= distance && (chunk.1 as i64 - center.1 as i64).abs() <= distance
+}
+
+/// Keeps track of which chunks each player can see, so a chunk is only loaded while at least one player can.
+pub struct ChunkTracker {
+    view_distance: u8,
+    simulation_distance: u8,
+    /// The chunk each player's view is centred on
+    viewers: HashMap<Uuid, (i32, i32)>,
+    /// How many players can see each chunk, leaving out the ones nobody can
+    references: HashMap<(i32, i32), usize>,
+}
+
+/// What changed when a player's view moved
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ViewChange {
+    /// The chunk the player's view is now centred on, unless they've stopped viewing altogether
+    pub center: Option<(i32, i32)>,
+    /// The chunks the player can now see, closest first
+    pub shown: Vec<(i32, i32)>,
+    /// The chunks the player can't see anymore
+    pub hidden: Vec<(i32, i32)>,
+    /// The chunks nobody could see before, which need loading, closest first
+    pub loaded: Vec<(i32, i32)>,
+    /// The chunks nobody can see anymore, which can be saved and unloaded
+    pub unloaded: Vec<(i32, i32)>,
+}
+
+impl ChunkTracker {
+    /// The simulation distance is capped at the view distance, since chunks nobody can see aren't loaded to be ticked
+    pub fn new(view_distance: u8, simulation_distance: u8) -> Self {
+        ChunkTracker {
+            view_distance,
+            simulation_distance: simulation_distance.min(view_distance),
+            viewers: HashMap::new(),
+            references: HashMap::new(),
+        }
+    }
+
+    pub fn view_distance(&self) -> u8 {
+        self.view_distance
+    }
+
+    pub fn simulation_distance(&self) -> u8 {
+        self.simulation_distance
+    }
+
+    /// The chunk the player's view is centred on, if they're viewing this dimension
+    pub fn viewer(&self, player: Uuid) -> Option<(i32, i32)> {
+        self.viewers.get(&player).copied()
+    }
+
+    /// How many players can see the chunk at the given chunk coords
+    pub fn references(&self, x: i32, z: i32) -> usize {
+        self.references.get(&(x, z)).copied().unwrap_or(0)
+    }
+
+    /// How many chunks away the closest player is from the chunk at the given chunk coords
+    pub fn distance(&self, x: i32, z: i32) -> Option<u32> {
+        self.viewers
+            .values()
+            .map(|&(center_x, center_z)| {
+                let dx = (x as i64 - center_x as i64).unsigned_abs();
+                let dz = (z as i64 - center_z as i64).unsigned_abs();
+                dx.max(dz).min(u32::MAX as u64) as u32
+            })
+            .min()
+    }
+
+    /// Every player that can see the chunk at the given chunk coords
+    pub fn viewers_of(&self, x: i32, z: i32) -> impl Iterator<Item = Uuid> + '_ {
+        let distance = self.view_distance;
+        self.viewers
+            .iter()
+            .filter(move |(_, &center)| within(center, (x, z), distance))
+            .map(|(&player, _)| player)
+    }
+
+    /// Whether the chunk at the given chunk coords is close enough to a player to be ticked
+    pub fn is_simulated(&self, x: i32, z: i32) -> bool {
+        self.viewers
+            .values()
+            .any(|&center| within(center, (x, z), self.simulation_distance))
+    }
+
+    /// Centres the player's view on the given chunk, starting to track them if they weren't already
+    pub fn move_viewer(&mut self, player: Uuid, center: (i32, i32)) -> ViewChange {
+        let previous = self.viewers.insert(player, center);
+        if previous == Some(center) {
+            return ViewChange::default();
+        }
+
+        let mut change = ViewChange {
+            center: Some(center),
+            ..ViewChange::default()
+        };
+        for chunk in spiral(center, self.view_distance) {
+            if previous.is_none_or(|previous| !within(previous, chunk, self.view_distance)) {
+                self.acquire(chunk, &mut change);
+            }
+        }
+        if let Some(previous) = previous {
+            for chunk in spiral(previous, self.view_distance) {
+                if !within(center, chunk, self.view_distance) {
+                    self.release(chunk, &mut change);
+                }
+            }
+        }
+
+        change
+    }
+
+    /// Stops tracking the player, releasing every chunk they could see
+    pub fn remove_viewer(&mut self, player: Uuid) -> ViewChange {
+        let mut change = ViewChange::default();
+        if let Some(previous) = self.viewers.remove(&player) {
+            for chunk in spiral(previous, self.view_distance) {
+                self.release(chunk, &mut change);
+            }
+        }
+        change
+    }
+
+    fn acquire(&mut self, chunk: (i32, i32), change: &mut ViewChange) {
+        change.shown.push(chunk);
+        let count = self.references.entry(chunk).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            change.loaded.push(chunk);
+        }
+    }
+
+    fn release(&mut self, chunk: (i32, i32), change: &mut ViewChange) {
+        change.hidden.push(chunk);
+        if let Entry::Occupied(mut count) = self.references.entry(chunk) {
+            *count.get_mut() -= 1;
+            if *count.get() == 0 {
+                count.remove();
+                change.unloaded.push(chunk);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn spiral_covers_the_square_closest_first() {
+        let chunks: Vec<_> = spiral((5, -3), 3).collect();
+        assert_eq!(chunks.len(), 7 * 7);
+        assert_eq!(chunks.iter().collect::<HashSet<_>>().len(), chunks.len());
+        assert_eq!(chunks[0], (5, -3));
+
+        let rings: Vec<_> = chunks
+            .iter()
+            .map(|&(x, z)| (x - 5).abs().max((z + 3).abs()))
+            .collect();
+        assert!(rings.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(rings.last(), Some(&3));
+    }
+
+    #[test]
+    fn chunks_stay_loaded_while_anyone_can_see_them() {
+        let mut tracker = ChunkTracker::new(2, 10);
+        let (alice, bob) = (Uuid::from_u128(1), Uuid::from_u128(2));
+
+        let change = tracker.move_viewer(alice, (0, 0));
+        assert_eq!(change.center, Some((0, 0)));
+        assert_eq!(change.shown.len(), 25);
+        assert_eq!(change.loaded, change.shown);
+
+        // bob overlaps alice's view in a 3x5 strip, which is already loaded
+        let change = tracker.move_viewer(bob, (2, 0));
+        assert_eq!(change.shown.len(), 25);
+        assert_eq!(change.loaded.len(), 10);
+        assert_eq!(tracker.references(1, 0), 2);
+
+        let change = tracker.remove_viewer(alice);
+        assert_eq!(change.center, None);
+        assert_eq!(change.hidden.len(), 25);
+        assert_eq!(change.unloaded.len(), 10);
+        assert!(change.unloaded.iter().all(|&(x, _)| x < 0));
+        assert_eq!(tracker.references(1, 0), 1);
+        assert_eq!(tracker.references(-1, 0), 0);
+    }
+
+    #[test]
+    fn moving_shows_and_hides_the_edges() {
+        let mut tracker = ChunkTracker::new(2, 2);
+        let player = Uuid::from_u128(1);
+        tracker.move_viewer(player, (0, 0));
+
+        assert_eq!(tracker.move_viewer(player, (0, 0)), ViewChange::default());
+
+        let change = tracker.move_viewer(player, (0, 1));
+        assert_eq!(change.center, Some((0, 1)));
+        assert_eq!(change.shown, vec![(2, 3), (1, 3), (0, 3), (-1, 3), (-2, 3)]);
+        assert_eq!(change.hidden.len(), 5);
+        assert!(change.hidden.iter().all(|&(_, z)| z == -2));
+        assert_eq!(change.unloaded, change.hidden);
+        assert_eq!(tracker.viewer(player), Some((0, 1)));
+    }
+
+    #[test]
+    fn simulation_distance_is_capped_by_the_view_distance() {
+        let tracker = ChunkTracker::new(4, 16);
+        assert_eq!(tracker.simulation_distance(), 4);
+
+        let mut tracker = ChunkTracker::new(8, 2);
+        tracker.move_viewer(Uuid::from_u128(1), (10, 10));
+        assert!(tracker.is_simulated(12, 8));
+        assert!(!tracker.is_simulated(13, 10));
+        assert_eq!(tracker.references(13, 10), 1);
+    }
+}
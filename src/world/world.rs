@@ -1,51 +1,616 @@
-use std::collections::HashMap;
+use anyhow::Context;
+use std::{collections::{hash_map::Entry, HashMap}, fs, io::Cursor, path::PathBuf};
+use crate::nbt::{Compound, Tag};
+use crate::protocol::server::{
+    ChunkBlockEntity, ChunkDataAndUpdateLight, LightData, ServerPlayPacket, UnloadChunk, UpdateViewPosition,
+};
+use crate::protocol::error::ProtocolError;
+use crate::protocol::structs::{BitSet, ProtocolVersion, Readable, VarInt, VarIntPrefixedVec, Writeable};
+use crate::world::anvil::Region;
 use crate::world::blocks::BlockState;
+use crate::world::generation::ChunkGenerator;
+use crate::world::palette::{Biomes, BitStorage, Blocks, PaletteKind, PalettedContainer};
+use crate::util::log::{self, ThreadName};
+use crate::world::view::{ChunkTracker, ViewChange};
+use uuid::Uuid;
 
 pub struct Dimension {
-    chunks: HashMap<(i32, i32), Chunk>, // no idea if this is okay, rip ram
-    generator: ChunkGenerator // todo, each dimension should have its own generator
+    chunks: HashMap<(i32, i32), Chunk>, // only what's loaded, the rest is paged in from region files
+    // Where the region files are, like world/region for the overworld. None keeps everything in memory
+    region_folder: Option<PathBuf>,
+    // Keyed by region coords, opened the first time one of their chunks is needed and closed once none are loaded
+    pub(super) regions: HashMap<(i32, i32), Region>,
+    // Which chunks players can see, which decides what stays loaded
+    tracker: ChunkTracker,
+    // Makes the chunks that have never been saved, off on its own threads
+    generator: ChunkGenerator,
+    // The version each viewer's client speaks, which decides how chunks are encoded for them
+    versions: HashMap<Uuid, ProtocolVersion>,
 }
 impl Dimension {
-    //
+    pub fn new(
+        region_folder: Option<PathBuf>,
+        generator: ChunkGenerator,
+        view_distance: u8,
+        simulation_distance: u8,
+    ) -> Self {
+        Dimension {
+            chunks: HashMap::new(),
+            region_folder,
+            regions: HashMap::new(),
+            tracker: ChunkTracker::new(view_distance, simulation_distance),
+            generator,
+            versions: HashMap::new(),
+        }
+    }
+
+    pub fn tracker(&self) -> &ChunkTracker {
+        &self.tracker
+    }
+
+    // Centres a player's view on the given chunk coords, loading what they can now see and unloading what nobody
+    // can see anymore. Returns the packets to send them; chunks that still need generating are sent once they're done
+    pub fn move_viewer(
+        &mut self,
+        player: Uuid,
+        version: ProtocolVersion,
+        x: i32,
+        z: i32,
+    ) -> anyhow::Result<Vec<ServerPlayPacket<'static>>> {
+        self.versions.insert(player, version);
+        let change = self.tracker.move_viewer(player, (x, z));
+        self.apply_view_change(&change);
+
+        let mut packets = Vec::new();
+        if let Some((x, z)) = change.center {
+            packets.push(UpdateViewPosition { chunk_x: VarInt(x), chunk_z: VarInt(z) }.into());
+        }
+        for &(x, z) in &change.hidden {
+            packets.push(UnloadChunk { chunk_x: x, chunk_z: z }.into());
+        }
+        for (x, z) in change.shown {
+            if let Some(chunk) = self.chunks.get(&(x, z)) {
+                packets.push(chunk.data_packet(version)?.into());
+            }
+        }
+        Ok(packets)
+    }
+
+    // Picks up the chunks that have finished generating since last time, returning the packets to send whoever can
+    // see them. Chunks nobody can see anymore are thrown away, since they'd generate the same next time
+    pub fn receive_generated(&mut self) -> anyhow::Result<Vec<(Uuid, ServerPlayPacket<'static>)>> {
+        let mut packets = Vec::new();
+        for chunk in self.generator.finished() {
+            let (x, z) = (chunk.x_pos(), chunk.z_pos());
+            if self.tracker.references(x, z) == 0 || self.chunks.contains_key(&(x, z)) {
+                continue;
+            }
+            for player in self.tracker.viewers_of(x, z) {
+                let version = self.versions.get(&player).copied().unwrap_or_default();
+                packets.push((player, chunk.data_packet(version)?.into()));
+            }
+            self.insert_chunk(chunk);
+        }
+        Ok(packets)
+    }
+
+    // Stops tracking a player, like when they leave or change dimension
+    pub fn remove_viewer(&mut self, player: Uuid) {
+        self.versions.remove(&player);
+        let change = self.tracker.remove_viewer(player);
+        self.apply_view_change(&change);
+    }
+
+    // Returns the chunk at the given chunk coords if it's loaded
+    pub fn chunk(&self, x: i32, z: i32) -> Option<&Chunk> {
+        self.chunks.get(&(x, z))
+    }
+
+    pub fn chunk_mut(&mut self, x: i32, z: i32) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&(x, z))
+    }
+
+    // Adds a chunk, replacing whatever was loaded there
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        self.chunks.insert((chunk.x_pos(), chunk.z_pos()), chunk);
+    }
+
+    // Returns the chunk at the given chunk coords, reading it from its region file if it isn't loaded.
+    // None if it's never been saved, so it still needs generating
+    pub fn load_chunk(&mut self, x: i32, z: i32) -> anyhow::Result<Option<&mut Chunk>> {
+        if !self.chunks.contains_key(&(x, z)) {
+            let nbt = match self.region(x, z, false)? {
+                Some(region) => region.read_chunk(x, z)?,
+                None => None,
+            };
+            let chunk = match nbt {
+                Some(nbt) => Chunk::from_nbt(&nbt).with_context(|| format!("unable to load chunk {}, {}", x, z))?,
+                None => None,
+            };
+            match chunk {
+                Some(mut chunk) => {
+                    // a region file copied or renamed from elsewhere holds chunks that say they're somewhere else.
+                    // Like vanilla they're moved to where they were found, rather than over another chunk
+                    if (chunk.x_pos, chunk.z_pos) != (x, z) {
+                        log::warn(
+                            ThreadName::Chunk,
+                            &format!(
+                                "Chunk {}, {} is saved as chunk {}, {}; moving it back",
+                                x, z, chunk.x_pos, chunk.z_pos
+                            ),
+                        );
+                        chunk.x_pos = x;
+                        chunk.z_pos = z;
+                    }
+                    self.insert_chunk(chunk)
+                }
+                None => {
+                    self.close_unused_region(x, z);
+                    return Ok(None);
+                }
+            }
+        }
+
+        Ok(self.chunks.get_mut(&(x, z)))
+    }
+
+    // Writes the chunk at the given chunk coords to its region file, if it's loaded
+    pub fn save_chunk(&mut self, x: i32, z: i32) -> anyhow::Result<()> {
+        let nbt = match self.chunks.get(&(x, z)) {
+            Some(chunk) => chunk.to_nbt()?,
+            None => return Ok(()),
+        };
+        if let Some(region) = self.region(x, z, true)? {
+            region.write_chunk(x, z, &nbt)?;
+        }
+        Ok(())
+    }
+
+    // Saves the chunk and drops it from memory, handing it back
+    pub fn unload_chunk(&mut self, x: i32, z: i32) -> anyhow::Result<Option<Chunk>> {
+        self.save_chunk(x, z)?;
+        let chunk = self.chunks.remove(&(x, z));
+        self.close_unused_region(x, z);
+        Ok(chunk)
+    }
+
+    pub fn save_all(&mut self) -> anyhow::Result<()> {
+        let loaded: Vec<(i32, i32)> = self.chunks.keys().copied().collect();
+        for (x, z) in loaded {
+            self.save_chunk(x, z)?;
+        }
+        Ok(())
+    }
+
+    // Loads and unloads what the tracker says to. The tracker has already moved on, so a chunk that can't be saved
+    // or read doesn't hold up the rest: an unsaved one stays loaded for `save_all` to try again, and an unreadable
+    // one is made again
+    fn apply_view_change(&mut self, change: &ViewChange) {
+        for &(x, z) in &change.unloaded {
+            self.generator.cancel(x, z);
+            // without region files the chunk is just dropped, and made again if it's needed
+            if let Err(e) = self.unload_chunk(x, z) {
+                log::warn(ThreadName::Chunk, &format!("Unable to save chunk {}, {}: {:#}", x, z, e));
+            }
+        }
+        for &(x, z) in &change.loaded {
+            let loaded = match self.load_chunk(x, z) {
+                Ok(chunk) => chunk.is_some(),
+                Err(e) => {
+                    log::warn(ThreadName::Chunk, &format!("Unable to load chunk {}, {}, making it again: {:#}", x, z, e));
+                    false
+                }
+            };
+            if !loaded {
+                let priority = self.tracker.distance(x, z).unwrap_or(u32::MAX);
+                self.generator.request(x, z, priority);
+            }
+        }
+    }
+
+    // The region file the chunk at the given chunk coords is in. Missing files are only created if `create` is set
+    fn region(&mut self, x: i32, z: i32, create: bool) -> anyhow::Result<Option<&mut Region>> {
+        let folder = match &self.region_folder {
+            Some(folder) => folder,
+            None => return Ok(None),
+        };
+
+        match self.regions.entry(region_coords(x, z)) {
+            Entry::Occupied(region) => Ok(Some(region.into_mut())),
+            Entry::Vacant(entry) => {
+                let path = folder.join(Region::file_name(x, z));
+                if !create && !path.exists() {
+                    return Ok(None);
+                }
+                fs::create_dir_all(folder).with_context(|| format!("unable to create {}", folder.display()))?;
+                Ok(Some(entry.insert(Region::open(&path)?)))
+            }
+        }
+    }
+
+    // Closes the region file the chunk at the given chunk coords is in, if none of its chunks are loaded anymore
+    fn close_unused_region(&mut self, x: i32, z: i32) {
+        let region = region_coords(x, z);
+        if !self.chunks.keys().any(|&(x, z)| region_coords(x, z) == region) {
+            self.regions.remove(&region);
+        }
+    }
 }
 
+// The region the chunk at the given chunk coords is in
+fn region_coords(x: i32, z: i32) -> (i32, i32) {
+    (x.div_euclid(32), z.div_euclid(32))
+}
+
+// The lowest section and block in the world, and how many sections there are (in 1.18.2)
+pub const MIN_SECTION: i32 = -4;
+pub const MIN_Y: i32 = MIN_SECTION * 16;
+pub const SECTION_COUNT: usize = 24;
+
+// Light covers an extra section above and below the blocks
+pub const LIGHT_SECTION_COUNT: usize = SECTION_COUNT + 2;
+
 pub struct Chunk {
-    xPos: i64,
-    zPos: i64,
-    sections: [ChunkSection; 24] // -64 to 319
+    x_pos: i32,
+    z_pos: i32,
+    pub(super) sections: [ChunkSection; SECTION_COUNT], // -64 to 319
+    // keyed by the x, y and z within the chunk
+    pub(super) block_entities: HashMap<(u8, i16, u8), BlockEntity>,
+    pub(super) sky_light: [LightSection; LIGHT_SECTION_COUNT],
+    pub(super) block_light: [LightSection; LIGHT_SECTION_COUNT],
 }
 impl Chunk {
+    // Returns an empty chunk at the given chunk coords, lit by the sky everywhere since there's no light engine yet
+    pub fn new(x_pos: i32, z_pos: i32) -> Self {
+        Chunk {
+            x_pos,
+            z_pos,
+            sections: std::array::from_fn(|_| ChunkSection::default()),
+            block_entities: HashMap::new(),
+            sky_light: std::array::from_fn(|_| LightSection::Full),
+            block_light: std::array::from_fn(|_| LightSection::Empty),
+        }
+    }
+
+    pub fn x_pos(&self) -> i32 {
+        self.x_pos
+    }
+
+    pub fn z_pos(&self) -> i32 {
+        self.z_pos
+    }
+
     // Returns mutable reference to the ChunkSection at the index. Index is from -4 to 19 (in 1.18.2)
     pub fn section(&mut self, index: i32) -> &mut ChunkSection {
-        &mut self.sections[(index + 4) as usize]
+        &mut self.sections[(index - MIN_SECTION) as usize]
+    }
+
+    // Returns the BlockState at the given coords within the chunk, with y being the actual world height
+    pub fn get_blockstate(&self, x: usize, y: i32, z: usize) -> BlockState {
+        let section = &self.sections[((y - MIN_Y) >> 4) as usize];
+        section.get_blockstate(x, (y & 15) as usize, z)
+    }
+
+    // Sets the BlockState at the given coords within the chunk, with y being the actual world height
+    pub fn set_blockstate(&mut self, new_state: BlockState, x: usize, y: i32, z: usize) {
+        let section = &mut self.sections[((y - MIN_Y) >> 4) as usize];
+        section.set_blockstate(new_state, x, (y & 15) as usize, z)
+    }
+
+    pub fn set_block_entity(&mut self, x: u8, y: i16, z: u8, block_entity: BlockEntity) {
+        self.block_entities.insert((x, y, z), block_entity);
+    }
+
+    pub fn remove_block_entity(&mut self, x: u8, y: i16, z: u8) -> Option<BlockEntity> {
+        self.block_entities.remove(&(x, y, z))
+    }
+
+    // Index is from -5 to 20, since light goes a section past the blocks at each end
+    pub fn set_sky_light(&mut self, index: i32, light: LightSection) {
+        self.sky_light[(index - MIN_SECTION + 1) as usize] = light;
+    }
+
+    pub fn set_block_light(&mut self, index: i32, light: LightSection) {
+        self.block_light[(index - MIN_SECTION + 1) as usize] = light;
     }
 
     pub fn update(&mut self) {}
+
+    // The height of each column above the bottom of the world, counting the highest block that isn't air,
+    // packed the way the MOTION_BLOCKING heightmap is
+    pub(super) fn heightmap(&self) -> Vec<u64> {
+        let mut heights = BitStorage::new(HEIGHTMAP_BITS, 16 * 16);
+
+        for z in 0..16 {
+            for x in 0..16 {
+                let top = self
+                    .sections
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .filter(|(_, section)| section.block_count() > 0)
+                    .find_map(|(index, section)| {
+                        (0..16)
+                            .rev()
+                            .find(|&y| !section.get_blockstate(x, y, z).is_air())
+                            .map(|y| index * 16 + y + 1)
+                    });
+                heights.set(z * 16 + x, top.unwrap_or(0) as u32);
+            }
+        }
+
+        heights.into_data()
+    }
+
+    // Builds the packet that sends this chunk to a client on the given version, blocks and light together
+    pub fn data_packet(&self, version: ProtocolVersion) -> anyhow::Result<ChunkDataAndUpdateLight<'static>> {
+        let heightmap = Tag::LongArray(self.heightmap().into_iter().map(|long| long as i64).collect());
+        let mut heightmaps = Compound::new();
+        heightmaps.insert("MOTION_BLOCKING".to_owned(), heightmap.clone());
+        heightmaps.insert("WORLD_SURFACE".to_owned(), heightmap);
+
+        let mut data = Vec::new();
+        for section in &self.sections {
+            section.write(&mut data, version)?;
+        }
+
+        let block_entities: Vec<ChunkBlockEntity> = self
+            .block_entities
+            .iter()
+            .map(|(&(x, y, z), block_entity)| ChunkBlockEntity {
+                packed_xz: (x & 15) << 4 | (z & 15),
+                y,
+                kind: VarInt(block_entity.kind),
+                data: block_entity.data.clone().into(),
+            })
+            .collect();
+
+        let (sky_light_mask, empty_sky_light_mask, sky_light) = light_arrays(&self.sky_light);
+        let (block_light_mask, empty_block_light_mask, block_light) = light_arrays(&self.block_light);
+
+        Ok(ChunkDataAndUpdateLight {
+            chunk_x: self.x_pos,
+            chunk_z: self.z_pos,
+            heightmaps: heightmaps.into(),
+            data: data.into(),
+            block_entities: block_entities.into(),
+            light: LightData {
+                trust_edges: true,
+                sky_light_mask,
+                block_light_mask,
+                empty_sky_light_mask,
+                empty_block_light_mask,
+                sky_light: sky_light.into(),
+                block_light: block_light.into(),
+            },
+        })
+    }
+}
+
+// Enough bits for a height from 0 to 384
+const HEIGHTMAP_BITS: u8 = 9;
+
+// Splits light sections into which ones have arrays, which ones are dark, and the arrays themselves
+fn light_arrays(sections: &[LightSection]) -> (BitSet, BitSet, Vec<VarIntPrefixedVec<'static, u8>>) {
+    let mut mask = BitSet::default();
+    let mut empty_mask = BitSet::default();
+    let mut arrays = Vec::new();
+
+    for (index, section) in sections.iter().enumerate() {
+        match section {
+            LightSection::Empty => empty_mask.set(index),
+            LightSection::Full => {
+                mask.set(index);
+                arrays.push(vec![0xFF; LIGHT_ARRAY_LEN].into());
+            }
+            LightSection::Data(data) => {
+                mask.set(index);
+                arrays.push(data.to_vec().into());
+            }
+        }
+    }
+
+    (mask, empty_mask, arrays)
+}
+
+// Half a byte of light for each block in a section
+pub const LIGHT_ARRAY_LEN: usize = 16 * 16 * 16 / 2;
+
+// The light levels of a section
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LightSection {
+    // Everything is at level 0
+    Empty,
+    // Everything is at level 15
+    Full,
+    // A nibble per block, in the same YZX order as blocks
+    Data(Box<[u8; LIGHT_ARRAY_LEN]>),
+}
+
+// A block's extra data, like a chest's items or a sign's text
+#[derive(Clone)]
+pub struct BlockEntity {
+    // The block entity type's ID in the registry
+    pub kind: i32,
+    pub data: Compound,
 }
 
 // 16x16x16 set of blocks. Chunks consist of several of these
+#[derive(Clone)]
 pub struct ChunkSection {
-    blocks: [[[BlockState; 16]; 16]; 16]
+    // how many blocks aren't air, which the client wants to know
+    block_count: u16,
+    pub(super) blocks: PalettedContainer<Blocks>,
+    pub(super) biomes: PalettedContainer<Biomes>,
+}
+impl Default for ChunkSection {
+    fn default() -> Self {
+        ChunkSection {
+            block_count: 0,
+            blocks: PalettedContainer::filled(BlockState::AIR),
+            biomes: PalettedContainer::filled(0),
+        }
+    }
 }
 impl ChunkSection {
-    // Returns a reference to the BlockState at the provided coords
-    pub fn get_blockstate(&self, x: usize, y: usize, z: usize) -> &BlockState {
-        &self.blocks[x][y][z]
+    // Puts a section together from its blocks and biomes, counting the blocks that aren't air
+    pub(super) fn new(blocks: PalettedContainer<Blocks>, biomes: PalettedContainer<Biomes>) -> Self {
+        let block_count = (0..Blocks::LEN).filter(|&index| !blocks.get(index).is_air()).count() as u16;
+        ChunkSection {
+            block_count,
+            blocks,
+            biomes,
+        }
+    }
+
+    // Index into the containers, in the YZX order the protocol uses
+    fn block_index(x: usize, y: usize, z: usize) -> usize {
+        (y << 8) | (z << 4) | x
     }
 
-    // Returns a mutable reference to the BlockState at the provided coords
-    pub fn get_blockstate_mut(&mut self, x: usize, y: usize, z: usize) -> &mut BlockState {
-        &mut self.blocks[x][y][z]
+    // Returns the BlockState at the provided coords
+    pub fn get_blockstate(&self, x: usize, y: usize, z: usize) -> BlockState {
+        self.blocks.get(Self::block_index(x, y, z))
     }
 
     // Sets the given BlockState to the given coords in the chunk section
     pub fn set_blockstate(&mut self, new_state: BlockState, x: usize, y: usize, z: usize) {
-        self.blocks[x][y][z] = new_state;
+        let index = Self::block_index(x, y, z);
+        let old_state = self.blocks.get(index);
+
+        match (old_state.is_air(), new_state.is_air()) {
+            (true, false) => self.block_count += 1,
+            (false, true) => self.block_count -= 1,
+            _ => {}
+        }
+        self.blocks.set(index, new_state);
+    }
+
+    // Returns the biome at the provided coords, which are in 4x4x4 cells rather than blocks
+    pub fn get_biome(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.biomes.get((y << 4) | (z << 2) | x)
+    }
+
+    // Sets the biome at the provided 4x4x4 cell coords
+    pub fn set_biome(&mut self, biome: u32, x: usize, y: usize, z: usize) {
+        self.biomes.set((y << 4) | (z << 2) | x, biome)
+    }
+
+    pub fn block_count(&self) -> u16 {
+        self.block_count
+    }
+}
+
+impl<'de> Readable<'de> for ChunkSection {
+    fn read(buffer: &mut Cursor<&'de [u8]>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        // recounted rather than trusted, so it can't disagree with the blocks
+        i16::read(buffer, version)?;
+        let blocks = PalettedContainer::read(buffer, version)?;
+        Ok(ChunkSection::new(blocks, PalettedContainer::read(buffer, version)?))
+    }
+}
+
+impl Writeable for ChunkSection {
+    fn write(&self, buffer: &mut Vec<u8>, version: ProtocolVersion) -> anyhow::Result<()> {
+        (self.block_count as i16).write(buffer, version)?;
+        self.blocks.write(buffer, version)?;
+        self.biomes.write(buffer, version)
     }
 }
 
-pub struct ChunkGenerator {
-    // options for generation
-    // ...
-    gen_queue: Vec<(i32, i32)>
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::packets::Packet;
+    use std::{thread, time::{Duration, Instant}};
+
+    const VERSION: ProtocolVersion = ProtocolVersion::V1_18_2;
+
+    #[test]
+    fn chunk_data_packet_reads_back() {
+        let mut chunk = Chunk::new(3, -7);
+        chunk.set_blockstate(BlockState::BEDROCK, 0, MIN_Y, 0);
+        chunk.set_blockstate(BlockState::STONE, 5, 70, 9);
+        chunk.set_block_entity(5, 71, 9, BlockEntity { kind: 1, data: Compound::new() });
+        chunk.set_block_light(0, LightSection::Full);
+
+        let mut buffer = Vec::new();
+        chunk.data_packet(VERSION).unwrap().write(&mut buffer, VERSION).unwrap();
+        assert_eq!(ChunkDataAndUpdateLight::id(ProtocolVersion::V1_18_2), 0x22);
+
+        let packet = ChunkDataAndUpdateLight::read(&mut Cursor::new(&buffer[..]), VERSION).unwrap();
+        assert_eq!((packet.chunk_x, packet.chunk_z), (3, -7));
+
+        // heights are 9 bits each, 7 to a long, counted up from the bottom of the world
+        let heights = match &packet.heightmaps.root["MOTION_BLOCKING"] {
+            Tag::LongArray(heights) => heights.clone(),
+            _ => panic!("heightmap should be a long array"),
+        };
+        assert_eq!(heights.len(), 37);
+        assert_eq!(heights[0] & 0x1FF, 1);
+        let index = 9 * 16 + 5;
+        assert_eq!(heights[index / 7] >> (index % 7 * 9) & 0x1FF, (70 - MIN_Y + 1) as i64);
+
+        let mut data = Cursor::new(&packet.data.0[..]);
+        let sections: Vec<ChunkSection> = (0..SECTION_COUNT)
+            .map(|_| ChunkSection::read(&mut data, VERSION).unwrap())
+            .collect();
+        assert_eq!(data.position() as usize, packet.data.0.len());
+        assert_eq!(sections[0].get_blockstate(0, 0, 0), BlockState::BEDROCK);
+        assert_eq!(sections[8].get_blockstate(5, 70 & 15, 9), BlockState::STONE);
+        assert_eq!(sections[8].block_count(), 1);
+
+        assert_eq!(packet.block_entities.0[0].packed_xz, 5 << 4 | 9);
+
+        let light = &packet.light;
+        assert_eq!(light.sky_light.0.len(), LIGHT_SECTION_COUNT);
+        assert!(light.block_light_mask.get(5) && !light.empty_block_light_mask.get(5));
+        assert!(light.empty_block_light_mask.get(4));
+        assert_eq!(light.block_light.0[0].0.len(), LIGHT_ARRAY_LEN);
+    }
+
+    #[test]
+    fn chunks_are_only_sent_to_versions_with_a_registry() {
+        let chunk = Chunk::new(0, 0);
+        assert!(chunk.data_packet(ProtocolVersion::V1_18_2).is_ok());
+        assert!(chunk.data_packet(ProtocolVersion::V1_19_2).is_ok());
+        assert!(chunk.data_packet(ProtocolVersion::V1_20_1).is_err());
+    }
+
+    #[test]
+    fn viewers_are_sent_what_they_can_see() {
+        let mut dimension = Dimension::new(None, ChunkGenerator::new(Chunk::new, 2), 2, 2);
+        dimension.insert_chunk(Chunk::new(0, 0));
+        let player = Uuid::from_u128(1);
+
+        let packets = dimension.move_viewer(player, VERSION, 0, 0).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert!(matches!(&packets[0], ServerPlayPacket::UpdateViewPosition(p) if p.chunk_x.0 == 0 && p.chunk_z.0 == 0));
+        assert!(matches!(&packets[1], ServerPlayPacket::ChunkDataAndUpdateLight(p) if p.chunk_x == 0));
+
+        // everything else is sent as it's generated
+        let start = Instant::now();
+        let mut generated = Vec::new();
+        while generated.len() < 24 {
+            assert!(start.elapsed() < Duration::from_secs(10), "chunks took too long to generate");
+            generated.extend(dimension.receive_generated().unwrap());
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(generated
+            .iter()
+            .all(|(to, packet)| *to == player && matches!(packet, ServerPlayPacket::ChunkDataAndUpdateLight(_))));
+        assert!(dimension.chunk(2, -2).is_some());
+
+        let packets = dimension.move_viewer(player, VERSION, 10, 0).unwrap();
+        let unloads = packets
+            .iter()
+            .filter(|packet| matches!(packet, ServerPlayPacket::UnloadChunk(_)))
+            .count();
+        assert_eq!(unloads, 25);
+        // with no region files there's nowhere to save them, but they still don't stay in memory
+        assert!(dimension.chunk(0, 0).is_none());
+
+        dimension.remove_viewer(player);
+        assert_eq!(dimension.tracker().references(10, 0), 0);
+    }
+}